@@ -0,0 +1,419 @@
+//! `tx_broadcaster batch`: broadcast a directory of signed transactions.
+//!
+//! Transactions are grouped by recovered sender and sent in nonce order. The first round goes
+//! out as one JSON-RPC batch request where the endpoint supports it, otherwise one request per
+//! transaction. A transaction the node rejects as a future nonce is resent once its predecessor
+//! has been mined; if the predecessor was not broadcast it is reported as blocked instead.
+//! A results table lists every file at the end.
+
+use crate::journal::{Journal, JournalArgs};
+use crate::node_error::{BroadcastError, ErrorKind};
+use crate::preflight::{self, SignedTx};
+use crate::rpc::{quantity, Rpc, RpcArgs, Strategy};
+use crate::submit::{self, Submission};
+use crate::transport::redact;
+use ethers::types::{Address, H256, U256};
+use eyre::{eyre, Result};
+use serde_json::{json, Value};
+use std::process::ExitCode;
+use std::time::{Duration, Instant};
+use std::{fs, path::PathBuf};
+
+/// Written by `tx_signer batch` next to the signed files; not a transaction.
+const SIGNER_MANIFEST: &str = "manifest.json";
+
+#[derive(clap::Args, Debug)]
+pub struct BatchArgs {
+    /// Directory of signed transactions (hex or envelopes), e.g. the output of `tx_signer batch`
+    #[arg(long)]
+    input_dir: PathBuf,
+
+    #[command(flatten)]
+    rpc: RpcArgs,
+
+    #[command(flatten)]
+    journal: JournalArgs,
+
+    /// Seconds to wait for a predecessor to be mined when a node rejects a future nonce
+    #[arg(long, default_value_t = 300)]
+    gap_timeout: u64,
+
+    /// Seconds between nonce polls while waiting for a predecessor
+    #[arg(long, default_value_t = 4)]
+    poll_interval: u64,
+}
+
+#[derive(Clone)]
+struct Item {
+    file: String,
+    tx: SignedTx,
+    raw: Vec<u8>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Status {
+    Sent(String),
+    /// Rejected as a future nonce; resent once the predecessor lands.
+    Waiting(BroadcastError),
+    Failed(String),
+    Blocked(String),
+    Skipped(String),
+    /// Same tx as the named file, which is sent instead.
+    Duplicate(String),
+}
+
+struct Row {
+    file: String,
+    sender: Option<Address>,
+    nonce: Option<U256>,
+    hash: Option<H256>,
+    status: Status,
+}
+
+impl Row {
+    fn new(item: &Item, status: Status) -> Self {
+        Row { file: item.file.clone(), sender: Some(item.tx.sender), nonce: Some(item.tx.nonce), hash: Some(item.tx.hash), status }
+    }
+}
+
+/// Decode every file in `dir`; unreadable files become failed rows.
+fn load(dir: &PathBuf) -> Result<(Vec<Item>, Vec<Row>)> {
+    let mut paths: Vec<PathBuf> = fs::read_dir(dir)
+        .map_err(|e| eyre!("Cannot read {}: {}", dir.display(), e))?
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|path| path.is_file() && path.file_name().is_some_and(|n| n != SIGNER_MANIFEST))
+        .collect();
+    paths.sort();
+
+    let (mut items, mut rows) = (Vec::new(), Vec::new());
+    for path in paths {
+        let file = path.file_name().unwrap_or_default().to_string_lossy().into_owned();
+        let decoded = tx_envelope::read_signed(&path).and_then(|input| {
+            let raw = input.raw_tx().to_vec();
+            Ok((preflight::decode(&raw)?, raw))
+        });
+        match decoded {
+            Ok((tx, raw)) => items.push(Item { file, tx, raw }),
+            Err(e) => rows.push(Row { file, sender: None, nonce: None, hash: None, status: Status::Failed(e.to_string()) }),
+        }
+    }
+    Ok((items, rows))
+}
+
+/// Sort by sender then nonce. The same tx found twice (e.g. hex and envelope) is sent once;
+/// different txs for one nonce are all skipped, since either could be the intended one.
+/// Returns the kept items, rows for the skipped files and the conflicting (sender, nonce) pairs.
+fn order(mut items: Vec<Item>) -> (Vec<Item>, Vec<Row>, Vec<(Address, U256)>) {
+    items.sort_by(|a, b| (a.tx.sender, a.tx.nonce, &a.file).cmp(&(b.tx.sender, b.tx.nonce, &b.file)));
+    let (mut kept, mut skipped, mut conflicts) = (Vec::new(), Vec::new(), Vec::new());
+    for group in items.chunk_by(|a, b| (a.tx.sender, a.tx.nonce) == (b.tx.sender, b.tx.nonce)) {
+        let first = &group[0];
+        if group.iter().any(|item| item.tx.hash != first.tx.hash) {
+            let files: Vec<&str> = group.iter().map(|item| item.file.as_str()).collect();
+            let reason = format!("conflicting txs for nonce {}: {}", first.tx.nonce, files.join(", "));
+            skipped.extend(group.iter().map(|item| Row::new(item, Status::Skipped(reason.clone()))));
+            conflicts.push((first.tx.sender, first.tx.nonce));
+            continue;
+        }
+        skipped.extend(group[1..].iter().map(|item| Row::new(item, Status::Duplicate(first.file.clone()))));
+        kept.push(first.clone());
+    }
+    (kept, skipped, conflicts)
+}
+
+fn status_of(submission: Submission) -> Status {
+    match submission {
+        Submission::Accepted(note) => Status::Sent(note),
+        Submission::Rejected(e) if e.kind == ErrorKind::NonceTooHigh => Status::Waiting(e),
+        Submission::Rejected(e) => Status::Failed(format!("[{}] {}", e.kind.label(), e.message)),
+        Submission::Unreachable(e) => Status::Failed(e),
+    }
+}
+
+/// First round: one JSON-RPC batch where supported, else one request per tx in order.
+async fn submit_all(rpc: &Rpc, journal: &Journal, strategy: Strategy, items: &[Item]) -> Vec<Status> {
+    if strategy == Strategy::Failover {
+        let calls: Vec<(&str, Value)> = items
+            .iter()
+            .map(|item| ("eth_sendRawTransaction", json!([format!("0x{}", hex::encode(&item.raw))])))
+            .collect();
+        for url in rpc.urls() {
+            match rpc.batch_at(url, &calls).await {
+                Ok(Some(responses)) => {
+                    let mut statuses = Vec::new();
+                    for (item, response) in items.iter().zip(responses) {
+                        let response = match response {
+                            Ok(hash) => Ok(hash.as_str().unwrap_or_default().to_string()),
+                            Err(error) => Err(BroadcastError::from_rpc(&error)),
+                        };
+                        statuses.push(status_of(submit::settle(rpc, journal, url, &item.tx, response).await));
+                    }
+                    return statuses;
+                }
+                Ok(None) => {
+                    println!("{} does not answer JSON-RPC batches; sending one request per transaction", redact(url));
+                    break;
+                }
+                Err(e) => {
+                    println!("{}", redact(&format!("RPC {} unreachable for the batch request: {}", url, e)));
+                    for item in items {
+                        journal.record(url, &item.tx, Err(format!("unreachable: {}", e)));
+                    }
+                }
+            }
+        }
+    }
+    let mut statuses = Vec::new();
+    for item in items {
+        statuses.push(match submit::broadcast(rpc, journal, &item.tx, &item.raw, strategy).await {
+            Ok(_) => Status::Sent("accepted".to_string()),
+            Err(e) => match e.downcast::<BroadcastError>() {
+                Ok(error) => status_of(Submission::Rejected(error)),
+                Err(e) => Status::Failed(e.to_string()),
+            },
+        });
+    }
+    statuses
+}
+
+/// Resend `item` once the sender's mined nonce reaches it, until `gap_timeout` passes.
+async fn wait_for_predecessor(rpc: &Rpc, journal: &Journal, strategy: Strategy, item: &Item, poll: Duration, gap_timeout: Duration) -> Result<Status> {
+    let Some(predecessor) = item.tx.nonce.checked_sub(U256::one()) else {
+        return Ok(Status::Failed("rejected as a future nonce, but nonce 0 has no predecessor".to_string()));
+    };
+    println!("{}: nonce {} is ahead of the node; waiting for nonce {} to be mined", item.file, item.tx.nonce, predecessor);
+    let started = Instant::now();
+    loop {
+        let mined = quantity("eth_getTransactionCount", &rpc.call("eth_getTransactionCount", json!([item.tx.sender, "latest"])).await?)?;
+        if mined >= item.tx.nonce {
+            match submit::broadcast(rpc, journal, &item.tx, &item.raw, strategy).await {
+                Ok(_) => return Ok(Status::Sent("accepted after predecessor".to_string())),
+                Err(e) => match e.downcast::<BroadcastError>() {
+                    Ok(error) if error.kind == ErrorKind::NonceTooHigh => {}
+                    Ok(error) => return Ok(status_of(Submission::Rejected(error))),
+                    Err(e) => return Ok(Status::Failed(e.to_string())),
+                },
+            }
+        }
+        if started.elapsed() >= gap_timeout {
+            return Ok(Status::Failed(format!("nonce {} was not mined within {}s", predecessor, gap_timeout.as_secs())));
+        }
+        tokio::time::sleep(poll).await;
+    }
+}
+
+/// Submit `items`, then resend those rejected as future nonces once their predecessor is mined.
+/// A tx whose predecessor was not broadcast, or has conflicting txs, is blocked instead.
+async fn send_all(
+    rpc: &Rpc,
+    journal: &Journal,
+    strategy: Strategy,
+    items: &[Item],
+    conflicts: &[(Address, U256)],
+    poll: Duration,
+    gap_timeout: Duration,
+) -> Result<Vec<Status>> {
+    let mut statuses = submit_all(rpc, journal, strategy, items).await;
+    for i in 0..items.len() {
+        if !matches!(statuses[i], Status::Waiting(_)) {
+            continue;
+        }
+        let item = &items[i];
+        let predecessor_failed = (0..i).find(|&j| items[j].tx.sender == item.tx.sender && !matches!(statuses[j], Status::Sent(_)));
+        let conflict = conflicts.iter().find(|(sender, nonce)| *sender == item.tx.sender && *nonce < item.tx.nonce);
+        statuses[i] = match (predecessor_failed, conflict) {
+            (Some(j), _) => Status::Blocked(format!("predecessor {} (nonce {}) was not broadcast", items[j].file, items[j].tx.nonce)),
+            (None, Some((_, nonce))) => Status::Blocked(format!("nonce {} has conflicting txs", nonce)),
+            (None, None) => wait_for_predecessor(rpc, journal, strategy, item, poll, gap_timeout).await?,
+        };
+    }
+    Ok(statuses)
+}
+
+fn print_table(rows: &[Row]) {
+    let short = |a: &Address| {
+        let full = format!("{:?}", a);
+        format!("{}…{}", &full[..6], &full[38..])
+    };
+    println!("{:<24} {:<13} {:>6}  {:<9} DETAIL", "FILE", "SENDER", "NONCE", "STATUS");
+    for row in rows {
+        let (status, detail) = match &row.status {
+            Status::Sent(note) => ("SENT", format!("{:?} ({})", row.hash.unwrap_or_default(), note)),
+            Status::Waiting(e) => ("FAILED", e.to_string()),
+            Status::Failed(e) => ("FAILED", e.clone()),
+            Status::Blocked(e) => ("BLOCKED", e.clone()),
+            Status::Skipped(e) => ("SKIPPED", e.clone()),
+            Status::Duplicate(file) => ("DUPLICATE", format!("same tx as {}", file)),
+        };
+        let sender = row.sender.as_ref().map(short).unwrap_or_else(|| "-".to_string());
+        let nonce = row.nonce.map(|n| n.to_string()).unwrap_or_else(|| "-".to_string());
+        println!("{:<24} {:<13} {:>6}  {:<9} {}", row.file, sender, nonce, status, redact(detail.lines().next().unwrap_or_default()));
+    }
+}
+
+pub async fn run(args: BatchArgs) -> Result<ExitCode> {
+    let (rpc, strategy) = args.rpc.connect()?;
+    let journal = args.journal.open();
+    let (items, mut rows) = load(&args.input_dir)?;
+    let (items, skipped, conflicts) = order(items);
+    rows.extend(skipped);
+    if items.is_empty() {
+        print_table(&rows);
+        return Err(eyre!("No signed transactions found in {}", args.input_dir.display()));
+    }
+
+    let node_chain = preflight::chain_id(&rpc).await?;
+    let (items, wrong_chain): (Vec<Item>, Vec<Item>) = items.into_iter().partition(|item| item.tx.chain_id == Some(node_chain));
+    for item in &wrong_chain {
+        let detail = match item.tx.chain_id {
+            Some(chain_id) => format!("tx is for chain {}, node is chain {}", chain_id, node_chain),
+            None => "tx has no chain ID".to_string(),
+        };
+        rows.push(Row::new(item, Status::Failed(detail)));
+    }
+
+    let senders = items.iter().map(|i| i.tx.sender).collect::<std::collections::BTreeSet<_>>().len();
+    println!("Broadcasting {} transaction(s) from {} sender(s) to chain {}", items.len(), senders, node_chain);
+    let (poll, gap_timeout) = (Duration::from_secs(args.poll_interval), Duration::from_secs(args.gap_timeout));
+    let statuses = send_all(&rpc, &journal, strategy, &items, &conflicts, poll, gap_timeout).await?;
+
+    rows.extend(items.iter().zip(statuses).map(|(item, status)| Row::new(item, status)));
+    rows.sort_by(|a, b| a.file.cmp(&b.file));
+    print_table(&rows);
+
+    let not_sent = rows.iter().filter(|r| !matches!(r.status, Status::Sent(_) | Status::Duplicate(_))).count();
+    if not_sent > 0 {
+        return Err(eyre!("{} of {} file(s) were not broadcast", not_sent, rows.len()));
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{node_error, signed_tx, spawn_mock_node, test_journal};
+    use ethers::signers::{LocalWallet, Signer};
+    use std::collections::HashMap;
+    use std::sync::{Arc, Mutex};
+
+    fn item(file: &str, wallet: &LocalWallet, nonce: u64, value: u64) -> Item {
+        let (tx, raw) = signed_tx(wallet, 137, nonce, value);
+        Item { file: file.to_string(), tx, raw }
+    }
+
+    #[test]
+    fn orders_by_sender_and_nonce_and_drops_duplicates() {
+        let a = LocalWallet::new(&mut ethers::core::rand::thread_rng());
+        let b = LocalWallet::new(&mut ethers::core::rand::thread_rng());
+        let items = vec![
+            item("a3.txt", &a, 3, 1),
+            item("b1.txt", &b, 1, 1),
+            item("a1.txt", &a, 1, 1),
+            item("a1.envelope.json", &a, 1, 1),
+            item("b2.txt", &b, 2, 1),
+            item("b2-other.txt", &b, 2, 99),
+        ];
+        let (kept, skipped, conflicts) = order(items);
+
+        let sequence: Vec<(Address, U256)> = kept.iter().map(|i| (i.tx.sender, i.tx.nonce)).collect();
+        let mut sorted = sequence.clone();
+        sorted.sort();
+        assert_eq!(sequence, sorted);
+        let mut files: Vec<&str> = kept.iter().map(|i| i.file.as_str()).collect();
+        files.sort();
+        assert_eq!(files, ["a1.envelope.json", "a3.txt", "b1.txt"]);
+
+        let mut skipped: Vec<(&str, &Status)> = skipped.iter().map(|r| (r.file.as_str(), &r.status)).collect();
+        skipped.sort_by_key(|(file, _)| *file);
+        assert_eq!(skipped[0], ("a1.txt", &Status::Duplicate("a1.envelope.json".into())));
+        let conflict = Status::Skipped("conflicting txs for nonce 2: b2-other.txt, b2.txt".into());
+        assert_eq!(skipped[1..], [("b2-other.txt", &conflict), ("b2.txt", &conflict)]);
+        assert_eq!(conflicts, [(b.address(), U256::from(2))]);
+    }
+
+    /// The signed tx in `eth_sendRawTransaction` params.
+    fn sent_tx(params: &Value) -> SignedTx {
+        preflight::decode(&hex::decode(params[0].as_str().unwrap().trim_start_matches("0x")).unwrap()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn resends_once_the_predecessor_is_mined() {
+        let sender = LocalWallet::new(&mut ethers::core::rand::thread_rng());
+        let (items, _, conflicts) = order(vec![item("0.txt", &sender, 0, 1), item("1.txt", &sender, 1, 1)]);
+        // Nonce 1 is "too high" until the first nonce poll reports nonce 0 as mined.
+        let mined = Arc::new(Mutex::new(0u64));
+        let (url, methods) = spawn_mock_node(false, move |method, params| {
+            let mut mined = mined.lock().unwrap();
+            match method {
+                "eth_sendRawTransaction" => {
+                    let tx = sent_tx(params);
+                    if tx.nonce > U256::from(*mined) { node_error("nonce too high") } else { Ok(json!(format!("{:?}", tx.hash))) }
+                }
+                "eth_getTransactionCount" => {
+                    let count = *mined;
+                    *mined = 1;
+                    Ok(json!(format!("0x{:x}", count)))
+                }
+                _ => node_error("unexpected method"),
+            }
+        })
+        .await;
+        let rpc = Rpc::new(reqwest::Client::new(), reqwest::header::HeaderMap::new(), vec![url], 5).unwrap();
+        let (journal, path) = test_journal("batch_resend");
+
+        let statuses = send_all(&rpc, &journal, Strategy::Failover, &items, &conflicts, Duration::ZERO, Duration::from_secs(5)).await.unwrap();
+        assert_eq!(statuses, [Status::Sent("accepted".into()), Status::Sent("accepted after predecessor".into())]);
+        // The refused batch falls back to one request per tx, then the gap is polled twice.
+        let methods = methods.lock().unwrap().clone();
+        assert_eq!(
+            methods,
+            ["batch", "eth_sendRawTransaction", "eth_sendRawTransaction", "eth_getTransactionCount", "eth_getTransactionCount", "eth_sendRawTransaction"]
+        );
+        let _ = fs::remove_file(path);
+    }
+
+    #[tokio::test]
+    async fn blocks_successors_of_failed_txs_and_times_out_gaps() {
+        let (a, b, c) = (
+            LocalWallet::new(&mut ethers::core::rand::thread_rng()),
+            LocalWallet::new(&mut ethers::core::rand::thread_rng()),
+            LocalWallet::new(&mut ethers::core::rand::thread_rng()),
+        );
+        let (items, _, conflicts) = order(vec![
+            item("a0.txt", &a, 0, 1),
+            item("a1.txt", &a, 1, 1),
+            item("b3.txt", &b, 3, 1),
+            item("c0.txt", &c, 0, 1),
+        ]);
+        let (a_addr, c_addr) = (a.address(), c.address());
+        let (url, methods) = spawn_mock_node(true, move |method, params| match method {
+            "eth_sendRawTransaction" => {
+                let tx = sent_tx(params);
+                if tx.sender == a_addr && tx.nonce.is_zero() {
+                    node_error("insufficient funds for gas * price + value")
+                } else if tx.sender == c_addr {
+                    // Classified as a future nonce although nonce 0 has no predecessor.
+                    node_error("nonce gap")
+                } else {
+                    node_error("nonce too high")
+                }
+            }
+            "eth_getTransactionCount" => Ok(json!("0x0")),
+            _ => node_error("unexpected method"),
+        })
+        .await;
+        let rpc = Rpc::new(reqwest::Client::new(), reqwest::header::HeaderMap::new(), vec![url], 5).unwrap();
+        let (journal, path) = test_journal("batch_blocked");
+
+        let statuses = send_all(&rpc, &journal, Strategy::Failover, &items, &conflicts, Duration::ZERO, Duration::ZERO).await.unwrap();
+        let by_file: HashMap<&str, &Status> = items.iter().map(|i| i.file.as_str()).zip(&statuses).collect();
+        assert!(matches!(by_file["a0.txt"], Status::Failed(e) if e.contains("insufficient funds")), "{by_file:?}");
+        assert_eq!(by_file["a1.txt"], &Status::Blocked("predecessor a0.txt (nonce 0) was not broadcast".into()));
+        assert_eq!(by_file["b3.txt"], &Status::Failed("nonce 2 was not mined within 0s".into()));
+        assert_eq!(by_file["c0.txt"], &Status::Failed("rejected as a future nonce, but nonce 0 has no predecessor".into()));
+        // All four went out in one batch request.
+        assert_eq!(methods.lock().unwrap()[0], "batch");
+        assert_eq!(methods.lock().unwrap().iter().filter(|m| *m == "eth_sendRawTransaction").count(), 0);
+        let _ = fs::remove_file(path);
+    }
+}
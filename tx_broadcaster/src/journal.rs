@@ -0,0 +1,234 @@
+//! Broadcast journal and `tx_broadcaster status`.
+//!
+//! Every submission attempt is appended to a local JSON-lines journal: timestamp, endpoint,
+//! the locally computed tx hash, sender, nonce, and the node's answer or the error. `status`
+//! re-queries the journaled hashes later, after the terminal that sent them is gone.
+
+use crate::preflight::{self, SignedTx};
+use crate::rpc::{quantity, RpcArgs};
+use crate::transport::redact;
+use ethers::types::{Address, H256, U256, U64};
+use eyre::{eyre, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::{
+    fs::{self, OpenOptions},
+    io::Write,
+    path::{Path, PathBuf},
+    process::ExitCode,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+#[derive(clap::Args, Debug)]
+pub struct JournalArgs {
+    /// Journal of broadcast attempts [default: ~/.tx_broadcaster/journal.jsonl]
+    #[arg(long)]
+    journal: Option<PathBuf>,
+}
+
+impl JournalArgs {
+    pub fn open(&self) -> Journal {
+        let path = self.journal.clone().or_else(default_journal_path);
+        if path.is_none() {
+            eprintln!("WARNING: HOME is not set and no --journal given; broadcast attempts are not journaled");
+        }
+        Journal { path }
+    }
+}
+
+/// `$HOME/.tx_broadcaster/journal.jsonl`
+pub fn default_journal_path() -> Option<PathBuf> {
+    std::env::var_os("HOME").map(|home| Path::new(&home).join(".tx_broadcaster").join("journal.jsonl"))
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct JournalEntry {
+    pub timestamp: u64,
+    pub endpoint: String,
+    /// keccak256 of the raw transaction, computed before sending.
+    pub tx_hash: H256,
+    pub from: Address,
+    pub nonce: U256,
+    pub chain_id: Option<u64>,
+    /// What the node returned (its tx hash) when it accepted the request.
+    pub response: Option<String>,
+    pub error: Option<String>,
+}
+
+fn now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0)
+}
+
+pub struct Journal {
+    path: Option<PathBuf>,
+}
+
+impl Journal {
+    /// Append one attempt. A journal that cannot be written only warns: the tx may already be out.
+    pub fn record(&self, endpoint: &str, tx: &SignedTx, response: std::result::Result<&str, String>) {
+        let Some(path) = &self.path else {
+            return;
+        };
+        let (response, error) = match response {
+            Ok(node_hash) => (Some(node_hash.to_string()), None),
+            Err(error) => (None, Some(redact(&error))),
+        };
+        let entry = JournalEntry {
+            timestamp: now(),
+            endpoint: redact(endpoint),
+            tx_hash: tx.hash,
+            from: tx.sender,
+            nonce: tx.nonce,
+            chain_id: tx.chain_id,
+            response,
+            error,
+        };
+        if let Err(e) = append(path, &entry) {
+            eprintln!("WARNING: cannot write journal {}: {}", path.display(), e);
+        }
+    }
+}
+
+fn append(path: &Path, entry: &JournalEntry) -> Result<()> {
+    if let Some(dir) = path.parent()
+        && !dir.as_os_str().is_empty()
+    {
+        fs::create_dir_all(dir)?;
+    }
+    let mut f = OpenOptions::new().create(true).append(true).open(path)?;
+    writeln!(f, "{}", serde_json::to_string(entry)?)?;
+    f.sync_all()?;
+    Ok(())
+}
+
+pub fn read(path: &Path) -> Result<Vec<JournalEntry>> {
+    fs::read_to_string(path)
+        .map_err(|e| eyre!("Cannot read journal {}: {}", path.display(), e))?
+        .lines()
+        .enumerate()
+        .filter(|(_, l)| !l.trim().is_empty())
+        .map(|(i, l)| serde_json::from_str(l).map_err(|e| eyre!("Journal {} line {}: {}", path.display(), i + 1, e)))
+        .collect()
+}
+
+/// One line per tx hash: the first entry for it, plus the number of attempts and whether any was accepted.
+fn summarize(entries: &[JournalEntry]) -> Vec<(&JournalEntry, usize, bool)> {
+    let mut summary: Vec<(&JournalEntry, usize, bool)> = Vec::new();
+    for entry in entries {
+        match summary.iter_mut().find(|(first, _, _)| first.tx_hash == entry.tx_hash) {
+            Some((_, attempts, accepted)) => {
+                *attempts += 1;
+                *accepted |= entry.response.is_some();
+            }
+            None => summary.push((entry, 1, entry.response.is_some())),
+        }
+    }
+    summary
+}
+
+#[derive(clap::Args, Debug)]
+pub struct StatusArgs {
+    #[command(flatten)]
+    journal: JournalArgs,
+
+    #[command(flatten)]
+    rpc: RpcArgs,
+
+    /// Only query these tx hashes (repeatable) [default: every journaled hash for the node's chain]
+    #[arg(long)]
+    hash: Vec<H256>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Receipt {
+    block_number: U64,
+    status: Option<U64>,
+}
+
+pub async fn status(args: StatusArgs) -> Result<ExitCode> {
+    let path = args
+        .journal
+        .journal
+        .clone()
+        .or_else(default_journal_path)
+        .ok_or_else(|| eyre!("Cannot locate the journal: set HOME or pass --journal"))?;
+    let entries = read(&path)?;
+    let (rpc, _) = args.rpc.connect()?;
+    let chain_id = preflight::chain_id(&rpc).await?;
+    let head = quantity("eth_blockNumber", &rpc.call("eth_blockNumber", json!([])).await?)?.low_u64();
+
+    let summary = summarize(&entries);
+    let (selected, other_chains): (Vec<_>, Vec<_>) = summary
+        .into_iter()
+        .filter(|(entry, _, _)| args.hash.is_empty() || args.hash.contains(&entry.tx_hash))
+        .partition(|(entry, _, _)| entry.chain_id == Some(chain_id));
+
+    println!("{:<66} {:>6} {:>8} {:>9}  STATE", "TX HASH", "NONCE", "ATTEMPTS", "AGE");
+    for (entry, attempts, accepted) in &selected {
+        let receipt = rpc.call("eth_getTransactionReceipt", json!([entry.tx_hash])).await?;
+        let state = if receipt.is_null() {
+            match rpc.call("eth_getTransactionByHash", json!([entry.tx_hash])).await? {
+                Value::Null if *accepted => "not found (dropped, or not yet propagated)".to_string(),
+                Value::Null => "not found (never accepted by a node)".to_string(),
+                _ => "pending".to_string(),
+            }
+        } else {
+            let receipt: Receipt = serde_json::from_value(receipt)?;
+            let block = receipt.block_number.as_u64();
+            let outcome = match receipt.status.map(|s| s.as_u64()) {
+                Some(0) => "reverted",
+                _ => "mined",
+            };
+            format!("{} in block {} ({} confirmations)", outcome, block, (head + 1).saturating_sub(block))
+        };
+        let age = now().saturating_sub(entry.timestamp);
+        println!("{:<66} {:>6} {:>8} {:>8}m  {}", format!("{:?}", entry.tx_hash), entry.nonce, attempts, age / 60, state);
+    }
+    if !other_chains.is_empty() {
+        println!("({} journaled tx(s) for other chains not queried; point --rpc-url at their chain)", other_chains.len());
+    }
+    if selected.is_empty() {
+        println!("No journaled transactions for chain {} in {}", chain_id, path.display());
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ethers::types::transaction::eip2718::TypedTransaction;
+    use ethers::types::TransactionRequest;
+
+    fn tx(hash: u8) -> SignedTx {
+        SignedTx {
+            sender: Address::repeat_byte(0xaa),
+            hash: H256::repeat_byte(hash),
+            chain_id: Some(137),
+            nonce: 5u64.into(),
+            gas_limit: 21_000u64.into(),
+            max_fee_per_gas: 1u64.into(),
+            value: 0u64.into(),
+            request: TypedTransaction::Legacy(TransactionRequest::new()),
+        }
+    }
+
+    #[test]
+    fn journals_attempts_and_summarizes_by_hash() {
+        let path = std::env::temp_dir().join(format!("tx_broadcaster_journal_{}.jsonl", std::process::id()));
+        let _ = fs::remove_file(&path);
+        let journal = Journal { path: Some(path.clone()) };
+        journal.record("https://a", &tx(1), Err("unreachable: timeout".to_string()));
+        journal.record("https://b", &tx(1), Ok("0x0101"));
+        journal.record("https://a", &tx(2), Err("[insufficient funds] insufficient funds".to_string()));
+
+        let entries = read(&path).unwrap();
+        assert_eq!(entries.len(), 3);
+        assert_eq!((entries[1].endpoint.as_str(), entries[1].response.as_deref()), ("https://b", Some("0x0101")));
+
+        let summary = summarize(&entries);
+        let counts: Vec<(H256, usize, bool)> = summary.iter().map(|(e, n, ok)| (e.tx_hash, *n, *ok)).collect();
+        assert_eq!(counts, [(H256::repeat_byte(1), 2, true), (H256::repeat_byte(2), 1, false)]);
+        let _ = fs::remove_file(path);
+    }
+}
@@ -1,80 +1,155 @@
-use clap::Parser;
-use reqwest::Client;
+mod batch;
+mod journal;
+mod node_error;
+mod preflight;
+mod relay;
+mod rpc;
+mod simulate;
+mod socket;
+mod submit;
+mod transport;
+mod wait;
+
+use clap::{Parser, Subcommand};
 use serde_json::json;
-use std::{fs, time::Duration};
-use hex;
+use std::{
+    path::{Path, PathBuf},
+    process::ExitCode,
+};
+use tx_envelope::SignedInput;
 
 /// CLI arguments for the transaction broadcaster
 #[derive(Parser, Debug)]
 #[command(name = "tx_broadcaster")]
 #[command(about = "Broadcasts a signed Ethereum/Polygon transaction", arg_required_else_help = true)]
+#[command(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
 struct Args {
-    /// Signed transaction file input (hex)
-    #[arg(long)]
-    input: String,
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    /// Signed transaction file input (hex, or a JSON/CBOR envelope from `tx_signer --envelope`)
+    #[arg(long, required = true)]
+    input: Option<String>,
+
+    #[command(flatten)]
+    rpc: rpc::RpcArgs,
+
+    #[command(flatten)]
+    journal: journal::JournalArgs,
 
-    /// RPC URL to broadcast to (e.g., Infura, Alchemy)
+    /// Replay the transaction with eth_call and eth_estimateGas first; refuse to send if it reverts
     #[arg(long)]
-    rpc_url: String,
+    simulate: bool,
+
+    /// JSON ABI used to decode custom revert errors (repeatable)
+    #[arg(long, requires = "simulate")]
+    abi: Vec<PathBuf>,
+
+    /// Broadcast even if the simulation reverts
+    #[arg(long, requires = "simulate")]
+    force: bool,
+
+    #[command(flatten)]
+    wait: wait::WaitArgs,
+}
 
-    /// RPC timeout in seconds
-    #[arg(long, default_value_t = 30)]
-    timeout: u64,
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Broadcast a directory of signed transactions in nonce order per sender
+    Batch(batch::BatchArgs),
+    /// Re-query the state of journaled transactions
+    Status(journal::StatusArgs),
+    /// Send a signed transaction or an ordered bundle privately to a Flashbots-style relay
+    Relay(relay::RelayArgs),
 }
 
 /// Broadcasts the signed transaction to the Ethereum network via JSON-RPC
-async fn broadcast_transaction(rpc_url: &str, signed_tx: Vec<u8>, timeout_secs: u64) -> eyre::Result<String> {
-    let client = Client::new();
+async fn broadcast_transaction(rpc: &rpc::Rpc, rpc_url: &str, signed_tx: Vec<u8>) -> eyre::Result<String> {
     let params = vec![format!("0x{}", hex::encode(signed_tx))];
 
-    let response = client
-        .post(rpc_url)
-        .json(&json!({
-            "jsonrpc": "2.0",
-            "method": "eth_sendRawTransaction",
-            "params": params,
-            "id": 1
-        }))
-        .timeout(Duration::from_secs(timeout_secs))
-        .send()
-        .await?
-        .json::<serde_json::Value>()
-        .await?;
-
-    if let Some(error) = response.get("error") {
-        return Err(eyre::eyre!("Error broadcasting transaction: {}", error));
-    }
-
-    let tx_hash = response
-        .get("result")
-        .and_then(|r| r.as_str())
-        .ok_or_else(|| eyre::eyre!("Failed to get transaction hash"))?;
+    let response = rpc.request_at(rpc_url, "eth_sendRawTransaction", json!(params)).await?;
+    let result = response.map_err(|error| node_error::BroadcastError::from_rpc(&error))?;
+
+    let tx_hash = result.as_str().ok_or_else(|| eyre::eyre!("Failed to get transaction hash"))?;
 
     Ok(tx_hash.to_string())
 }
 
 #[tokio::main]
-async fn main() -> eyre::Result<()> {
-    let args = Args::parse();
+async fn main() -> ExitCode {
+    match run(Args::parse()).await {
+        Ok(code) => code,
+        Err(e) => {
+            eprintln!("Error: {}", transport::redact(&format!("{:?}", e)));
+            e.downcast_ref::<node_error::BroadcastError>().map_or(ExitCode::FAILURE, |b| b.kind.exit_code())
+        }
+    }
+}
 
-    let signed_tx_hex = fs::read_to_string(args.input)?;
-    let signed_tx = hex::decode(signed_tx_hex.trim())?;
+async fn run(args: Args) -> eyre::Result<ExitCode> {
+    match args.command {
+        Some(Command::Batch(batch_args)) => return batch::run(batch_args).await,
+        Some(Command::Status(status_args)) => return journal::status(status_args).await,
+        Some(Command::Relay(relay_args)) => return relay::run(relay_args).await,
+        None => {}
+    }
+    let input = args.input.as_deref().expect("--input is required unless running a subcommand");
+    let input = tx_envelope::read_signed(Path::new(input))?;
+    if let SignedInput::Envelope(envelope) = &input {
+        println!(
+            "Envelope: from {:?}, chain {}, nonce {}, tx hash {:?}, unsigned payload {:?}",
+            envelope.from, envelope.chain_id, envelope.nonce, envelope.tx_hash, envelope.unsigned_hash
+        );
+    }
 
-    let tx_hash = broadcast_transaction(&args.rpc_url, signed_tx, args.timeout).await?;
-    println!("Transaction broadcasted successfully with hash: {}", tx_hash);
-    Ok(())
+    let (rpc, strategy) = args.rpc.connect()?;
+    let journal = args.journal.open();
+
+    let tx = preflight::decode(input.raw_tx())?;
+    if let SignedInput::Envelope(envelope) = &input
+        && envelope.from != tx.sender
+    {
+        return Err(eyre::eyre!("Envelope sender {:?} does not match the recovered sender {:?}", envelope.from, tx.sender));
+    }
+    let node = preflight::fetch(&rpc, tx.sender).await?;
+    if !preflight::report(&tx, &preflight::evaluate(&tx, &node)) {
+        return Err(eyre::eyre!("Pre-broadcast checks failed; nothing was broadcast"));
+    }
+    if args.simulate {
+        let decoder = simulate::RevertDecoder::new(&args.abi)?;
+        let simulation = simulate::simulate(&rpc, &tx).await?;
+        if !simulate::report(&tx, &simulation, &decoder) {
+            if !args.force {
+                return Err(eyre::eyre!("Simulation reverted; nothing was broadcast (pass --force to send anyway)"));
+            }
+            println!("WARNING: simulation reverted; broadcasting anyway because of --force");
+        }
+    }
+
+    let tx_hash = submit::broadcast(&rpc, &journal, &tx, input.raw_tx(), strategy).await?;
+    println!("Transaction broadcasted successfully with hash: {:?} (computed locally, confirmed by the node)", tx_hash);
+    if args.wait.wait {
+        let outcome = wait::wait(&rpc, &journal, strategy, &tx, input.raw_tx(), &args.wait).await?;
+        println!("Final status: {:?}", outcome);
+        return Ok(outcome.exit_code());
+    }
+    Ok(ExitCode::SUCCESS)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use ethers::signers::LocalWallet;
+    use ethers::types::{transaction::eip2718::TypedTransaction, Eip1559TransactionRequest};
+    use reqwest::{header::HeaderMap, Client};
     use std::fs;
+    use std::sync::{Arc, Mutex};
     use tokio::net::{TcpListener, TcpStream};
     use tokio::io::{AsyncReadExt, AsyncWriteExt};
     use serde_json::{json, Value};
 
     /// Read headers fully, then read exactly Content-Length bytes for the body.
-    async fn read_http_request(socket: &mut TcpStream) -> (String, Vec<u8>) {
+    pub(crate) async fn read_http_request(socket: &mut TcpStream) -> (String, Vec<u8>) {
         let mut buf: Vec<u8> = Vec::with_capacity(8192);
         let mut tmp = [0u8; 2048];
         let header_end_seq = b"\r\n\r\n";
@@ -127,6 +202,90 @@ mod tests {
         haystack.windows(needle.len()).position(|w| w == needle)
     }
 
+    pub(crate) type Answer = std::result::Result<Value, Value>;
+
+    /// Mock node answering every request with `answer(method, params)`. JSON-RPC batches are
+    /// answered per request when `batches` is set, and refused otherwise. Returns its URL and
+    /// the methods it was asked for, with "batch" for each batch request.
+    pub(crate) async fn spawn_mock_node(batches: bool, answer: impl Fn(&str, &Value) -> Answer + Send + Sync + 'static) -> (String, Arc<Mutex<Vec<String>>>) {
+        spawn_mock_node_with_headers(batches, move |_, method, params| answer(method, params)).await
+    }
+
+    /// [`spawn_mock_node`] whose `answer` also sees the raw HTTP request headers.
+    pub(crate) async fn spawn_mock_node_with_headers(
+        batches: bool,
+        answer: impl Fn(&str, &str, &Value) -> Answer + Send + Sync + 'static,
+    ) -> (String, Arc<Mutex<Vec<String>>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let methods = Arc::new(Mutex::new(Vec::new()));
+        let seen = methods.clone();
+        tokio::spawn(async move {
+            let respond = |headers: &str, request: &Value| {
+                let method = request["method"].as_str().unwrap_or_default();
+                match answer(headers, method, &request["params"]) {
+                    Ok(result) => json!({"jsonrpc": "2.0", "id": request["id"], "result": result}),
+                    Err(error) => json!({"jsonrpc": "2.0", "id": request["id"], "error": error}),
+                }
+            };
+            loop {
+                let Ok((mut socket, _)) = listener.accept().await else { return };
+                let (headers, body) = read_http_request(&mut socket).await;
+                let request: Value = serde_json::from_slice(&body).unwrap();
+                let response = match request.as_array() {
+                    Some(calls) => {
+                        seen.lock().unwrap().push("batch".to_string());
+                        if batches {
+                            Value::Array(calls.iter().map(|call| respond(&headers, call)).collect())
+                        } else {
+                            json!({"jsonrpc": "2.0", "id": null, "error": {"code": -32600, "message": "batch requests disabled"}})
+                        }
+                    }
+                    None => {
+                        seen.lock().unwrap().push(request["method"].as_str().unwrap_or_default().to_string());
+                        respond(&headers, &request)
+                    }
+                }
+                .to_string();
+                let resp = format!("HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}", response.len(), response);
+                let _ = socket.write_all(resp.as_bytes()).await;
+                let _ = socket.shutdown().await;
+            }
+        });
+        (url, methods)
+    }
+
+    pub(crate) fn node_error(message: &str) -> Answer {
+        Err(json!({"code": -32000, "message": message}))
+    }
+
+    /// An EIP-1559 transfer of `value` wei to 0x11..11, signed by `wallet`; returns the raw tx too.
+    pub(crate) fn signed_tx(wallet: &LocalWallet, chain_id: u64, nonce: u64, value: u64) -> (preflight::SignedTx, Vec<u8>) {
+        let tx: TypedTransaction = Eip1559TransactionRequest::new()
+            .to(ethers::types::Address::repeat_byte(0x11))
+            .value(value)
+            .nonce(nonce)
+            .gas(21_000u64)
+            .max_fee_per_gas(10u64)
+            .chain_id(chain_id)
+            .into();
+        let raw = tx.rlp_signed(&wallet.sign_transaction_sync(&tx).unwrap()).to_vec();
+        (preflight::decode(&raw).unwrap(), raw)
+    }
+
+    #[derive(Parser)]
+    struct JournalCli {
+        #[command(flatten)]
+        journal: journal::JournalArgs,
+    }
+
+    /// A journal in a fresh temp file; returns the file too, for cleanup.
+    pub(crate) fn test_journal(name: &str) -> (journal::Journal, std::path::PathBuf) {
+        let path = std::env::temp_dir().join(format!("tx_broadcaster_{}_{}.jsonl", name, std::process::id()));
+        let _ = fs::remove_file(&path);
+        (JournalCli::try_parse_from(["test", "--journal", &path.display().to_string()]).unwrap().journal.open(), path)
+    }
+
     /// Mock JSON-RPC server that validates request JSON.
     ///
     /// Checks:
@@ -159,7 +318,7 @@ mod tests {
                 // Validate params[0]
                 let p0 = v.get("params")
                     .and_then(|p| p.as_array())
-                    .and_then(|a| a.get(0))
+                    .and_then(|a| a.first())
                     .and_then(|x| x.as_str())
                     .expect("params[0] missing or not string");
                 assert_eq!(p0, expected_param_hex, "raw tx hex mismatch");
@@ -187,6 +346,37 @@ mod tests {
         (format!("http://{}", addr), mock_hash)
     }
 
+    /// IPC stand-in for geth.ipc: answers one `eth_sendRawTransaction` after checking the raw tx.
+    async fn spawn_mock_ipc_server(expected_param_hex: String) -> (String, String) {
+        let path = std::env::temp_dir().join(format!("tx_broadcaster_main_{}.ipc", std::process::id()));
+        let _ = fs::remove_file(&path);
+        let listener = tokio::net::UnixListener::bind(&path).unwrap();
+        let mock_hash = "0xbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbb".to_string();
+        let mock_hash_for_task = mock_hash.clone();
+
+        tokio::spawn(async move {
+            if let Ok((mut socket, _)) = listener.accept().await {
+                // No framing on IPC: read until the buffer holds one complete JSON value.
+                let mut buf = Vec::new();
+                let v: Value = loop {
+                    let mut tmp = [0u8; 2048];
+                    let n = socket.read(&mut tmp).await.unwrap_or(0);
+                    assert!(n > 0, "connection closed before a full request");
+                    buf.extend_from_slice(&tmp[..n]);
+                    if let Ok(v) = serde_json::from_slice(&buf) {
+                        break v;
+                    }
+                };
+                assert_eq!(v.get("method").and_then(|x| x.as_str()), Some("eth_sendRawTransaction"), "method mismatch");
+                assert_eq!(v["params"][0].as_str(), Some(expected_param_hex.as_str()), "raw tx hex mismatch");
+                let resp = json!({"jsonrpc": "2.0", "id": v["id"], "result": mock_hash_for_task}).to_string();
+                let _ = socket.write_all(resp.as_bytes()).await;
+            }
+        });
+
+        (path.display().to_string(), mock_hash)
+    }
+
     #[tokio::test]
     async fn test_broadcast_transaction_file_input_success_with_validation() {
         // Prepare dummy signed tx file
@@ -202,12 +392,26 @@ mod tests {
         let (rpc_url, mock_hash) = spawn_mock_rpc_server_with_validation(expected_param_hex, true).await;
 
         // Exercise function under test
-        let tx_hash = broadcast_transaction(&rpc_url, decoded, 5).await.unwrap();
+        let rpc = rpc::Rpc::new(Client::new(), HeaderMap::new(), vec![rpc_url.clone()], 5).unwrap();
+        let tx_hash = broadcast_transaction(&rpc, &rpc_url, decoded).await.unwrap();
         assert_eq!(tx_hash, mock_hash);
 
         let _ = fs::remove_file(tmp_file_path);
     }
 
+    #[tokio::test]
+    async fn test_broadcast_transaction_over_ipc() {
+        let signed_tx_hex = "f86c808504e3b2920082520894deadbeefdeadbeefdeadbeefdeadbeefdeadbeef88016345785d8a000080018080";
+        let decoded = hex::decode(signed_tx_hex).unwrap();
+        let (ipc_path, mock_hash) = spawn_mock_ipc_server(format!("0x{}", signed_tx_hex)).await;
+
+        let rpc = rpc::Rpc::new(Client::new(), HeaderMap::new(), vec![ipc_path.clone()], 5).unwrap();
+        let tx_hash = broadcast_transaction(&rpc, &ipc_path, decoded).await.unwrap();
+        assert_eq!(tx_hash, mock_hash);
+
+        let _ = fs::remove_file(ipc_path);
+    }
+
     #[tokio::test]
     async fn test_broadcast_transaction_error_path_with_validation() {
         let signed_tx_hex = "f86c808504e3b2920082520894deadbeefdeadbeefdeadbeefdeadbeefdeadbeef88016345785d8a000080018080";
@@ -217,7 +421,8 @@ mod tests {
         // Mock RPC server validates and returns an error
         let (rpc_url, _mock_hash) = spawn_mock_rpc_server_with_validation(expected_param_hex, false).await;
 
-        let err = broadcast_transaction(&rpc_url, decoded, 5).await.unwrap_err();
+        let rpc = rpc::Rpc::new(Client::new(), HeaderMap::new(), vec![rpc_url.clone()], 5).unwrap();
+        let err = broadcast_transaction(&rpc, &rpc_url, decoded).await.unwrap_err();
         let msg = format!("{:#}", err);
         assert!(msg.contains("Error broadcasting transaction"), "unexpected error text: {}", msg);
     }
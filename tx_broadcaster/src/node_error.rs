@@ -0,0 +1,153 @@
+//! Typed classification of `eth_sendRawTransaction` errors.
+//!
+//! Nodes report rejections as free text that differs between clients ("nonce too low",
+//! "OldNonce", ...). Each common family gets an [`ErrorKind`] with a remediation hint and its
+//! own process exit code, so automation can react without matching strings.
+
+use serde_json::Value;
+use std::fmt;
+use std::process::ExitCode;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorKind {
+    NonceTooLow,
+    NonceTooHigh,
+    ReplacementUnderpriced,
+    InsufficientFunds,
+    IntrinsicGasTooLow,
+    FeeCapTooLow,
+    TxTypeNotSupported,
+    AlreadyKnown,
+    /// No endpoint could be reached.
+    Unreachable,
+    Other,
+}
+
+/// Normalised substrings (letters only, lower case) identifying each family, checked in order.
+const PATTERNS: &[(ErrorKind, &[&str])] = &[
+    (ErrorKind::AlreadyKnown, &["alreadyknown", "knowntransaction", "alreadyimported"]),
+    (ErrorKind::ReplacementUnderpriced, &["replacementtransactionunderpriced", "replacementunderpriced"]),
+    (ErrorKind::NonceTooLow, &["noncetoolow", "oldnonce"]),
+    (ErrorKind::NonceTooHigh, &["noncetoohigh", "noncegap"]),
+    (ErrorKind::InsufficientFunds, &["insufficientfunds"]),
+    (ErrorKind::IntrinsicGasTooLow, &["intrinsicgastoolow", "intrinsicgas"]),
+    (ErrorKind::FeeCapTooLow, &["lessthanblockbasefee", "feecaptoolow", "transactionunderpriced"]),
+    (ErrorKind::TxTypeNotSupported, &["typenotsupported"]),
+];
+
+impl ErrorKind {
+    /// Classify a node's error message.
+    pub fn classify(message: &str) -> Self {
+        let normalised: String = message.chars().filter(|c| c.is_ascii_alphabetic()).collect::<String>().to_ascii_lowercase();
+        PATTERNS
+            .iter()
+            .find(|(_, needles)| needles.iter().any(|n| normalised.contains(n)))
+            .map_or(ErrorKind::Other, |(kind, _)| *kind)
+    }
+
+    /// Stable process exit code. 2-4 are used by `--wait`, 1 by any other error.
+    pub fn exit_code(self) -> ExitCode {
+        ExitCode::from(match self {
+            ErrorKind::NonceTooLow => 10,
+            ErrorKind::NonceTooHigh => 11,
+            ErrorKind::ReplacementUnderpriced => 12,
+            ErrorKind::InsufficientFunds => 13,
+            ErrorKind::IntrinsicGasTooLow => 14,
+            ErrorKind::FeeCapTooLow => 15,
+            ErrorKind::TxTypeNotSupported => 16,
+            ErrorKind::AlreadyKnown => 17,
+            ErrorKind::Unreachable => 18,
+            ErrorKind::Other => 19,
+        })
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            ErrorKind::NonceTooLow => "nonce too low",
+            ErrorKind::NonceTooHigh => "nonce too high",
+            ErrorKind::ReplacementUnderpriced => "replacement underpriced",
+            ErrorKind::InsufficientFunds => "insufficient funds",
+            ErrorKind::IntrinsicGasTooLow => "intrinsic gas too low",
+            ErrorKind::FeeCapTooLow => "fee cap below base fee",
+            ErrorKind::TxTypeNotSupported => "tx type not supported",
+            ErrorKind::AlreadyKnown => "already known",
+            ErrorKind::Unreachable => "unreachable",
+            ErrorKind::Other => "rejected",
+        }
+    }
+
+    pub fn hint(self) -> &'static str {
+        match self {
+            ErrorKind::NonceTooLow => "the nonce is already used; rebuild the tx with the account's current nonce",
+            ErrorKind::NonceTooHigh => "earlier nonces are missing; broadcast those first or rebuild with the next nonce",
+            ErrorKind::ReplacementUnderpriced => "a pending tx has this nonce; re-sign with fees at least 10% higher to replace it",
+            ErrorKind::InsufficientFunds => "the sender cannot cover value + gas_limit * max fee; fund it or lower the fees",
+            ErrorKind::IntrinsicGasTooLow => "the gas limit is below the minimum for this tx; rebuild with a higher gas limit",
+            ErrorKind::FeeCapTooLow => "the max fee is below the base fee or the node's minimum; re-sign with a higher max fee",
+            ErrorKind::TxTypeNotSupported => "this chain or node does not accept the tx type; rebuild as a legacy tx",
+            ErrorKind::AlreadyKnown => "the node already has this tx; nothing to do",
+            ErrorKind::Unreachable => "no endpoint answered; check connectivity or add another --rpc-url",
+            ErrorKind::Other => "see the node's message",
+        }
+    }
+}
+
+/// An `eth_sendRawTransaction` failure.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BroadcastError {
+    pub kind: ErrorKind,
+    /// JSON-RPC error code, when the node sent one.
+    pub code: Option<i64>,
+    pub message: String,
+}
+
+impl BroadcastError {
+    /// From the JSON-RPC `error` object.
+    pub fn from_rpc(error: &Value) -> Self {
+        let message = error.get("message").and_then(Value::as_str).map_or_else(|| error.to_string(), str::to_string);
+        BroadcastError { kind: ErrorKind::classify(&message), code: error.get("code").and_then(Value::as_i64), message }
+    }
+}
+
+impl fmt::Display for BroadcastError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Error broadcasting transaction [{}]: {}", self.kind.label(), self.message)?;
+        if let Some(code) = self.code {
+            write!(f, " (code {})", code)?;
+        }
+        write!(f, "\nHint: {}", self.kind.hint())
+    }
+}
+
+impl std::error::Error for BroadcastError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn classifies_common_client_messages() {
+        let cases = [
+            ("nonce too low: next nonce 5, tx nonce 3", ErrorKind::NonceTooLow),
+            ("OldNonce", ErrorKind::NonceTooLow),
+            ("nonce too high", ErrorKind::NonceTooHigh),
+            ("replacement transaction underpriced", ErrorKind::ReplacementUnderpriced),
+            ("insufficient funds for gas * price + value", ErrorKind::InsufficientFunds),
+            ("intrinsic gas too low", ErrorKind::IntrinsicGasTooLow),
+            ("max fee per gas less than block base fee", ErrorKind::FeeCapTooLow),
+            ("transaction underpriced", ErrorKind::FeeCapTooLow),
+            ("transaction type not supported", ErrorKind::TxTypeNotSupported),
+            ("ALREADY_KNOWN", ErrorKind::AlreadyKnown),
+            ("execution reverted", ErrorKind::Other),
+        ];
+        for (message, kind) in cases {
+            assert_eq!(ErrorKind::classify(message), kind, "{message}");
+        }
+
+        let error = BroadcastError::from_rpc(&json!({"code": -32000, "message": "insufficient funds for transfer"}));
+        assert_eq!((error.kind, error.code), (ErrorKind::InsufficientFunds, Some(-32000)));
+        assert_eq!(error.kind.exit_code(), ExitCode::from(13));
+        assert!(error.to_string().starts_with("Error broadcasting transaction [insufficient funds]"));
+    }
+}
@@ -0,0 +1,180 @@
+//! Pre-broadcast checks: decode the signed transaction, recover its sender and compare it
+//! with what the node reports, so that a transaction for one chain is never sent to another
+//! chain's RPC and a stale or unfunded transaction is caught before it leaves the host.
+
+use crate::rpc::{quantity, Rpc};
+use ethers::types::transaction::eip2718::TypedTransaction;
+use ethers::types::{Address, H256, U256};
+use ethers::utils::{format_units, keccak256, rlp};
+use eyre::{eyre, Result};
+use serde_json::{json, Value};
+
+/// Fields of the signed transaction that the checks need.
+#[derive(Debug, Clone)]
+pub struct SignedTx {
+    pub sender: Address,
+    pub hash: H256,
+    pub chain_id: Option<u64>,
+    pub nonce: U256,
+    pub gas_limit: U256,
+    /// gasPrice (legacy) or maxFeePerGas (EIP-1559).
+    pub max_fee_per_gas: U256,
+    pub value: U256,
+    /// The decoded transaction itself, replayed by `--simulate`.
+    pub request: TypedTransaction,
+}
+
+impl SignedTx {
+    /// Worst-case cost: value + gas_limit * max price per gas.
+    pub fn max_cost(&self) -> U256 {
+        self.value.saturating_add(self.gas_limit.saturating_mul(self.max_fee_per_gas))
+    }
+}
+
+/// What the node reports for the sender.
+#[derive(Debug, Clone)]
+pub struct NodeState {
+    pub chain_id: u64,
+    pub latest_nonce: U256,
+    pub pending_nonce: U256,
+    pub balance: U256,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Verdict {
+    Go,
+    Warn,
+    NoGo,
+}
+
+#[derive(Debug)]
+pub struct Check {
+    pub name: &'static str,
+    pub verdict: Verdict,
+    pub detail: String,
+}
+
+pub fn decode(raw: &[u8]) -> Result<SignedTx> {
+    let (tx, sig) = TypedTransaction::decode_signed(&rlp::Rlp::new(raw))
+        .map_err(|e| eyre!("Input is not a signed transaction: {}", e))?;
+    let sender = sig.recover(tx.sighash()).map_err(|e| eyre!("Cannot recover the sender: {}", e))?;
+    Ok(SignedTx {
+        sender,
+        hash: H256::from(keccak256(raw)),
+        chain_id: tx.chain_id().map(|c| u64::try_from(c).map_err(|_| eyre!("Chain ID {} does not fit in 64 bits", c))).transpose()?,
+        nonce: tx.nonce().copied().unwrap_or_default(),
+        gas_limit: tx.gas().copied().unwrap_or_default(),
+        max_fee_per_gas: tx.gas_price().unwrap_or_default(),
+        value: tx.value().copied().unwrap_or_default(),
+        request: tx,
+    })
+}
+
+/// The node's `eth_chainId`. A value above `u64::MAX` is an error, so that it cannot be
+/// truncated into a chain ID that matches the transaction.
+pub async fn chain_id(rpc: &Rpc) -> Result<u64> {
+    let chain_id = quantity("eth_chainId", &rpc.call("eth_chainId", json!([])).await?)?;
+    u64::try_from(chain_id).map_err(|_| eyre!("Node reported chain ID {}, which does not fit in 64 bits", chain_id))
+}
+
+/// Ask the node for its chain ID and the sender's nonces and balance.
+pub async fn fetch(rpc: &Rpc, sender: Address) -> Result<NodeState> {
+    let query = async |method: &str, params: Value| -> Result<U256> { quantity(method, &rpc.call(method, params).await?) };
+    let chain_id = chain_id(rpc).await?;
+    let latest_nonce = query("eth_getTransactionCount", json!([sender, "latest"])).await?;
+    let pending_nonce = query("eth_getTransactionCount", json!([sender, "pending"])).await?;
+    let balance = query("eth_getBalance", json!([sender, "pending"])).await?;
+    Ok(NodeState { chain_id, latest_nonce, pending_nonce, balance })
+}
+
+pub fn evaluate(tx: &SignedTx, node: &NodeState) -> Vec<Check> {
+    let mut checks = Vec::new();
+    let mut check = |name, verdict, detail: String| checks.push(Check { name, verdict, detail });
+
+    match tx.chain_id {
+        Some(chain_id) if chain_id == node.chain_id => check("chain ID", Verdict::Go, format!("tx {chain_id}, node {}", node.chain_id)),
+        Some(chain_id) => check("chain ID", Verdict::NoGo, format!("tx is for chain {chain_id}, node is chain {}", node.chain_id)),
+        None => check("chain ID", Verdict::NoGo, "tx has no chain ID (not EIP-155 replay protected)".to_string()),
+    }
+
+    let (nonce, latest, pending) = (tx.nonce, node.latest_nonce, node.pending_nonce);
+    if nonce < latest {
+        check("nonce", Verdict::NoGo, format!("tx nonce {nonce} is already mined (account nonce {latest})"));
+    } else if nonce < pending {
+        check("nonce", Verdict::Warn, format!("tx nonce {nonce} replaces a pending transaction (pending nonce {pending})"));
+    } else if nonce > pending {
+        check("nonce", Verdict::NoGo, format!("tx nonce {nonce} leaves a gap (next nonce is {pending})"));
+    } else {
+        check("nonce", Verdict::Go, format!("tx nonce {nonce} is the next nonce"));
+    }
+
+    let eth = |v: U256| format_units(v, "ether").unwrap_or_else(|_| v.to_string());
+    let needed = tx.max_cost();
+    let detail = format!("needs {} (value + gas_limit * max fee), has {}", eth(needed), eth(node.balance));
+    check("balance", if node.balance >= needed { Verdict::Go } else { Verdict::NoGo }, detail);
+    checks
+}
+
+/// Print the go/no-go summary; returns whether the transaction may be sent.
+pub fn report(tx: &SignedTx, checks: &[Check]) -> bool {
+    println!("==================== PRE-BROADCAST CHECKS ====================");
+    println!("Sender:   {:?}", tx.sender);
+    println!("Tx hash:  {:?}", tx.hash);
+    for c in checks {
+        let tag = match c.verdict {
+            Verdict::Go => "[GO]   ",
+            Verdict::Warn => "[WARN] ",
+            Verdict::NoGo => "[NO-GO]",
+        };
+        println!("{tag} {}: {}", c.name, c.detail);
+    }
+    println!("==============================================================");
+    let go = checks.iter().all(|c| c.verdict != Verdict::NoGo);
+    println!("Decision: {}", if go { "GO" } else { "NO-GO" });
+    go
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::signed_tx;
+    use ethers::signers::{LocalWallet, Signer};
+    use std::str::FromStr;
+
+    fn verdicts(tx: &SignedTx, node: &NodeState) -> Vec<Verdict> {
+        evaluate(tx, node).into_iter().map(|c| c.verdict).collect()
+    }
+
+    #[test]
+    fn decodes_and_evaluates_against_node_state() {
+        let wallet = LocalWallet::from_str("4c0883a69102937d6231471b5ecb4765d5e97f8e4dc6e8fa6a4de3b8a3a2f55b").unwrap();
+        let (sender, (_, raw)) = (wallet.address(), signed_tx(&wallet, 137, 5, 1_000));
+        let tx = decode(&raw).unwrap();
+        assert_eq!((tx.sender, tx.chain_id, tx.nonce), (sender, Some(137), 5u64.into()));
+        assert_eq!(tx.max_cost(), U256::from(1_000 + 21_000 * 10));
+
+        let node = NodeState { chain_id: 137, latest_nonce: 5u64.into(), pending_nonce: 5u64.into(), balance: tx.max_cost() };
+        assert_eq!(verdicts(&tx, &node), [Verdict::Go, Verdict::Go, Verdict::Go]);
+        // Polygon tx against a mainnet RPC.
+        assert_eq!(verdicts(&tx, &NodeState { chain_id: 1, ..node.clone() })[0], Verdict::NoGo);
+        // Mined, replacement, gap.
+        assert_eq!(verdicts(&tx, &NodeState { latest_nonce: 6u64.into(), pending_nonce: 6u64.into(), ..node.clone() })[1], Verdict::NoGo);
+        assert_eq!(verdicts(&tx, &NodeState { pending_nonce: 6u64.into(), ..node.clone() })[1], Verdict::Warn);
+        assert_eq!(verdicts(&tx, &NodeState { latest_nonce: 4u64.into(), pending_nonce: 4u64.into(), ..node.clone() })[1], Verdict::NoGo);
+        // One wei short.
+        assert_eq!(verdicts(&tx, &NodeState { balance: tx.max_cost() - 1, ..node })[2], Verdict::NoGo);
+    }
+
+    #[test]
+    fn chain_id_above_u64_is_an_error() {
+        let mut stream = rlp::RlpStream::new_list(12);
+        stream.append(&(U256::from(u64::MAX) + 1));
+        for field in [0u64, 1, 10, 21_000] {
+            stream.append(&field);
+        }
+        stream.append(&Address::repeat_byte(0x11)).append(&1u64).append(&Vec::<u8>::new()).begin_list(0);
+        stream.append(&0u64).append(&U256::from(1)).append(&U256::from(1));
+        let raw = [&[2u8][..], &stream.out()].concat();
+        assert!(decode(&raw).is_err());
+    }
+}
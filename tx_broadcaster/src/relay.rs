@@ -0,0 +1,405 @@
+//! `tx_broadcaster relay`: private submission to a Flashbots-style relay, keeping transactions
+//! out of the public mempool until they are mined.
+//!
+//! A single transaction goes out with `eth_sendPrivateTransaction`, valid until the last target
+//! block. Several (or `--bundle`) go out with `eth_sendBundle` once per target block, in the
+//! order given; blocks the relay rejects are reported, and the others are still followed.
+//! Every request carries `X-Flashbots-Signature`, signed by a reputation key that must not be
+//! one of the senders. Inclusion is followed on the public node, and the relay's bundle stats
+//! are printed for each target block that passes without it.
+
+use crate::journal::{Journal, JournalArgs};
+use crate::node_error::BroadcastError;
+use crate::preflight::{self, NodeState, SignedTx};
+use crate::rpc::{quantity, Rpc, RpcArgs};
+use crate::transport::{self, redact};
+use crate::wait::{Outcome, Receipt};
+use ethers::signers::{LocalWallet, Signer};
+use ethers::types::{Address, H256, U256};
+use ethers::utils::{format_units, hash_message, keccak256};
+use eyre::{eyre, Result};
+use reqwest::header::{HeaderMap, CONTENT_TYPE};
+use reqwest::Client;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::process::ExitCode;
+use std::time::Duration;
+
+#[derive(clap::Args, Debug)]
+pub struct RelayArgs {
+    /// Signed transaction (hex or envelope); repeat for a bundle, in execution order
+    #[arg(long = "input", required = true)]
+    inputs: Vec<PathBuf>,
+
+    /// Relay endpoint, e.g. https://relay.flashbots.net
+    #[arg(long)]
+    relay_url: String,
+
+    /// Reputation key for X-Flashbots-Signature: `env:VAR` or `file:PATH` (hex only with --insecure-argv-key). Must not be a sender's key
+    #[arg(long, value_name = "KEY")]
+    auth_key: String,
+
+    /// Allow a literal hex --auth-key even though argv is visible to other processes
+    #[arg(long)]
+    insecure_argv_key: bool,
+
+    /// Send a single transaction as a one-tx bundle instead of with eth_sendPrivateTransaction
+    #[arg(long)]
+    bundle: bool,
+
+    /// Number of blocks after the current head the submission is valid for
+    #[arg(long, default_value_t = 25, value_parser = clap::value_parser!(u64).range(1..))]
+    target_blocks: u64,
+
+    /// Seconds between inclusion polls, when no ws/IPC endpoint offers newHeads
+    #[arg(long, default_value_t = 4)]
+    poll_interval: u64,
+
+    /// The public node used for checks and to follow inclusion
+    #[command(flatten)]
+    rpc: RpcArgs,
+
+    #[command(flatten)]
+    journal: JournalArgs,
+}
+
+/// `X-Flashbots-Signature`: `address:signature`, the EIP-191 signature of the hex keccak of the body.
+pub fn flashbots_signature(wallet: &LocalWallet, body: &str) -> Result<String> {
+    let digest = format!("{:?}", H256::from(keccak256(body.as_bytes())));
+    let signature = wallet.sign_hash(hash_message(digest))?;
+    Ok(format!("{:?}:0x{}", wallet.address(), signature))
+}
+
+/// Check the transactions against the node as one bundle: the k-th tx of a sender must use its
+/// latest nonce + k, and each sender must afford all of its txs.
+fn check_bundle(txs: &[SignedTx], chain_id: u64, nodes: &HashMap<Address, NodeState>) -> Vec<String> {
+    let mut problems = Vec::new();
+    let mut next: HashMap<Address, (U256, U256)> = HashMap::new();
+    let eth = |v: U256| format_units(v, "ether").unwrap_or_else(|_| v.to_string());
+    for (i, tx) in txs.iter().enumerate() {
+        if tx.chain_id != Some(chain_id) {
+            problems.push(format!("tx {} ({:?}) is for chain {:?}, node is chain {}", i + 1, tx.hash, tx.chain_id, chain_id));
+        }
+        let node = &nodes[&tx.sender];
+        let (nonce, cost) = next.entry(tx.sender).or_insert((node.latest_nonce, U256::zero()));
+        if tx.nonce != *nonce {
+            problems.push(format!("tx {} ({:?}) has nonce {}, expected {} for {:?}", i + 1, tx.hash, tx.nonce, nonce, tx.sender));
+        }
+        *nonce = tx.nonce + 1;
+        *cost = cost.saturating_add(tx.max_cost());
+        if node.balance < *cost {
+            problems.push(format!("{:?} needs {} for txs up to {}, has {}", tx.sender, eth(*cost), i + 1, eth(node.balance)));
+        }
+    }
+    problems
+}
+
+struct Relay {
+    client: Client,
+    url: String,
+    wallet: LocalWallet,
+    timeout: Duration,
+}
+
+impl Relay {
+    async fn call(&self, method: &str, params: Value) -> Result<Value> {
+        let body = json!({ "jsonrpc": "2.0", "id": 1, "method": method, "params": params }).to_string();
+        let response = self
+            .client
+            .post(&self.url)
+            .header(CONTENT_TYPE, "application/json")
+            .header("X-Flashbots-Signature", flashbots_signature(&self.wallet, &body)?)
+            .body(body)
+            .timeout(self.timeout)
+            .send()
+            .await?
+            .json::<Value>()
+            .await?;
+        if let Some(error) = response.get("error") {
+            return Err(BroadcastError::from_rpc(error).into());
+        }
+        response.get("result").cloned().ok_or_else(|| eyre!("Relay {} returned no result", method))
+    }
+}
+
+/// The reputation key from `env:VAR` or `file:PATH`; a literal key needs `insecure_argv_key`.
+fn auth_wallet(spec: &str, insecure_argv_key: bool) -> Result<LocalWallet> {
+    if !spec.starts_with("env:") && !spec.starts_with("file:") {
+        if !insecure_argv_key {
+            return Err(eyre!(
+                "Refusing a literal --auth-key: command-line arguments are visible to other processes and shell history. \
+                 Use env:VAR or file:PATH, or pass --insecure-argv-key to override"
+            ));
+        }
+        eprintln!("Warning: relay auth key was passed on the command line (--insecure-argv-key)");
+    }
+    transport::secret(spec)?
+        .trim()
+        .trim_start_matches("0x")
+        .parse()
+        .map_err(|_| eyre!("--auth-key is not a valid private key"))
+}
+
+/// Send the bundle for every block in `first..=last`. A block the relay rejects is reported and
+/// skipped; the accepted ones are returned with their bundle hashes, and an error only if
+/// none was accepted.
+async fn send_bundles(
+    relay: &Relay,
+    journal: &Journal,
+    txs: &[SignedTx],
+    raws: &[String],
+    first: u64,
+    last: u64,
+) -> Result<Vec<(u64, Option<String>)>> {
+    let mut bundle_hashes = Vec::new();
+    let mut last_error = None;
+    for block in first..=last {
+        let result = relay.call("eth_sendBundle", json!([{ "txs": raws, "blockNumber": format!("{:#x}", block) }])).await;
+        journal_all(journal, &relay.url, txs, &result);
+        match result {
+            Ok(value) => bundle_hashes.push((block, value.get("bundleHash").and_then(Value::as_str).map(str::to_string))),
+            Err(e) => {
+                println!("Block {}: relay rejected the bundle: {}", block, redact(&e.to_string()));
+                last_error = Some(e);
+            }
+        }
+    }
+    match last_error {
+        Some(e) if bundle_hashes.is_empty() => Err(e.wrap_err("Relay rejected the bundle for every target block")),
+        Some(_) => {
+            println!("Relay accepted the bundle for {} of {} target blocks", bundle_hashes.len(), last - first + 1);
+            Ok(bundle_hashes)
+        }
+        None => Ok(bundle_hashes),
+    }
+}
+
+/// Record one relay answer (the tx or bundle hash, or the error) for every tx of the submission.
+fn journal_all(journal: &Journal, url: &str, txs: &[SignedTx], result: &Result<Value>) {
+    for tx in txs {
+        match result {
+            Ok(value) => {
+                let response = value.get("bundleHash").unwrap_or(value);
+                journal.record(url, tx, Ok(&response.as_str().map_or_else(|| response.to_string(), str::to_string)))
+            }
+            Err(e) => journal.record(url, tx, Err(e.to_string())),
+        }
+    }
+}
+
+pub async fn run(args: RelayArgs) -> Result<ExitCode> {
+    let (rpc, _) = args.rpc.connect()?;
+    let journal = args.journal.open();
+    let mut txs = Vec::new();
+    let mut raws = Vec::new();
+    for path in &args.inputs {
+        let input = tx_envelope::read_signed(path)?;
+        txs.push(preflight::decode(input.raw_tx())?);
+        raws.push(format!("0x{}", hex::encode(input.raw_tx())));
+    }
+
+    let wallet = auth_wallet(&args.auth_key, args.insecure_argv_key)?;
+    if txs.iter().any(|tx| tx.sender == wallet.address()) {
+        return Err(eyre!("--auth-key must be a separate reputation key, not the key of a sender"));
+    }
+    transport::register_url(&args.relay_url);
+    let relay = Relay {
+        client: args.rpc.transport.client(HeaderMap::new())?,
+        url: args.relay_url.clone(),
+        wallet,
+        timeout: Duration::from_secs(args.rpc.timeout),
+    };
+
+    let chain_id = preflight::chain_id(&rpc).await?;
+    let mut nodes = HashMap::new();
+    for tx in &txs {
+        if let std::collections::hash_map::Entry::Vacant(entry) = nodes.entry(tx.sender) {
+            entry.insert(preflight::fetch(&rpc, tx.sender).await?);
+        }
+    }
+    let problems = check_bundle(&txs, chain_id, &nodes);
+    for problem in &problems {
+        println!("[NO-GO] {}", problem);
+    }
+    if !problems.is_empty() {
+        return Err(eyre!("Bundle checks failed; nothing was sent to the relay"));
+    }
+
+    let head = quantity("eth_blockNumber", &rpc.call("eth_blockNumber", json!([])).await?)?.as_u64();
+    let (first, last) = (head + 1, head + args.target_blocks);
+    let bundle_hashes = if txs.len() == 1 && !args.bundle {
+        let params = json!([{ "tx": raws[0], "maxBlockNumber": format!("{:#x}", last) }]);
+        let result = relay.call("eth_sendPrivateTransaction", params).await;
+        journal_all(&journal, &args.relay_url, &txs, &result);
+        result?;
+        println!("Private transaction {:?} sent to {}, valid through block {}", txs[0].hash, redact(&args.relay_url), last);
+        Vec::new()
+    } else {
+        let bundle_hashes = send_bundles(&relay, &journal, &txs, &raws, first, last).await?;
+        println!("Bundle of {} tx(s) sent to {} for blocks {}-{}", txs.len(), redact(&args.relay_url), first, last);
+        if let Some((_, Some(hash))) = bundle_hashes.first() {
+            println!("Bundle hash: {}", hash);
+        }
+        bundle_hashes
+    };
+
+    let outcome = follow(&rpc, &relay, &txs, &bundle_hashes, last, Duration::from_secs(args.poll_interval)).await?;
+    println!("Final status: {:?}", outcome);
+    Ok(outcome.exit_code())
+}
+
+/// Wait until every tx has a receipt or the last target block passes without them.
+async fn follow(
+    rpc: &Rpc,
+    relay: &Relay,
+    txs: &[SignedTx],
+    bundle_hashes: &[(u64, Option<String>)],
+    last: u64,
+    poll: Duration,
+) -> Result<Outcome> {
+    let mut heads = rpc.new_heads().await;
+    let mut reported = bundle_hashes.first().map_or(0, |(block, _)| block - 1);
+    loop {
+        let head = quantity("eth_blockNumber", &rpc.call("eth_blockNumber", json!([])).await?)?.as_u64();
+        let mut receipts = Vec::new();
+        for tx in txs {
+            let receipt = rpc.call("eth_getTransactionReceipt", json!([tx.hash])).await?;
+            if receipt.is_null() {
+                break;
+            }
+            receipts.push(serde_json::from_value::<Receipt>(receipt)?);
+        }
+        if receipts.len() == txs.len() {
+            for (tx, receipt) in txs.iter().zip(&receipts) {
+                let status = if receipt.status.is_some_and(|s| s.is_zero()) { "REVERTED" } else { "success" };
+                println!("{:?}: included in block {} ({})", tx.hash, receipt.block_number, status);
+            }
+            return Ok(if receipts.iter().any(|r| r.status.is_some_and(|s| s.is_zero())) { Outcome::Reverted } else { Outcome::Success });
+        }
+
+        // Ask the relay what happened to the bundle in every target block that has passed.
+        for (block, hash) in bundle_hashes.iter().filter(|(block, _)| *block > reported && *block <= head) {
+            let Some(hash) = hash else {
+                continue;
+            };
+            match relay.call("flashbots_getBundleStatsV2", json!([{ "bundleHash": hash, "blockNumber": format!("{:#x}", block) }])).await {
+                Ok(stats) => println!(
+                    "Block {}: not included (simulated: {}, considered by {} builder(s))",
+                    block,
+                    stats.get("isSimulated").and_then(Value::as_bool).unwrap_or(false),
+                    stats.get("consideredByBuildersAt").and_then(Value::as_array).map_or(0, Vec::len)
+                ),
+                Err(e) => println!("Block {}: not included (no bundle stats: {})", block, redact(&e.to_string())),
+            }
+        }
+        reported = reported.max(head);
+
+        if head >= last {
+            if receipts.is_empty() {
+                println!("Not included by block {}; the submission has expired", last);
+            } else {
+                println!("Only {} of {} tx(s) were included by block {}", receipts.len(), txs.len(), last);
+            }
+            return Ok(Outcome::Dropped);
+        }
+        match &mut heads {
+            Some(subscription) => {
+                if let Ok(Err(e)) = tokio::time::timeout(poll.max(Duration::from_secs(30)), subscription.next()).await {
+                    println!("newHeads subscription lost ({}); polling every {}s", redact(&e.to_string()), poll.as_secs());
+                    heads = None;
+                }
+            }
+            None => tokio::time::sleep(poll).await,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{node_error, signed_tx, spawn_mock_node_with_headers, test_journal};
+    use ethers::types::Signature;
+
+    fn signed(wallet: &LocalWallet, nonce: u64) -> SignedTx {
+        signed_tx(wallet, 1, nonce, 1).0
+    }
+
+    #[test]
+    fn signs_the_body_hash_with_the_reputation_key() {
+        let wallet = LocalWallet::new(&mut ethers::core::rand::thread_rng());
+        let body = r#"{"jsonrpc":"2.0","id":1,"method":"eth_sendBundle","params":[]}"#;
+        let header = flashbots_signature(&wallet, body).unwrap();
+        let (address, signature) = header.split_once(':').unwrap();
+        assert_eq!(address, format!("{:?}", wallet.address()));
+
+        let signature: Signature = signature.parse().unwrap();
+        let digest = format!("{:?}", H256::from(keccak256(body.as_bytes())));
+        assert_eq!(signature.recover(hash_message(digest)).unwrap(), wallet.address());
+    }
+
+    #[test]
+    fn bundle_nonces_follow_the_latest_nonce_per_sender() {
+        let (a, b) = (LocalWallet::new(&mut ethers::core::rand::thread_rng()), LocalWallet::new(&mut ethers::core::rand::thread_rng()));
+        let node = |nonce: u64| NodeState { chain_id: 1, latest_nonce: nonce.into(), pending_nonce: nonce.into(), balance: 10u64.pow(9).into() };
+        let nodes = HashMap::from([(a.address(), node(4)), (b.address(), node(0))]);
+
+        let bundle = [signed(&a, 4), signed(&b, 0), signed(&a, 5)];
+        assert!(check_bundle(&bundle, 1, &nodes).is_empty());
+
+        let gap = [signed(&a, 4), signed(&a, 6)];
+        let problems = check_bundle(&gap, 1, &nodes);
+        assert_eq!(problems.len(), 1);
+        assert!(problems[0].contains("has nonce 6, expected 5"), "{problems:?}");
+        assert_eq!(check_bundle(&[signed(&b, 0)], 137, &nodes).len(), 1);
+    }
+
+    #[test]
+    fn literal_auth_key_needs_the_opt_in_flag() {
+        let key = "0x59c6995e998f97a5a0044966f0945389dc9e86dae88c7a8412f4603b6b78690d";
+        let err = auth_wallet(key, false).unwrap_err().to_string();
+        assert!(err.contains("--insecure-argv-key"), "{err}");
+        let literal = auth_wallet(key, true).unwrap();
+
+        let path = std::env::temp_dir().join(format!("tx_broadcaster_auth_key_{}", std::process::id()));
+        std::fs::write(&path, format!("{key}\n")).unwrap();
+        assert_eq!(auth_wallet(&format!("file:{}", path.display()), false).unwrap().address(), literal.address());
+        let _ = std::fs::remove_file(path);
+        assert!(auth_wallet("env:TX_BROADCASTER_TEST_UNSET_AUTH_KEY", false).is_err());
+    }
+
+    /// Relay answering `eth_sendBundle` per block: rejected for blocks in `reject`.
+    async fn spawn_mock_relay(reject: &'static [u64]) -> String {
+        let (url, _) = spawn_mock_node_with_headers(false, move |headers, method, params| {
+            assert!(headers.to_ascii_lowercase().contains("x-flashbots-signature"), "{headers}");
+            assert_eq!(method, "eth_sendBundle");
+            let block = u64::from_str_radix(params[0]["blockNumber"].as_str().unwrap().trim_start_matches("0x"), 16).unwrap();
+            if reject.contains(&block) { node_error("block already built") } else { Ok(json!({"bundleHash": format!("0xb{block}")})) }
+        })
+        .await;
+        url
+    }
+
+    #[tokio::test]
+    async fn rejected_blocks_do_not_abort_the_bundle() {
+        let sender = LocalWallet::new(&mut ethers::core::rand::thread_rng());
+        let txs = [signed(&sender, 0)];
+        let raws = vec!["0x02".to_string()];
+        let (journal, path) = test_journal("relay");
+        let relay = |url: String| Relay {
+            client: Client::new(),
+            url,
+            wallet: LocalWallet::new(&mut ethers::core::rand::thread_rng()),
+            timeout: Duration::from_secs(5),
+        };
+
+        let accepted = send_bundles(&relay(spawn_mock_relay(&[11]).await), &journal, &txs, &raws, 10, 12).await.unwrap();
+        assert_eq!(accepted, [(10, Some("0xb10".to_string())), (12, Some("0xb12".to_string()))]);
+        // Every answer is journaled, the rejection included.
+        assert_eq!(std::fs::read_to_string(&path).unwrap().lines().count(), 3);
+
+        let err = send_bundles(&relay(spawn_mock_relay(&[20, 21]).await), &journal, &txs, &raws, 20, 21).await.unwrap_err();
+        assert!(format!("{err:?}").contains("every target block"), "{err:?}");
+        let _ = std::fs::remove_file(path);
+    }
+}
@@ -0,0 +1,327 @@
+//! JSON-RPC access to one or more endpoints.
+//!
+//! Reads go to the first endpoint that answers, trying the others in order with backoff, so
+//! that one provider outage does not block a broadcast. Endpoints are HTTP(S), WebSocket or
+//! IPC, and come from repeated `--rpc-url` flags or from a TOML file given with `--rpc-config`:
+//!
+//! ```toml
+//! urls = ["/var/lib/geth/geth.ipc", "wss://polygon-rpc.example", "https://backup.example"]
+//! strategy = "fan-out"   # optional, default "failover"
+//! ```
+
+use crate::socket::{self, Heads, Socket};
+use crate::transport::{self, redact, TransportArgs};
+use ethers::types::U256;
+use eyre::{eyre, Result};
+use reqwest::{header::HeaderMap, Client};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+use tokio::sync::Mutex;
+
+/// How a signed transaction is submitted when several endpoints are configured.
+#[derive(clap::ValueEnum, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum Strategy {
+    /// Try the endpoints in order with backoff until one accepts the transaction.
+    #[default]
+    Failover,
+    /// Submit to every endpoint at once and reconcile the answers.
+    FanOut,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct RpcConfig {
+    pub urls: Vec<String>,
+    pub strategy: Option<Strategy>,
+}
+
+impl RpcConfig {
+    pub fn load(path: &Path) -> Result<Self> {
+        toml::from_str(&fs::read_to_string(path)?).map_err(|e| eyre!("RPC config {}: {}", path.display(), e))
+    }
+}
+
+/// Endpoint selection shared by the single and batch commands.
+#[derive(clap::Args, Debug)]
+pub struct RpcArgs {
+    /// RPC endpoint: http(s)://, ws(s):// or an IPC socket path (e.g. geth.ipc); repeat for failover or fan-out
+    #[arg(long, required_unless_present = "rpc_config", conflicts_with = "rpc_config")]
+    pub rpc_url: Vec<String>,
+
+    /// TOML file listing the RPC endpoints (`urls = [...]`, optional `strategy`)
+    #[arg(long)]
+    pub rpc_config: Option<PathBuf>,
+
+    /// How to submit when several endpoints are configured (default: failover)
+    #[arg(long, value_enum)]
+    pub strategy: Option<Strategy>,
+
+    /// RPC timeout in seconds
+    #[arg(long, default_value_t = 30)]
+    pub timeout: u64,
+
+    #[command(flatten)]
+    pub transport: TransportArgs,
+}
+
+impl RpcArgs {
+    pub fn connect(&self) -> Result<(Rpc, Strategy)> {
+        let (urls, config_strategy) = match &self.rpc_config {
+            Some(path) => {
+                let config = RpcConfig::load(path)?;
+                (config.urls, config.strategy)
+            }
+            None => (self.rpc_url.clone(), None),
+        };
+        urls.iter().for_each(|url| transport::register_url(url));
+        if self.transport.http_only() && urls.iter().any(|url| socket::is_ws(url)) {
+            return Err(eyre!("--proxy, --client-cert and --ca-bundle apply to HTTP endpoints only, not ws://"));
+        }
+        let headers = self.transport.headers()?;
+        let client = self.transport.client(headers.clone())?;
+        Ok((Rpc::new(client, headers, urls, self.timeout)?, self.strategy.or(config_strategy).unwrap_or_default()))
+    }
+}
+
+/// Delay before trying the endpoint after `attempt` failed ones.
+pub fn backoff(attempt: usize) -> Duration {
+    Duration::from_millis(250 << attempt.min(5))
+}
+
+pub struct Rpc {
+    client: Client,
+    /// Also sent with WebSocket handshakes.
+    headers: HeaderMap,
+    urls: Vec<String>,
+    /// One connection per WebSocket/IPC endpoint, opened on first use and after errors.
+    sockets: HashMap<String, Mutex<Option<Socket>>>,
+    timeout_secs: u64,
+}
+
+impl Rpc {
+    pub fn new(client: Client, headers: HeaderMap, urls: Vec<String>, timeout_secs: u64) -> Result<Self> {
+        if urls.is_empty() {
+            return Err(eyre!("No RPC endpoint configured"));
+        }
+        let sockets = urls.iter().filter(|url| socket::is_socket(url)).map(|url| (url.clone(), Mutex::new(None))).collect();
+        Ok(Rpc { client, headers, urls, sockets, timeout_secs })
+    }
+
+    pub fn urls(&self) -> &[String] {
+        &self.urls
+    }
+
+    /// POST `body` to an HTTP endpoint, or send it over the endpoint's socket.
+    async fn post(&self, url: &str, body: Value) -> Result<Value> {
+        let timeout = Duration::from_secs(self.timeout_secs);
+        let Some(slot) = self.sockets.get(url) else {
+            return Ok(self.client.post(url).json(&body).timeout(timeout).send().await?.json::<Value>().await?);
+        };
+        let mut connection = slot.lock().await;
+        let response = tokio::time::timeout(timeout, async {
+            if connection.is_none() {
+                *connection = Some(Socket::connect(url, &self.headers).await?);
+            }
+            connection.as_mut().expect("connected above").call(&body).await
+        })
+        .await
+        .unwrap_or_else(|_| Err(eyre!("no answer within {}s", self.timeout_secs)));
+        if response.is_err() {
+            *connection = None;
+        }
+        response
+    }
+
+    /// Subscribe to `newHeads` on the first WebSocket/IPC endpoint that accepts it. `None` if
+    /// there is none, in which case callers poll.
+    pub async fn new_heads(&self) -> Option<Heads> {
+        for url in self.urls.iter().filter(|url| self.sockets.contains_key(*url)) {
+            let subscription = tokio::time::timeout(Duration::from_secs(self.timeout_secs), Heads::subscribe(url, &self.headers)).await;
+            match subscription.unwrap_or_else(|_| Err(eyre!("no answer within {}s", self.timeout_secs))) {
+                Ok(heads) => return Some(heads),
+                Err(e) => println!("{}", redact(&format!("Cannot subscribe to newHeads on {}: {}", url, e))),
+            }
+        }
+        None
+    }
+
+    /// Send one request to `url`; the inner `Err` is the node's `error` object.
+    pub async fn request_at(&self, url: &str, method: &str, params: Value) -> Result<std::result::Result<Value, Value>> {
+        let response = self.post(url, json!({ "jsonrpc": "2.0", "method": method, "params": params, "id": 1 })).await?;
+        if let Some(error) = response.get("error") {
+            return Ok(Err(error.clone()));
+        }
+        response.get("result").cloned().map(Ok).ok_or_else(|| eyre!("RPC {} returned no result", method))
+    }
+
+    /// Send `calls` to `url` as one JSON-RPC batch. Returns `None` if the endpoint does not
+    /// answer with one response per request (batches unsupported or disabled).
+    pub async fn batch_at(&self, url: &str, calls: &[(&str, Value)]) -> Result<Option<Vec<std::result::Result<Value, Value>>>> {
+        let body: Vec<Value> = calls
+            .iter()
+            .enumerate()
+            .map(|(id, (method, params))| json!({ "jsonrpc": "2.0", "method": method, "params": params, "id": id }))
+            .collect();
+        let response = self.post(url, Value::Array(body)).await?;
+        let Some(responses) = response.as_array() else {
+            return Ok(None);
+        };
+        let mut results = vec![None; calls.len()];
+        for response in responses {
+            let Some(slot) = response.get("id").and_then(Value::as_u64).and_then(|id| results.get_mut(id as usize)) else {
+                return Ok(None);
+            };
+            *slot = match (response.get("error"), response.get("result")) {
+                (Some(error), _) => Some(Err(error.clone())),
+                (None, Some(result)) => Some(Ok(result.clone())),
+                (None, None) => None,
+            };
+        }
+        Ok(results.into_iter().collect())
+    }
+
+    /// Send a request to the first endpoint that answers. Only unreachable endpoints are
+    /// skipped; an `error` object from a node is returned as is.
+    pub async fn request(&self, method: &str, params: Value) -> Result<std::result::Result<Value, Value>> {
+        let mut failures = Vec::new();
+        for (attempt, url) in self.urls.iter().enumerate() {
+            if attempt > 0 {
+                tokio::time::sleep(backoff(attempt - 1)).await;
+            }
+            match self.request_at(url, method, params.clone()).await {
+                Ok(response) => return Ok(response),
+                Err(e) => {
+                    if self.urls.len() > 1 {
+                        println!("{}", redact(&format!("RPC {} unreachable for {}: {}", url, method, e)));
+                    }
+                    failures.push(format!("{}: {}", url, e));
+                }
+            }
+        }
+        Err(eyre!("RPC {} failed on every endpoint: {}", method, failures.join("; ")))
+    }
+
+    pub async fn call(&self, method: &str, params: Value) -> Result<Value> {
+        self.request(method, params).await?.map_err(|error| eyre!("RPC {} failed: {}", method, error))
+    }
+}
+
+pub fn quantity(method: &str, value: &Value) -> Result<U256> {
+    let text = value.as_str().ok_or_else(|| eyre!("RPC {} returned a non-string quantity: {}", method, value))?;
+    U256::from_str_radix(text.trim_start_matches("0x"), 16).map_err(|e| eyre!("RPC {} returned {:?}: {}", method, text, e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::read_http_request;
+    use std::sync::Arc;
+    use std::time::Instant;
+    use tokio::io::AsyncWriteExt;
+    use tokio::net::TcpListener;
+
+    /// Mock JSON-RPC server answering one connection per response, in order. Returns its URL
+    /// and the request bodies it received.
+    async fn spawn_mock_server(responses: Vec<Value>) -> (String, Arc<std::sync::Mutex<Vec<Value>>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let requests = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let seen = requests.clone();
+        tokio::spawn(async move {
+            for response in responses {
+                let Ok((mut socket, _)) = listener.accept().await else { return };
+                let (_, body) = read_http_request(&mut socket).await;
+                seen.lock().unwrap().push(serde_json::from_slice(&body).expect("request body must be valid JSON"));
+                let body = response.to_string();
+                let resp = format!(
+                    "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    body.len(),
+                    body
+                );
+                let _ = socket.write_all(resp.as_bytes()).await;
+                let _ = socket.shutdown().await;
+            }
+        });
+        (url, requests)
+    }
+
+    /// An address nothing listens on, so connections are refused at once.
+    async fn dead_url() -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        format!("http://{}", listener.local_addr().unwrap())
+    }
+
+    fn rpc(urls: Vec<String>) -> Rpc {
+        Rpc::new(Client::new(), HeaderMap::new(), urls, 5).unwrap()
+    }
+
+    #[test]
+    fn backoff_doubles_up_to_a_cap() {
+        assert_eq!(backoff(0), Duration::from_millis(250));
+        assert_eq!(backoff(1), Duration::from_millis(500));
+        assert_eq!(backoff(5), Duration::from_secs(8));
+        assert_eq!(backoff(9), Duration::from_secs(8));
+    }
+
+    #[tokio::test]
+    async fn request_fails_over_in_order_with_backoff() {
+        let (live, requests) = spawn_mock_server(vec![json!({"jsonrpc": "2.0", "id": 1, "result": "0x89"})]).await;
+        let (unused, unused_requests) = spawn_mock_server(vec![json!({"jsonrpc": "2.0", "id": 1, "result": "0x1"})]).await;
+        let rpc = rpc(vec![dead_url().await, dead_url().await, live, unused]);
+
+        let started = Instant::now();
+        let result = rpc.request("eth_chainId", json!([])).await.unwrap();
+        assert_eq!(result, Ok(json!("0x89")));
+        // Two unreachable endpoints: backoff(0) + backoff(1) before the third is tried.
+        assert!(started.elapsed() >= backoff(0) + backoff(1), "{:?}", started.elapsed());
+        assert_eq!(requests.lock().unwrap()[0]["method"], "eth_chainId");
+        assert!(unused_requests.lock().unwrap().is_empty(), "endpoints after the first answer are not tried");
+
+        let err = self::rpc(vec![dead_url().await]).request("eth_chainId", json!([])).await.unwrap_err();
+        assert!(err.to_string().contains("failed on every endpoint"), "{err}");
+    }
+
+    #[tokio::test]
+    async fn node_error_is_not_retried_on_the_next_endpoint() {
+        let error = json!({"code": -32000, "message": "nonce too low"});
+        let (first, _) = spawn_mock_server(vec![json!({"jsonrpc": "2.0", "id": 1, "error": error})]).await;
+        let (second, second_requests) = spawn_mock_server(vec![json!({"jsonrpc": "2.0", "id": 1, "result": "0xabc"})]).await;
+        let rpc = rpc(vec![first, second]);
+
+        assert_eq!(rpc.request("eth_sendRawTransaction", json!(["0x00"])).await.unwrap(), Err(error));
+        assert!(second_requests.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn batch_at_maps_responses_by_id() {
+        let calls = [("eth_sendRawTransaction", json!(["0x01"])), ("eth_sendRawTransaction", json!(["0x02"]))];
+        let error = json!({"code": -32000, "message": "already known"});
+        let (url, requests) = spawn_mock_server(vec![
+            // Out of order, as batch responses may be.
+            json!([{"jsonrpc": "2.0", "id": 1, "result": "0xbb"}, {"jsonrpc": "2.0", "id": 0, "error": error}]),
+            // Batches unsupported: a single error object.
+            json!({"jsonrpc": "2.0", "id": null, "error": {"code": -32600, "message": "batch requests disabled"}}),
+            // A response missing, or an id that matches no request.
+            json!([{"jsonrpc": "2.0", "id": 0, "result": "0xaa"}]),
+            json!([{"jsonrpc": "2.0", "id": 0, "result": "0xaa"}, {"jsonrpc": "2.0", "id": 7, "result": "0xbb"}]),
+        ])
+        .await;
+        let rpc = rpc(vec![url.clone()]);
+
+        assert_eq!(rpc.batch_at(&url, &calls).await.unwrap(), Some(vec![Err(error), Ok(json!("0xbb"))]));
+        let sent = requests.lock().unwrap()[0].clone();
+        assert_eq!(sent[0]["id"], 0);
+        assert_eq!(sent[1]["id"], 1);
+        assert_eq!(sent[1]["params"][0], "0x02");
+        for _ in 0..3 {
+            assert_eq!(rpc.batch_at(&url, &calls).await.unwrap(), None);
+        }
+    }
+}
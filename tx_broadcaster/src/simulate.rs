@@ -0,0 +1,241 @@
+//! `--simulate`: replay the signed transaction with `eth_call` and `eth_estimateGas` before
+//! sending it, and decode the revert reason (`Error(string)`, `Panic(uint256)` or a custom
+//! error from a user-supplied ABI) so that a transaction that would revert is never broadcast.
+
+use crate::preflight::SignedTx;
+use crate::rpc::{quantity, Rpc};
+use ethers::abi::{self, ethabi::AbiError, Abi, ParamType, Token};
+use ethers::types::{transaction::eip2718::TypedTransaction, U256};
+use eyre::{eyre, Result};
+use serde_json::{json, Value};
+use std::{fs, path::PathBuf};
+
+/// `Error(string)`
+const ERROR_SELECTOR: [u8; 4] = [0x08, 0xc3, 0x79, 0xa0];
+/// `Panic(uint256)`
+const PANIC_SELECTOR: [u8; 4] = [0x4e, 0x48, 0x7b, 0x71];
+
+/// Result of replaying the transaction against the node.
+#[derive(Debug)]
+pub struct Simulation {
+    /// Revert data returned by `eth_call`, if the call reverted.
+    pub revert: Option<Vec<u8>>,
+    /// Gas estimate, or the node's reason for not giving one.
+    pub gas_estimate: std::result::Result<U256, String>,
+}
+
+/// Decodes revert data, using custom errors from the ABIs given with `--abi`.
+pub struct RevertDecoder {
+    errors: Vec<AbiError>,
+}
+
+impl RevertDecoder {
+    pub fn new(abi_files: &[PathBuf]) -> Result<Self> {
+        let mut errors = Vec::new();
+        for path in abi_files {
+            let abi: Abi = serde_json::from_str(&fs::read_to_string(path)?)
+                .map_err(|e| eyre!("ABI {}: {}", path.display(), e))?;
+            errors.extend(abi.errors().cloned());
+        }
+        Ok(RevertDecoder { errors })
+    }
+
+    pub fn reason(&self, data: &[u8]) -> String {
+        let Some((selector, args)) = data.split_first_chunk::<4>() else {
+            return match data {
+                [] => "reverted without a reason".to_string(),
+                _ => format!("reverted with 0x{}", hex::encode(data)),
+            };
+        };
+        if *selector == ERROR_SELECTOR
+            && let Ok([Token::String(message)]) = abi::decode(&[ParamType::String], args).as_deref()
+        {
+            return format!("Error({:?})", message);
+        }
+        if *selector == PANIC_SELECTOR
+            && let Ok([Token::Uint(code)]) = abi::decode(&[ParamType::Uint(256)], args).as_deref()
+        {
+            return format!("Panic(0x{:02x}): {}", code, panic_meaning(*code));
+        }
+        for error in &self.errors {
+            if error.signature()[..4] == selector[..]
+                && let Ok(tokens) = error.decode(args)
+            {
+                let args: Vec<String> = tokens.iter().map(token).collect();
+                return format!("{}({})", error.name, args.join(", "));
+            }
+        }
+        format!("unknown custom error 0x{} (pass its ABI with --abi to decode)", hex::encode(selector))
+    }
+}
+
+fn token(t: &Token) -> String {
+    match t {
+        Token::Address(a) => format!("{:?}", a),
+        Token::Uint(v) | Token::Int(v) => v.to_string(),
+        Token::Bool(b) => b.to_string(),
+        Token::String(s) => format!("{:?}", s),
+        Token::Bytes(b) | Token::FixedBytes(b) => format!("0x{}", hex::encode(b)),
+        Token::Array(items) | Token::FixedArray(items) | Token::Tuple(items) => {
+            format!("[{}]", items.iter().map(token).collect::<Vec<_>>().join(", "))
+        }
+    }
+}
+
+/// Solidity panic codes.
+fn panic_meaning(code: U256) -> &'static str {
+    match code.low_u64() {
+        0x01 => "assertion failed",
+        0x11 => "arithmetic overflow or underflow",
+        0x12 => "division or modulo by zero",
+        0x21 => "invalid enum value",
+        0x22 => "corrupt storage byte array",
+        0x31 => "pop on an empty array",
+        0x32 => "array index out of bounds",
+        0x41 => "out of memory",
+        0x51 => "call to an uninitialized function",
+        _ => "unknown panic code",
+    }
+}
+
+/// The call object for `eth_estimateGas`, from the recovered sender. It has no `gas`: nodes
+/// treat that as the upper bound, so the estimate could never exceed the signed limit.
+fn call_object(tx: &SignedTx) -> Value {
+    let mut call = json!({
+        "from": tx.sender,
+        "value": tx.value,
+        "data": tx.request.data().cloned().unwrap_or_default(),
+    });
+    if let Some(to) = tx.request.to_addr() {
+        call["to"] = json!(to);
+    }
+    match &tx.request {
+        TypedTransaction::Eip1559(request) => {
+            call["maxFeePerGas"] = json!(tx.max_fee_per_gas);
+            call["maxPriorityFeePerGas"] = json!(request.max_priority_fee_per_gas.unwrap_or_default());
+        }
+        _ => call["gasPrice"] = json!(tx.max_fee_per_gas),
+    }
+    call
+}
+
+/// Revert data from an `eth_call` error, or `None` if the error is not a revert.
+fn revert_data(error: &Value) -> Option<Vec<u8>> {
+    let message = error.get("message").and_then(Value::as_str).unwrap_or_default();
+    let data = match error.get("data") {
+        Some(Value::String(data)) => Some(data.as_str()),
+        // Some nodes nest it as {"data": {"data": "0x..."}}.
+        Some(Value::Object(inner)) => inner.get("data").and_then(Value::as_str),
+        _ => None,
+    };
+    let is_revert = error.get("code").and_then(Value::as_i64) == Some(3) || message.contains("revert");
+    match data {
+        Some(data) if is_revert => Some(hex::decode(data.trim_start_matches("0x")).unwrap_or_default()),
+        None if is_revert => Some(Vec::new()),
+        _ => None,
+    }
+}
+
+pub async fn simulate(rpc: &Rpc, tx: &SignedTx) -> Result<Simulation> {
+    let call = call_object(tx);
+    let mut with_gas = call.clone();
+    with_gas["gas"] = json!(tx.gas_limit);
+
+    let revert = match rpc.request("eth_call", json!([with_gas, "pending"])).await? {
+        Ok(_) => None,
+        Err(error) => Some(revert_data(&error).ok_or_else(|| eyre!("RPC eth_call failed: {}", error))?),
+    };
+    let gas_estimate = match rpc.request("eth_estimateGas", json!([call])).await? {
+        Ok(value) => quantity("eth_estimateGas", &value).map_err(|e| e.to_string()),
+        Err(error) => Err(error.get("message").and_then(Value::as_str).map_or_else(|| error.to_string(), str::to_string)),
+    };
+    Ok(Simulation { revert, gas_estimate })
+}
+
+/// Print the simulation summary; returns whether the transaction executed without reverting.
+pub fn report(tx: &SignedTx, simulation: &Simulation, decoder: &RevertDecoder) -> bool {
+    println!("========================= SIMULATION =========================");
+    match &simulation.revert {
+        None => println!("[GO]    eth_call: executes successfully at pending"),
+        Some(data) => println!("[NO-GO] eth_call: {}", decoder.reason(data)),
+    }
+    println!("{}", gas_line(tx, &simulation.gas_estimate));
+    println!("==============================================================");
+    simulation.revert.is_none()
+}
+
+/// The summary line comparing the gas estimate with the signed gas limit.
+fn gas_line(tx: &SignedTx, gas_estimate: &std::result::Result<U256, String>) -> String {
+    match gas_estimate {
+        Ok(estimate) if *estimate > tx.gas_limit => format!(
+            "[WARN]  gas: signed gas limit {} is below the estimate {}; the tx may run out of gas",
+            tx.gas_limit, estimate
+        ),
+        Ok(estimate) => format!("[GO]    gas: estimate {}, signed gas limit {}", estimate, tx.gas_limit),
+        Err(reason) => format!("[WARN]  gas: no estimate ({})", reason),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ethers::types::{Address, TransactionRequest, H256};
+
+    fn decoder() -> RevertDecoder {
+        let abi = r#"[{"type":"error","name":"InsufficientBalance","inputs":[
+            {"name":"available","type":"uint256"},{"name":"required","type":"uint256"}]}]"#;
+        let path = std::env::temp_dir().join(format!("revert_abi_{}.json", std::process::id()));
+        fs::write(&path, abi).unwrap();
+        let decoder = RevertDecoder::new(std::slice::from_ref(&path)).unwrap();
+        let _ = fs::remove_file(path);
+        decoder
+    }
+
+    fn with_selector(selector: &[u8], tokens: &[Token]) -> Vec<u8> {
+        [selector, &abi::encode(tokens)].concat()
+    }
+
+    #[test]
+    fn decodes_error_panic_and_custom_reverts() {
+        let decoder = decoder();
+        let error = with_selector(&ERROR_SELECTOR, &[Token::String("ERC20: transfer amount exceeds balance".into())]);
+        assert_eq!(decoder.reason(&error), r#"Error("ERC20: transfer amount exceeds balance")"#);
+
+        let panic = with_selector(&PANIC_SELECTOR, &[Token::Uint(0x11.into())]);
+        assert_eq!(decoder.reason(&panic), "Panic(0x11): arithmetic overflow or underflow");
+
+        let selector = &ethers::utils::id("InsufficientBalance(uint256,uint256)");
+        let custom = with_selector(selector, &[Token::Uint(5.into()), Token::Uint(7.into())]);
+        assert_eq!(decoder.reason(&custom), "InsufficientBalance(5, 7)");
+        assert!(RevertDecoder::new(&[]).unwrap().reason(&custom).starts_with("unknown custom error 0x"));
+
+        assert_eq!(decoder.reason(&[]), "reverted without a reason");
+    }
+
+    #[test]
+    fn recognises_revert_errors() {
+        let geth = json!({"code": 3, "message": "execution reverted", "data": "0x4e487b710000"});
+        assert_eq!(revert_data(&geth), Some(vec![0x4e, 0x48, 0x7b, 0x71, 0, 0]));
+        assert_eq!(revert_data(&json!({"code": -32000, "message": "execution reverted"})), Some(vec![]));
+        assert_eq!(revert_data(&json!({"code": -32000, "message": "header not found"})), None);
+    }
+
+    #[test]
+    fn warns_when_the_estimate_exceeds_the_gas_limit() {
+        let tx = SignedTx {
+            sender: Address::repeat_byte(0xaa),
+            hash: H256::zero(),
+            chain_id: Some(1),
+            nonce: U256::zero(),
+            gas_limit: 21_000u64.into(),
+            max_fee_per_gas: 1u64.into(),
+            value: U256::zero(),
+            request: TypedTransaction::Legacy(TransactionRequest::new().to(Address::repeat_byte(0x11))),
+        };
+        assert!(call_object(&tx).get("gas").is_none(), "eth_estimateGas must not be capped by the signed limit");
+
+        let line = gas_line(&tx, &Ok(46_000u64.into()));
+        assert!(line.starts_with("[WARN]") && line.contains("below the estimate 46000"), "{line}");
+        assert!(gas_line(&tx, &Ok(21_000u64.into())).starts_with("[GO]"));
+    }
+}
@@ -0,0 +1,186 @@
+//! JSON-RPC over persistent sockets: WebSocket (`ws://`, `wss://`) and Unix-socket IPC
+//! (`ipc:///path/geth.ipc`, or just the path). Also provides the `newHeads` subscription that
+//! `--wait` follows instead of polling.
+
+use crate::rpc::quantity;
+use eyre::{eyre, Result};
+use futures::{SinkExt, StreamExt};
+use reqwest::header::HeaderMap;
+use serde_json::{json, Value};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpStream, UnixStream};
+use tokio_tungstenite::tungstenite::{client::IntoClientRequest, Message};
+use tokio_tungstenite::{MaybeTlsStream, WebSocketStream};
+
+/// Whether `url` is a WebSocket or IPC endpoint rather than HTTP.
+pub fn is_socket(url: &str) -> bool {
+    is_ws(url) || ipc_path(url).is_some()
+}
+
+pub fn is_ws(url: &str) -> bool {
+    url.starts_with("ws://") || url.starts_with("wss://")
+}
+
+/// The socket path of an IPC endpoint: `ipc:///path/geth.ipc`, or a bare absolute path or `.ipc` file.
+fn ipc_path(url: &str) -> Option<&str> {
+    url.strip_prefix("ipc://").or_else(|| (!url.contains("://") && (url.starts_with('/') || url.ends_with(".ipc"))).then_some(url))
+}
+
+pub enum Socket {
+    Ws(Box<WebSocketStream<MaybeTlsStream<TcpStream>>>),
+    /// IPC has no message framing; `buf` holds bytes read past the last complete JSON value.
+    Ipc { stream: UnixStream, buf: Vec<u8> },
+}
+
+impl Socket {
+    /// Connect to `url`; `headers` are sent with the WebSocket handshake.
+    pub async fn connect(url: &str, headers: &HeaderMap) -> Result<Self> {
+        if let Some(path) = ipc_path(url) {
+            let stream = UnixStream::connect(path).await.map_err(|e| eyre!("cannot connect to {}: {}", path, e))?;
+            return Ok(Socket::Ipc { stream, buf: Vec::new() });
+        }
+        let mut request = url.into_client_request()?;
+        request.headers_mut().extend(headers.clone());
+        let (ws, _) = tokio_tungstenite::connect_async(request).await?;
+        Ok(Socket::Ws(Box::new(ws)))
+    }
+
+    async fn send(&mut self, message: &Value) -> Result<()> {
+        match self {
+            Socket::Ws(ws) => ws.send(Message::Text(message.to_string())).await?,
+            Socket::Ipc { stream, .. } => stream.write_all(message.to_string().as_bytes()).await?,
+        }
+        Ok(())
+    }
+
+    /// The next JSON message from the endpoint.
+    async fn recv(&mut self) -> Result<Value> {
+        match self {
+            Socket::Ws(ws) => loop {
+                match ws.next().await.ok_or_else(|| eyre!("connection closed"))?? {
+                    Message::Text(text) => return Ok(serde_json::from_str(&text)?),
+                    Message::Binary(bytes) => return Ok(serde_json::from_slice(&bytes)?),
+                    Message::Close(_) => return Err(eyre!("connection closed")),
+                    // Pings are answered by tungstenite itself.
+                    _ => {}
+                }
+            },
+            Socket::Ipc { stream, buf } => loop {
+                let mut values = serde_json::Deserializer::from_slice(buf).into_iter::<Value>();
+                match values.next() {
+                    Some(Ok(value)) => {
+                        let used = values.byte_offset();
+                        buf.drain(..used);
+                        return Ok(value);
+                    }
+                    Some(Err(e)) if !e.is_eof() => return Err(e.into()),
+                    _ => {}
+                }
+                let mut chunk = [0u8; 8192];
+                let n = stream.read(&mut chunk).await?;
+                if n == 0 {
+                    return Err(eyre!("connection closed"));
+                }
+                buf.extend_from_slice(&chunk[..n]);
+            },
+        }
+    }
+
+    /// Send a request or batch and return the answer, skipping subscription notifications.
+    pub async fn call(&mut self, body: &Value) -> Result<Value> {
+        self.send(body).await?;
+        loop {
+            let message = self.recv().await?;
+            if message.get("method").is_none() {
+                return Ok(message);
+            }
+        }
+    }
+}
+
+/// A `newHeads` subscription, on its own connection so that notifications never interleave
+/// with request traffic.
+pub struct Heads {
+    socket: Socket,
+}
+
+impl Heads {
+    pub async fn subscribe(url: &str, headers: &HeaderMap) -> Result<Self> {
+        let mut socket = Socket::connect(url, headers).await?;
+        let response = socket.call(&json!({ "jsonrpc": "2.0", "method": "eth_subscribe", "params": ["newHeads"], "id": 1 })).await?;
+        if let Some(error) = response.get("error") {
+            return Err(eyre!("eth_subscribe failed: {}", error));
+        }
+        Ok(Heads { socket })
+    }
+
+    /// Block number of the next head.
+    pub async fn next(&mut self) -> Result<u64> {
+        loop {
+            let message = self.socket.recv().await?;
+            if message.get("method").and_then(Value::as_str) == Some("eth_subscription")
+                && let Some(number) = message.pointer("/params/result/number")
+            {
+                return Ok(quantity("newHeads", number)?.as_u64());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::net::UnixListener;
+
+    /// IPC stand-in: answers `eth_chainId`, and after `eth_subscribe` pushes two heads in a
+    /// single write, the way nodes coalesce messages on a busy socket.
+    async fn spawn_mock_ipc() -> String {
+        let path = std::env::temp_dir().join(format!("tx_broadcaster_{}.ipc", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let listener = UnixListener::bind(&path).unwrap();
+        tokio::spawn(async move {
+            loop {
+                let Ok((stream, _)) = listener.accept().await else {
+                    return;
+                };
+                tokio::spawn(async move {
+                    let mut socket = Socket::Ipc { stream, buf: Vec::new() };
+                    while let Ok(request) = socket.recv().await {
+                        let Socket::Ipc { stream, .. } = &mut socket else {
+                            unreachable!()
+                        };
+                        let id = request["id"].clone();
+                        let reply = match request["method"].as_str() {
+                            Some("eth_chainId") => json!({ "jsonrpc": "2.0", "id": id, "result": "0x89" }).to_string(),
+                            Some("eth_subscribe") => {
+                                let head = |n: &str| {
+                                    json!({ "jsonrpc": "2.0", "method": "eth_subscription", "params": { "subscription": "0x1", "result": { "number": n } } })
+                                };
+                                format!("{}\n{}{}", json!({ "jsonrpc": "2.0", "id": id, "result": "0x1" }), head("0x10"), head("0x11"))
+                            }
+                            _ => json!({ "jsonrpc": "2.0", "id": id, "error": { "code": -32601, "message": "method not found" } }).to_string(),
+                        };
+                        let _ = stream.write_all(reply.as_bytes()).await;
+                    }
+                });
+            }
+        });
+        path.display().to_string()
+    }
+
+    #[tokio::test]
+    async fn calls_and_follows_heads_over_ipc() {
+        let path = spawn_mock_ipc().await;
+        assert!(is_socket(&path) && is_socket(&format!("ipc://{}", path)) && is_socket("wss://node.example"));
+        assert!(!is_socket("https://node.example"));
+
+        let mut socket = Socket::connect(&format!("ipc://{}", path), &HeaderMap::new()).await.unwrap();
+        let response = socket.call(&json!({ "jsonrpc": "2.0", "method": "eth_chainId", "params": [], "id": 1 })).await.unwrap();
+        assert_eq!(response["result"], "0x89");
+
+        let mut heads = Heads::subscribe(&path, &HeaderMap::new()).await.unwrap();
+        assert_eq!(heads.next().await.unwrap(), 0x10);
+        assert_eq!(heads.next().await.unwrap(), 0x11);
+        let _ = std::fs::remove_file(path);
+    }
+}
@@ -0,0 +1,166 @@
+//! Submitting the signed transaction to the configured endpoints.
+//!
+//! Every answer is checked against the locally computed tx hash. "already known" and a
+//! "nonce too low" for a transaction the node already has under the same hash count as
+//! accepted, so a rebroadcast or a fan-out that races other nodes does not look like a failure.
+
+use crate::journal::Journal;
+use crate::node_error::{BroadcastError, ErrorKind};
+use crate::preflight::SignedTx;
+use crate::rpc::{Rpc, Strategy};
+use crate::transport::redact;
+use ethers::types::H256;
+use eyre::{eyre, Result};
+use serde_json::json;
+
+/// What one endpoint did with the transaction.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Submission {
+    Accepted(String),
+    Rejected(BroadcastError),
+    Unreachable(String),
+}
+
+/// The node must report the hash computed locally; anything else means a different tx was sent.
+pub fn check_node_hash(local: H256, node_hash: &str) -> Result<()> {
+    if !node_hash.eq_ignore_ascii_case(&format!("{:?}", local)) {
+        return Err(eyre!("Node returned tx hash {}, but the transaction hashes to {:?}", node_hash, local));
+    }
+    Ok(())
+}
+
+async fn submit_to(rpc: &Rpc, journal: &Journal, url: &str, tx: &SignedTx, raw: &[u8]) -> Submission {
+    match crate::broadcast_transaction(rpc, url, raw.to_vec()).await {
+        Ok(node_hash) => settle(rpc, journal, url, tx, Ok(node_hash)).await,
+        Err(e) => match e.downcast::<BroadcastError>() {
+            Ok(error) => settle(rpc, journal, url, tx, Err(error)).await,
+            Err(e) => {
+                journal.record(url, tx, Err(format!("unreachable: {}", e)));
+                Submission::Unreachable(e.to_string())
+            }
+        },
+    }
+}
+
+/// Journal and interpret `url`'s answer to `eth_sendRawTransaction` for `tx`.
+pub async fn settle(
+    rpc: &Rpc,
+    journal: &Journal,
+    url: &str,
+    tx: &SignedTx,
+    response: std::result::Result<String, BroadcastError>,
+) -> Submission {
+    journal.record(url, tx, response.as_deref().map_err(|e| format!("[{}] {}", e.kind.label(), e.message)));
+    let local = tx.hash;
+    let error = match response {
+        Ok(node_hash) => {
+            return match check_node_hash(local, &node_hash) {
+                Ok(()) => Submission::Accepted("accepted".to_string()),
+                Err(e) => Submission::Rejected(BroadcastError { kind: ErrorKind::Other, code: None, message: e.to_string() }),
+            };
+        }
+        Err(error) => error,
+    };
+    match error.kind {
+        ErrorKind::AlreadyKnown => Submission::Accepted("already known".to_string()),
+        ErrorKind::NonceTooLow
+            if rpc
+                .request_at(url, "eth_getTransactionByHash", json!([local]))
+                .await
+                .is_ok_and(|known| known.is_ok_and(|tx| !tx.is_null())) =>
+        {
+            Submission::Accepted("nonce too low, node already has this tx".to_string())
+        }
+        _ => Submission::Rejected(error),
+    }
+}
+
+/// Submit `raw` with the given strategy; succeeds if at least one endpoint accepted it.
+/// Otherwise the error is a [`BroadcastError`] classified from the first rejection.
+pub async fn broadcast(rpc: &Rpc, journal: &Journal, tx: &SignedTx, raw: &[u8], strategy: Strategy) -> Result<H256> {
+    let results: Vec<(&String, Submission)> = match strategy {
+        Strategy::Failover => {
+            let mut results = Vec::new();
+            for (attempt, url) in rpc.urls().iter().enumerate() {
+                if attempt > 0 {
+                    tokio::time::sleep(crate::rpc::backoff(attempt - 1)).await;
+                }
+                let submission = submit_to(rpc, journal, url, tx, raw).await;
+                let accepted = matches!(submission, Submission::Accepted(_));
+                results.push((url, submission));
+                if accepted {
+                    break;
+                }
+            }
+            results
+        }
+        Strategy::FanOut => {
+            let submissions = futures::future::join_all(rpc.urls().iter().map(|url| submit_to(rpc, journal, url, tx, raw))).await;
+            rpc.urls().iter().zip(submissions).collect()
+        }
+    };
+    reconcile(tx.hash, &results)
+}
+
+fn reconcile(local: H256, results: &[(&String, Submission)]) -> Result<H256> {
+    if results.len() > 1 || results.iter().any(|(_, s)| !matches!(s, Submission::Accepted(_))) {
+        for (url, submission) in results {
+            let line = match submission {
+                Submission::Accepted(note) => format!("  [OK]          {}: {}", url, note),
+                Submission::Rejected(e) => format!("  [REJECTED]    {}: [{}] {}", url, e.kind.label(), e.message),
+                Submission::Unreachable(e) => format!("  [UNREACHABLE] {}: {}", url, e),
+            };
+            println!("{}", redact(&line));
+        }
+    }
+    if results.iter().any(|(_, s)| matches!(s, Submission::Accepted(_))) {
+        return Ok(local);
+    }
+    let first_rejection = results.iter().find_map(|(_, s)| match s {
+        Submission::Rejected(e) => Some(e.clone()),
+        _ => None,
+    });
+    let mut error = first_rejection.unwrap_or(BroadcastError { kind: ErrorKind::Unreachable, code: None, message: String::new() });
+    if results.len() > 1 || error.kind == ErrorKind::Unreachable {
+        let details: Vec<String> = results
+            .iter()
+            .map(|(url, s)| match s {
+                Submission::Rejected(e) => format!("{}: {}", url, e.message),
+                Submission::Unreachable(e) | Submission::Accepted(e) => format!("{}: {}", url, e),
+            })
+            .collect();
+        error.message = format!("no endpoint accepted the transaction ({})", details.join("; "));
+    }
+    Err(error.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn node_hash_must_match_local_hash() {
+        let local = H256::repeat_byte(0xab);
+        let expected = format!("{:?}", local);
+        assert!(check_node_hash(local, &expected).is_ok());
+        assert!(check_node_hash(local, &expected.to_uppercase().replace("0X", "0x")).is_ok());
+        assert!(check_node_hash(local, "0xaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa").is_err());
+    }
+
+    #[test]
+    fn reconciles_fan_out_results() {
+        let local = H256::repeat_byte(0xab);
+        let (a, b) = ("https://a".to_string(), "https://b".to_string());
+        let one_accepted = [(&a, Submission::Unreachable("timeout".into())), (&b, Submission::Accepted("already known".into()))];
+        assert_eq!(reconcile(local, &one_accepted).unwrap(), local);
+
+        let rejected = BroadcastError { kind: ErrorKind::InsufficientFunds, code: Some(-32000), message: "insufficient funds".into() };
+        let none = [(&a, Submission::Unreachable("timeout".into())), (&b, Submission::Rejected(rejected))];
+        let err = reconcile(local, &none).unwrap_err().downcast::<BroadcastError>().unwrap();
+        assert_eq!(err.kind, ErrorKind::InsufficientFunds);
+        assert!(err.message.contains("https://a: timeout") && err.message.contains("https://b: insufficient funds"), "{err}");
+
+        let down = [(&a, Submission::Unreachable("timeout".into()))];
+        assert_eq!(reconcile(local, &down).unwrap_err().downcast::<BroadcastError>().unwrap().kind, ErrorKind::Unreachable);
+    }
+}
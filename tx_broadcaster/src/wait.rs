@@ -0,0 +1,323 @@
+//! `--wait`: follow a broadcast transaction until it has the requested number of
+//! confirmations. Checks `eth_getTransactionReceipt` on every new head (from a `newHeads`
+//! subscription on a WebSocket/IPC endpoint, else by polling), reports reorgs that move or
+//! remove the transaction, and rebroadcasts it if it disappears from the node's mempool.
+
+use crate::journal::Journal;
+use crate::preflight::SignedTx;
+use crate::rpc::{quantity, Rpc, Strategy};
+use crate::submit;
+use crate::transport::redact;
+use ethers::types::{H256, U256, U64};
+use ethers::utils::format_units;
+use eyre::Result;
+use serde::Deserialize;
+use serde_json::json;
+use std::process::ExitCode;
+use std::time::{Duration, Instant};
+
+#[derive(clap::Args, Debug)]
+pub struct WaitArgs {
+    /// After broadcasting, wait for the receipt and follow reorgs until confirmed
+    #[arg(long)]
+    pub wait: bool,
+
+    /// Confirmations to wait for with --wait
+    #[arg(long, default_value_t = 1, requires = "wait", value_parser = clap::value_parser!(u64).range(1..))]
+    confirmations: u64,
+
+    /// Seconds between receipt polls with --wait, when no ws/IPC endpoint offers newHeads
+    #[arg(long, default_value_t = 4, requires = "wait")]
+    poll_interval: u64,
+
+    /// Give up waiting after this many seconds
+    #[arg(long, default_value_t = 1800, requires = "wait")]
+    wait_timeout: u64,
+}
+
+/// How the wait ended; each maps to its own exit status, clear of clap's usage error (2) and
+/// the node rejection codes (10-19).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Outcome {
+    Success,
+    Reverted,
+    Dropped,
+    TimedOut,
+}
+
+impl Outcome {
+    pub fn exit_code(self) -> ExitCode {
+        match self {
+            Outcome::Success => ExitCode::SUCCESS,
+            Outcome::Reverted => ExitCode::from(20),
+            Outcome::Dropped => ExitCode::from(21),
+            Outcome::TimedOut => ExitCode::from(22),
+        }
+    }
+}
+
+/// The receipt fields we report; parsed leniently so that any node's receipt works.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Receipt {
+    pub block_hash: H256,
+    pub block_number: U64,
+    pub gas_used: U256,
+    pub effective_gas_price: Option<U256>,
+    /// 1 = success, 0 = reverted (absent before Byzantium).
+    pub status: Option<U64>,
+}
+
+/// Tracks the block that includes the transaction across polls.
+#[derive(Default)]
+struct Tracker {
+    included: Option<(u64, H256)>,
+    confirmations: u64,
+}
+
+impl Tracker {
+    /// Record a receipt seen at chain head `head`; returns the outcome once it has `needed` confirmations.
+    fn on_receipt(&mut self, receipt: &Receipt, head: u64, needed: u64) -> Option<Outcome> {
+        let block = (receipt.block_number.as_u64(), receipt.block_hash);
+        match self.included {
+            Some(previous) if previous != block => {
+                println!("Reorg: tx moved from block {} ({:?}) to block {} ({:?})", previous.0, previous.1, block.0, block.1);
+                report(receipt);
+            }
+            Some(_) => {}
+            None => report(receipt),
+        }
+        self.included = Some(block);
+
+        let confirmations = (head + 1).saturating_sub(block.0);
+        if confirmations != self.confirmations {
+            println!("Confirmations: {}/{}", confirmations.min(needed), needed);
+            self.confirmations = confirmations;
+        }
+        (confirmations >= needed).then(|| match receipt.status.map(|s| s.as_u64()) {
+            Some(0) => Outcome::Reverted,
+            _ => Outcome::Success,
+        })
+    }
+
+    /// The node has no receipt: if we had one, the including block was reorged out.
+    fn on_missing(&mut self) {
+        if let Some((number, hash)) = self.included.take() {
+            println!("Reorg: block {} ({:?}) no longer includes the tx; waiting for re-inclusion", number, hash);
+            self.confirmations = 0;
+        }
+    }
+}
+
+fn report(receipt: &Receipt) {
+    let status = match receipt.status.map(|s| s.as_u64()) {
+        Some(1) => "success",
+        Some(0) => "REVERTED",
+        _ => "unknown (pre-Byzantium)",
+    };
+    let gwei = |v: U256| format_units(v, "gwei").unwrap_or_else(|_| v.to_string());
+    println!("Included in block {} ({:?})", receipt.block_number, receipt.block_hash);
+    println!("  Status:              {}", status);
+    println!("  Gas used:            {}", receipt.gas_used);
+    match receipt.effective_gas_price {
+        Some(price) => println!("  Effective gas price: {} gwei", gwei(price)),
+        None => println!("  Effective gas price: (not reported)"),
+    }
+}
+
+/// Poll until the transaction has `--confirmations` confirmations, is dropped, or `--wait-timeout` passes.
+pub async fn wait(rpc: &Rpc, journal: &Journal, strategy: Strategy, tx: &SignedTx, raw_tx: &[u8], args: &WaitArgs) -> Result<Outcome> {
+    let (confirmations, poll, timeout) =
+        (args.confirmations, Duration::from_secs(args.poll_interval), Duration::from_secs(args.wait_timeout));
+    let started = Instant::now();
+    let mut tracker = Tracker::default();
+    let mut heads = rpc.new_heads().await;
+    println!(
+        "Waiting for {} confirmation(s) of {:?} ({})...",
+        confirmations,
+        tx.hash,
+        if heads.is_some() { "following newHeads".to_string() } else { format!("polling every {}s", poll.as_secs()) }
+    );
+
+    loop {
+        let receipt = rpc.call("eth_getTransactionReceipt", json!([tx.hash])).await?;
+        if receipt.is_null() {
+            tracker.on_missing();
+            if rpc.call("eth_getTransactionByHash", json!([tx.hash])).await?.is_null() {
+                let nonce = quantity(
+                    "eth_getTransactionCount",
+                    &rpc.call("eth_getTransactionCount", json!([tx.sender, "latest"])).await?,
+                )?;
+                if nonce > tx.nonce {
+                    // The tx may have been mined between the receipt and nonce reads; only a
+                    // receipt that is still missing means another tx took the nonce.
+                    if rpc.call("eth_getTransactionReceipt", json!([tx.hash])).await?.is_null() {
+                        println!("Nonce {} was used by another transaction; this tx was dropped", tx.nonce);
+                        return Ok(Outcome::Dropped);
+                    }
+                    continue;
+                }
+                println!("Tx is not in the node's mempool; rebroadcasting");
+                if let Err(e) = submit::broadcast(rpc, journal, tx, raw_tx, strategy).await {
+                    println!("Rebroadcast failed: {}", redact(&e.to_string()));
+                }
+            }
+        } else {
+            let receipt: Receipt = serde_json::from_value(receipt)?;
+            let head = quantity("eth_blockNumber", &rpc.call("eth_blockNumber", json!([])).await?)?;
+            if let Some(outcome) = tracker.on_receipt(&receipt, head.as_u64(), confirmations) {
+                return Ok(outcome);
+            }
+        }
+
+        if started.elapsed() >= timeout {
+            println!("Gave up after {}s without {} confirmation(s)", timeout.as_secs(), confirmations);
+            return Ok(Outcome::TimedOut);
+        }
+        match &mut heads {
+            Some(subscription) => match tokio::time::timeout(timeout.saturating_sub(started.elapsed()), subscription.next()).await {
+                Ok(Ok(_)) | Err(_) => {}
+                Ok(Err(e)) => {
+                    println!("newHeads subscription lost ({}); polling every {}s", redact(&e.to_string()), poll.as_secs());
+                    heads = None;
+                }
+            },
+            None => tokio::time::sleep(poll).await,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::preflight;
+    use crate::tests::{node_error, signed_tx, spawn_mock_node, test_journal};
+    use clap::Parser;
+    use ethers::signers::LocalWallet;
+    use reqwest::{header::HeaderMap, Client};
+    use std::sync::{Arc, Mutex};
+
+    fn receipt(block: u64, hash: u8, status: u64) -> Receipt {
+        serde_json::from_value(json!({
+            "blockHash": H256::repeat_byte(hash),
+            "blockNumber": format!("{:#x}", block),
+            "gasUsed": "0x5208",
+            "effectiveGasPrice": "0x3b9aca00",
+            "status": format!("{:#x}", status),
+            "logs": [],
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn follows_reorgs_until_confirmed() {
+        let mut tracker = Tracker::default();
+        assert_eq!(tracker.on_receipt(&receipt(100, 0xaa, 1), 100, 3), None);
+        // Block 100 is reorged out, then the tx lands in a different block 101.
+        tracker.on_missing();
+        assert_eq!(tracker.included, None);
+        assert_eq!(tracker.on_receipt(&receipt(101, 0xbb, 1), 102, 3), None);
+        assert_eq!(tracker.on_receipt(&receipt(101, 0xbb, 1), 103, 3), Some(Outcome::Success));
+
+        let mut tracker = Tracker::default();
+        assert_eq!(tracker.on_receipt(&receipt(7, 0xcc, 0), 7, 1), Some(Outcome::Reverted));
+    }
+
+    #[derive(Parser)]
+    struct Cli {
+        #[command(flatten)]
+        wait: WaitArgs,
+    }
+
+    fn signed(nonce: u64) -> (SignedTx, Vec<u8>) {
+        signed_tx(&LocalWallet::new(&mut ethers::core::rand::thread_rng()), 1, nonce, 1)
+    }
+
+    fn wait_args() -> WaitArgs {
+        Cli::try_parse_from(["wait", "--wait", "--poll-interval", "0", "--wait-timeout", "5"]).unwrap().wait
+    }
+
+    #[tokio::test]
+    async fn reports_a_drop_when_the_nonce_moves_past_the_tx() {
+        let (tx, raw) = signed(5);
+        let (url, methods) = spawn_mock_node(false, |method, _| match method {
+            "eth_getTransactionReceipt" | "eth_getTransactionByHash" => Ok(serde_json::Value::Null),
+            "eth_getTransactionCount" => Ok(json!("0x6")),
+            _ => node_error("unexpected method"),
+        })
+        .await;
+        let rpc = Rpc::new(Client::new(), HeaderMap::new(), vec![url], 5).unwrap();
+        let (journal, path) = test_journal("wait_dropped");
+
+        assert_eq!(wait(&rpc, &journal, Strategy::Failover, &tx, &raw, &wait_args()).await.unwrap(), Outcome::Dropped);
+        assert!(!methods.lock().unwrap().iter().any(|m| m == "eth_sendRawTransaction"), "a dropped tx is not rebroadcast");
+        let _ = std::fs::remove_file(path);
+    }
+
+    #[tokio::test]
+    async fn a_tx_mined_while_reading_the_nonce_is_not_dropped() {
+        let (tx, raw) = signed(5);
+        // The receipt appears right after the nonce read sees the tx's nonce used.
+        let mined = Arc::new(Mutex::new(false));
+        let (url, methods) = spawn_mock_node(false, move |method, _| {
+            let mut mined = mined.lock().unwrap();
+            match method {
+                "eth_getTransactionReceipt" if *mined => Ok(json!({
+                    "blockHash": H256::repeat_byte(0xaa),
+                    "blockNumber": "0x10",
+                    "gasUsed": "0x5208",
+                    "status": "0x1",
+                })),
+                "eth_getTransactionReceipt" | "eth_getTransactionByHash" => Ok(serde_json::Value::Null),
+                "eth_getTransactionCount" => {
+                    *mined = true;
+                    Ok(json!("0x6"))
+                }
+                "eth_blockNumber" => Ok(json!("0x10")),
+                _ => node_error("unexpected method"),
+            }
+        })
+        .await;
+        let rpc = Rpc::new(Client::new(), HeaderMap::new(), vec![url], 5).unwrap();
+        let (journal, path) = test_journal("wait_mined_late");
+
+        assert_eq!(wait(&rpc, &journal, Strategy::Failover, &tx, &raw, &wait_args()).await.unwrap(), Outcome::Success);
+        assert!(!methods.lock().unwrap().iter().any(|m| m == "eth_sendRawTransaction"));
+        let _ = std::fs::remove_file(path);
+    }
+
+    #[tokio::test]
+    async fn rebroadcasts_a_tx_missing_from_the_mempool() {
+        let (tx, raw) = signed(5);
+        let hash = format!("{:?}", tx.hash);
+        // The node lost the tx; it is mined in block 0x10 once it is sent again.
+        let resent = Arc::new(Mutex::new(false));
+        let (url, methods) = spawn_mock_node(false, move |method, params| {
+            let mut resent = resent.lock().unwrap();
+            match method {
+                "eth_getTransactionReceipt" if *resent => Ok(json!({
+                    "blockHash": H256::repeat_byte(0xaa),
+                    "blockNumber": "0x10",
+                    "gasUsed": "0x5208",
+                    "status": "0x1",
+                })),
+                "eth_getTransactionReceipt" | "eth_getTransactionByHash" => Ok(serde_json::Value::Null),
+                "eth_getTransactionCount" => Ok(json!("0x5")),
+                "eth_sendRawTransaction" => {
+                    *resent = true;
+                    Ok(json!(preflight::decode(&hex::decode(params[0].as_str().unwrap().trim_start_matches("0x")).unwrap()).unwrap().hash))
+                }
+                "eth_blockNumber" => Ok(json!("0x10")),
+                _ => node_error("unexpected method"),
+            }
+        })
+        .await;
+        let rpc = Rpc::new(Client::new(), HeaderMap::new(), vec![url], 5).unwrap();
+        let (journal, path) = test_journal("wait_rebroadcast");
+
+        assert_eq!(wait(&rpc, &journal, Strategy::Failover, &tx, &raw, &wait_args()).await.unwrap(), Outcome::Success);
+        assert_eq!(methods.lock().unwrap().iter().filter(|m| *m == "eth_sendRawTransaction").count(), 1);
+        assert!(std::fs::read_to_string(&path).unwrap().contains(&hash), "the rebroadcast is journaled");
+        let _ = std::fs::remove_file(path);
+    }
+}
@@ -114,6 +114,7 @@ fn build_legacy_preimage(
 }
 
 /// Build EIP-1559 (type-2) signing payload bytes = 0x02 || RLP([chainId, nonce, maxPriorityFeePerGas, maxFeePerGas, gasLimit, to, value, data, accessList])
+#[allow(clippy::too_many_arguments)]
 fn build_eip1559_signing_payload(
     chain_id: U256,
     nonce: U256,
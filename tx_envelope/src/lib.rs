@@ -0,0 +1,146 @@
+//! Signed-output envelope shared by the toolkit binaries.
+//!
+//! `tx_signer --envelope` writes the raw transaction together with its locally computed hash,
+//! sender, nonce, chain ID and the hash of the unsigned payload it was signed from, as JSON or
+//! CBOR. `tx_broadcaster` and `tx_inspector` accept the envelope wherever they accept bare
+//! signed hex, so a transaction can be followed from intent to broadcast by its hashes.
+
+use ethers::types::{Address, Bytes, H256};
+use ethers::utils::keccak256;
+use eyre::{eyre, Result};
+use serde::{Deserialize, Serialize};
+use std::{fs, path::Path};
+
+pub mod message;
+
+pub const ENVELOPE_VERSION: u32 = 1;
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+    Json,
+    Cbor,
+}
+
+impl Format {
+    pub fn extension(self) -> &'static str {
+        match self {
+            Format::Json => "json",
+            Format::Cbor => "cbor",
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct SignedEnvelope {
+    pub version: u32,
+    /// Signed raw transaction, ready for `eth_sendRawTransaction`.
+    pub raw_tx: Bytes,
+    /// keccak256 of `raw_tx`.
+    pub tx_hash: H256,
+    pub from: Address,
+    pub nonce: u64,
+    pub chain_id: u64,
+    /// keccak256 of the unsigned payload bytes the transaction was signed from.
+    pub unsigned_hash: H256,
+    /// Unix time of signing.
+    pub signed_at: u64,
+}
+
+impl SignedEnvelope {
+    pub fn new(raw_tx: Bytes, from: Address, nonce: u64, chain_id: u64, unsigned_payload: &[u8], signed_at: u64) -> Self {
+        SignedEnvelope {
+            version: ENVELOPE_VERSION,
+            tx_hash: H256::from(keccak256(&raw_tx)),
+            raw_tx,
+            from,
+            nonce,
+            chain_id,
+            unsigned_hash: H256::from(keccak256(unsigned_payload)),
+            signed_at,
+        }
+    }
+
+    pub fn encode(&self, format: Format) -> Result<Vec<u8>> {
+        match format {
+            Format::Json => Ok(serde_json::to_vec_pretty(self)?),
+            Format::Cbor => {
+                let mut out = Vec::new();
+                ciborium::into_writer(self, &mut out).map_err(|e| eyre!("CBOR encoding failed: {}", e))?;
+                Ok(out)
+            }
+        }
+    }
+
+    pub fn write(&self, path: &Path, format: Format) -> Result<()> {
+        Ok(fs::write(path, self.encode(format)?)?)
+    }
+
+    /// Reject envelopes from an unknown version or whose hash does not match the raw tx.
+    pub fn check(&self) -> Result<()> {
+        if self.version != ENVELOPE_VERSION {
+            return Err(eyre!("Unsupported envelope version {} (expected {})", self.version, ENVELOPE_VERSION));
+        }
+        let actual = H256::from(keccak256(&self.raw_tx));
+        if actual != self.tx_hash {
+            return Err(eyre!("Envelope tx_hash {:?} does not match its raw transaction ({:?})", self.tx_hash, actual));
+        }
+        Ok(())
+    }
+}
+
+/// A signed transaction as found in a file: bare hex or an envelope.
+pub enum SignedInput {
+    Raw(Vec<u8>),
+    Envelope(SignedEnvelope),
+}
+
+impl SignedInput {
+    pub fn raw_tx(&self) -> &[u8] {
+        match self {
+            SignedInput::Raw(raw) => raw,
+            SignedInput::Envelope(envelope) => &envelope.raw_tx,
+        }
+    }
+}
+
+/// Detect JSON (`{`), CBOR (a map) or bare hex. Envelopes are checked before being returned.
+pub fn parse_signed(bytes: &[u8]) -> Result<SignedInput> {
+    let trimmed = bytes.trim_ascii();
+    let envelope: SignedEnvelope = match trimmed.first() {
+        Some(b'{') => serde_json::from_slice(trimmed).map_err(|e| eyre!("Invalid JSON envelope: {}", e))?,
+        Some(0xa0..=0xbf) => ciborium::from_reader(bytes).map_err(|e| eyre!("Invalid CBOR envelope: {}", e))?,
+        _ => {
+            let text = std::str::from_utf8(trimmed).map_err(|_| eyre!("Input is neither hex nor an envelope"))?;
+            let hex_data: String = text.trim_start_matches("0x").chars().filter(|c| !c.is_whitespace()).collect();
+            return Ok(SignedInput::Raw(hex::decode(hex_data).map_err(|e| eyre!("Failed to decode hex: {}", e))?));
+        }
+    };
+    envelope.check()?;
+    Ok(SignedInput::Envelope(envelope))
+}
+
+pub fn read_signed(path: &Path) -> Result<SignedInput> {
+    parse_signed(&fs::read(path).map_err(|e| eyre!("Cannot read {}: {}", path.display(), e))?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trips_json_cbor_and_hex() {
+        let raw = hex::decode("f86c808504e3b2920082520894deadbeefdeadbeefdeadbeefdeadbeefdeadbeef88016345785d8a000080018080").unwrap();
+        let envelope = SignedEnvelope::new(raw.clone().into(), Address::repeat_byte(0x11), 7, 137, b"unsigned", 1_700_000_000);
+        for format in [Format::Json, Format::Cbor] {
+            let SignedInput::Envelope(decoded) = parse_signed(&envelope.encode(format).unwrap()).unwrap() else {
+                panic!("{format:?} envelope not detected");
+            };
+            assert_eq!(decoded, envelope);
+        }
+        assert_eq!(parse_signed(format!("0x{}\n", hex::encode(&raw)).as_bytes()).unwrap().raw_tx(), raw.as_slice());
+
+        let mut tampered = envelope.clone();
+        tampered.raw_tx = Bytes::from(raw[1..].to_vec());
+        assert!(parse_signed(&tampered.encode(Format::Json).unwrap()).is_err());
+    }
+}
@@ -0,0 +1,44 @@
+//! Signed-message JSON written by `tx_signer message` and read by `tx_inspector
+//! --verify-message`.
+
+use ethers::types::{Address, Signature};
+use eyre::{eyre, Result};
+use serde::{Deserialize, Serialize};
+
+/// Signed message in the widely used `{address, msg, sig, version}` JSON layout.
+/// `msg` is the message text; non-UTF-8 messages are hex with `"encoding": "hex"`.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct SignedMessage {
+    pub address: Address,
+    pub msg: String,
+    pub sig: String,
+    #[serde(default)]
+    pub version: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub encoding: Option<String>,
+}
+
+impl SignedMessage {
+    pub fn new(message: &[u8], address: Address, signature: &Signature) -> Self {
+        let (msg, encoding) = match std::str::from_utf8(message) {
+            Ok(text) => (text.to_string(), None),
+            Err(_) => (format!("0x{}", hex::encode(message)), Some("hex".to_string())),
+        };
+        SignedMessage {
+            address,
+            msg,
+            sig: format!("0x{}", hex::encode(signature.to_vec())),
+            version: "2".to_string(),
+            encoding,
+        }
+    }
+
+    /// The signed bytes, decoding `msg` according to `encoding`.
+    pub fn message_bytes(&self) -> Result<Vec<u8>> {
+        match self.encoding.as_deref() {
+            None | Some("utf8") => Ok(self.msg.as_bytes().to_vec()),
+            Some("hex") => hex::decode(self.msg.trim_start_matches("0x")).map_err(|e| eyre!("Invalid hex message: {}", e)),
+            Some(other) => Err(eyre!("Unknown message encoding {:?}", other)),
+        }
+    }
+}
@@ -0,0 +1,69 @@
+//! Checks for signed-output envelopes written by `tx_signer --envelope`.
+
+use ethers::types::transaction::eip2718::TypedTransaction;
+use ethers::types::Address;
+use ethers::utils::rlp;
+use eyre::{eyre, Result};
+use tx_envelope::SignedEnvelope;
+
+/// Recover the sender of the envelope's raw tx and compare it, the nonce and the chain ID with
+/// the envelope metadata. Returns the recovered sender.
+pub fn verify(envelope: &SignedEnvelope) -> Result<Address> {
+    let (tx, sig) = TypedTransaction::decode_signed(&rlp::Rlp::new(&envelope.raw_tx))
+        .map_err(|e| eyre!("Envelope raw_tx is not a signed transaction: {}", e))?;
+    let recovered = sig.recover(tx.sighash())?;
+    let mut problems = Vec::new();
+    if recovered != envelope.from {
+        problems.push(format!("signature recovers to {:?}, envelope says {:?}", recovered, envelope.from));
+    }
+    if tx.nonce().map(|n| n.low_u64()) != Some(envelope.nonce) {
+        problems.push(format!("tx nonce {:?}, envelope says {}", tx.nonce(), envelope.nonce));
+    }
+    if tx.chain_id().map(|c| c.as_u64()) != Some(envelope.chain_id) {
+        problems.push(format!("tx chain ID {:?}, envelope says {}", tx.chain_id(), envelope.chain_id));
+    }
+    if !problems.is_empty() {
+        return Err(eyre!("Envelope does not match its transaction: {}", problems.join("; ")));
+    }
+    Ok(recovered)
+}
+
+pub fn print(envelope: &SignedEnvelope) {
+    println!("Signed envelope (version {}):", envelope.version);
+    println!("  From:           {:?}", envelope.from);
+    println!("  Chain ID:       {}", envelope.chain_id);
+    println!("  Nonce:          {}", envelope.nonce);
+    println!("  Tx hash:        {:?}", envelope.tx_hash);
+    println!("  Unsigned hash:  {:?}", envelope.unsigned_hash);
+    println!("  Signed at:      {} (unix)", envelope.signed_at);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ethers::signers::{LocalWallet, Signer};
+    use ethers::types::{Eip1559TransactionRequest, TransactionRequest};
+    use std::str::FromStr;
+
+    #[test]
+    fn checks_sender_nonce_and_chain() {
+        let wallet = LocalWallet::from_str("4c0883a69102937d6231471b5ecb4765d5e97f8e4dc6e8fa6a4de3b8a3a2f55b").unwrap();
+        let legacy: TypedTransaction =
+            TransactionRequest::new().to(Address::repeat_byte(0x11)).nonce(5).gas(21_000).gas_price(1).chain_id(1).into();
+        let eip1559: TypedTransaction = Eip1559TransactionRequest::new()
+            .to(Address::repeat_byte(0x11))
+            .nonce(5)
+            .gas(21_000)
+            .max_fee_per_gas(2)
+            .max_priority_fee_per_gas(1)
+            .chain_id(137)
+            .into();
+        for (tx, chain_id) in [(legacy, 1), (eip1559, 137)] {
+            let raw = tx.rlp_signed(&wallet.sign_transaction_sync(&tx).unwrap());
+            let envelope = SignedEnvelope::new(raw, wallet.address(), 5, chain_id, b"", 0);
+            assert_eq!(verify(&envelope).unwrap(), wallet.address());
+            assert!(verify(&SignedEnvelope { from: Address::zero(), ..envelope.clone() }).is_err());
+            assert!(verify(&SignedEnvelope { nonce: 6, ..envelope }).is_err());
+        }
+    }
+}
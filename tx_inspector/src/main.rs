@@ -1,32 +1,63 @@
+mod envelope;
+mod message;
+mod typed_data;
+
 use clap::Parser;
-use ethers::types::{transaction::eip2718::TypedTransaction, Signature};
+use ethers::types::{transaction::eip2718::TypedTransaction, Address, Signature};
 use ethers::utils::rlp;
-use std::fs;
-use hex;
+use std::{fs, path::Path};
+use tx_envelope::SignedInput;
 
 /// CLI to inspect an Ethereum/Polygon transaction from RLP hex
 #[derive(Parser, Debug)]
 #[command(name = "tx_inspector")]
 #[command(about = "Inspects an RLP-encoded Ethereum/Polygon transaction", long_about = None)]
 struct Args {
-    /// Path to file with RLP-encoded hex transaction (signed or unsigned)
-    #[arg(long)]
-    input: String,
+    /// Path to file with RLP-encoded hex transaction (signed or unsigned), or a tx_signer envelope
+    #[arg(long, required_unless_present_any = ["verify_message", "verify_typed_data"])]
+    input: Option<String>,
+
+    /// Verify an EIP-191 signed message JSON from `tx_signer message` and recover the signer
+    #[arg(long, conflicts_with = "input")]
+    verify_message: Option<String>,
+
+    /// Verify an EIP-712 signature: signed JSON from `tx_signer typed-data`, or v4 typed data with --signature
+    #[arg(long, conflicts_with_all = ["input", "verify_message"])]
+    verify_typed_data: Option<String>,
+
+    /// Signature (hex) to check against --verify-typed-data
+    #[arg(long, requires = "verify_typed_data")]
+    signature: Option<String>,
+
+    /// Expected signer address for --verify-typed-data
+    #[arg(long, requires = "verify_typed_data")]
+    signer: Option<Address>,
 }
 
 fn main() -> eyre::Result<()> {
     let args = Args::parse();
 
-    println!(">> Reading transaction from: {}", args.input);
-    let raw = fs::read_to_string(&args.input)?.trim().to_string();
-
-    let hex_data = raw.chars().filter(|c| !c.is_whitespace()).collect::<String>();
-
-    if hex_data.len() % 2 != 0 {
-        return Err(eyre::eyre!("Hex string has an odd number of characters: {}", hex_data.len()));
+    if let Some(path) = &args.verify_message {
+        println!(">> Verifying signed message from: {}", path);
+        return message::verify(&fs::read_to_string(path)?);
     }
-
-    let rlp_bytes = hex::decode(&hex_data).map_err(|e| eyre::eyre!("Failed to decode hex: {}", e))?;
+    if let Some(path) = &args.verify_typed_data {
+        println!(">> Verifying EIP-712 typed data signature from: {}", path);
+        typed_data::verify(&fs::read_to_string(path)?, args.signature.as_deref(), args.signer)?;
+        return Ok(());
+    }
+    let input = args.input.as_deref().expect("--input is required unless verifying a signature");
+
+    println!(">> Reading transaction from: {}", input);
+    let rlp_bytes = match tx_envelope::read_signed(Path::new(input))? {
+        SignedInput::Raw(bytes) => bytes,
+        SignedInput::Envelope(signed) => {
+            envelope::print(&signed);
+            let recovered = envelope::verify(&signed)?;
+            println!("Envelope verified: tx hash, sender {:?}, nonce and chain ID match the raw tx", recovered);
+            signed.raw_tx.to_vec()
+        }
+    };
 
     println!(">> Attempting full transaction inspection...");
     if let Ok(tx) = rlp::decode::<TypedTransaction>(&rlp_bytes) {
@@ -67,8 +98,6 @@ fn main() -> eyre::Result<()> {
 
 #[cfg(test)]
 mod tests {
-    use hex;
-
     #[test]
     fn test_even_length_hex_ok() {
         let hex_data = "deadbeef";
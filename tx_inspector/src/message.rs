@@ -0,0 +1,47 @@
+//! Verification of EIP-191 (`personal_sign`) messages produced by `tx_signer message`.
+
+use ethers::types::{Address, Signature};
+use ethers::utils::hash_message;
+use eyre::{eyre, Result};
+use std::str::FromStr;
+use tx_envelope::message::SignedMessage;
+
+/// Recover the signer of an EIP-191 signature.
+pub fn recover(message: &[u8], sig: &str) -> Result<Address> {
+    let sig = Signature::from_str(sig.trim()).map_err(|e| eyre!("Invalid signature: {}", e))?;
+    Ok(sig.recover(hash_message(message))?)
+}
+
+/// Print the verification result; error if the recovered signer is not the claimed address.
+pub fn verify(json: &str) -> Result<()> {
+    let signed: SignedMessage = serde_json::from_str(json).map_err(|e| eyre!("Invalid signed message JSON: {}", e))?;
+    let message = signed.message_bytes()?;
+    let recovered = recover(&message, &signed.sig)?;
+    println!("Message ({} bytes): {}", message.len(), signed.msg);
+    println!("Claimed signer: {:?}", signed.address);
+    println!("Recovered signer: {:?}", recovered);
+    if recovered == signed.address {
+        println!("Signature is VALID for the claimed address");
+        Ok(())
+    } else {
+        Err(eyre!("Signature does NOT match the claimed address"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn verifies_signer_output() {
+        // Output of `tx_signer message --text "I control this address"`.
+        let json = r#"{
+            "address": "0xbae8513769f2174fe262dcd8182ec184786d2d7a",
+            "msg": "I control this address",
+            "sig": "0x895a44e192b9e119a5560925aa790ac8c320f6d8c825cf3c0e8eaf12c1443f4f6890bfa02b0690ca3527fad643793df7089e100173aa8b6904f780d57019c89b1b",
+            "version": "2"
+        }"#;
+        assert!(verify(json).is_ok());
+        assert!(verify(&json.replace("I control", "You control")).is_err());
+    }
+}
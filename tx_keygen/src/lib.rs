@@ -0,0 +1,3 @@
+//! Key material helpers shared by the toolkit binaries.
+
+pub mod slip39;
@@ -0,0 +1,151 @@
+use clap::{Parser, Subcommand};
+use eyre::{eyre, Result};
+use std::{fs, io::Write, path::{Path, PathBuf}};
+use tx_keygen::slip39;
+use zeroize::Zeroizing;
+
+/// Seed lengths SLIP-39 share splitting accepts, in bytes.
+const SEED_BYTES: std::ops::RangeInclusive<usize> = 16..=64;
+
+/// CLI for cold key material handling (SLIP-39 share splitting).
+#[derive(Parser, Debug)]
+#[command(name = "tx_keygen")]
+#[command(about = "Key material tooling for the offline toolkit", arg_required_else_help = true)]
+struct Args {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Split an existing seed into M-of-N SLIP-39 share phrases
+    Split {
+        /// Number of shares required to recover the seed (M)
+        #[arg(long)]
+        threshold: u8,
+
+        /// Total number of shares to create (N, at most 16)
+        #[arg(long)]
+        shares: u8,
+
+        /// File holding the seed as hex (16..64 bytes). Prompted for on the terminal if omitted.
+        #[arg(long)]
+        seed_file: Option<PathBuf>,
+
+        /// Prompt for a SLIP-39 passphrase to encrypt the seed with
+        #[arg(long)]
+        passphrase: bool,
+
+        /// PBKDF2 iteration exponent (10000 * 2^e iterations)
+        #[arg(long, default_value_t = 1)]
+        iteration_exponent: u8,
+
+        /// Write each share to <dir>/share_<i>.txt instead of printing it
+        #[arg(long)]
+        output_dir: Option<PathBuf>,
+    },
+}
+
+fn read_seed(seed_file: Option<&PathBuf>) -> Result<Zeroizing<Vec<u8>>> {
+    let hex_seed = match seed_file {
+        Some(path) => Zeroizing::new(fs::read_to_string(path)?),
+        None => Zeroizing::new(rpassword::prompt_password("Seed (hex): ")?),
+    };
+    let trimmed = hex_seed.trim();
+    let seed = Zeroizing::new(hex::decode(trimmed.strip_prefix("0x").unwrap_or(trimmed))?);
+    if !SEED_BYTES.contains(&seed.len()) {
+        return Err(eyre!(
+            "Seed must be {}..{} bytes, got {}",
+            SEED_BYTES.start(),
+            SEED_BYTES.end(),
+            seed.len()
+        ));
+    }
+    Ok(seed)
+}
+
+/// Create the share directory readable by the owner only.
+#[cfg(unix)]
+fn create_share_dir(dir: &Path) -> Result<()> {
+    use std::os::unix::fs::DirBuilderExt;
+    fs::DirBuilder::new().recursive(true).mode(0o700).create(dir)?;
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn create_share_dir(dir: &Path) -> Result<()> {
+    fs::create_dir_all(dir)?;
+    Ok(())
+}
+
+/// Write one share to a new file that only the owner can read; an existing file is an error.
+fn write_share(path: &Path, mnemonic: &str) -> Result<()> {
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    let mut file = options
+        .open(path)
+        .map_err(|e| eyre!("Cannot create {}: {} (existing shares are never overwritten)", path.display(), e))?;
+    file.write_all(mnemonic.as_bytes())?;
+    file.sync_all()?;
+    Ok(())
+}
+
+fn read_passphrase() -> Result<Zeroizing<String>> {
+    let first = Zeroizing::new(rpassword::prompt_password("SLIP-39 passphrase: ")?);
+    let again = Zeroizing::new(rpassword::prompt_password("Repeat passphrase: ")?);
+    if *first != *again {
+        return Err(eyre!("Passphrases do not match"));
+    }
+    Ok(first)
+}
+
+fn main() -> Result<()> {
+    let args = Args::parse();
+
+    match args.command {
+        Command::Split {
+            threshold,
+            shares,
+            seed_file,
+            passphrase,
+            iteration_exponent,
+            output_dir,
+        } => {
+            let seed = read_seed(seed_file.as_ref())?;
+            let passphrase = if passphrase { read_passphrase()? } else { Zeroizing::new(String::new()) };
+
+            let groups = slip39::generate_mnemonics(
+                1,
+                &[(threshold, shares)],
+                &seed,
+                passphrase.as_bytes(),
+                true,
+                iteration_exponent,
+            )?;
+            let mnemonics = &groups[0];
+
+            // Round-trip before handing anything out.
+            let check = slip39::combine_mnemonics(&mnemonics[..threshold as usize], passphrase.as_bytes())?;
+            if *check != *seed {
+                return Err(eyre!("Share self-check failed: recovered seed does not match"));
+            }
+
+            println!("Created {threshold}-of-{shares} SLIP-39 shares ({} bytes of seed)", seed.len());
+            if let Some(dir) = output_dir {
+                create_share_dir(&dir)?;
+                for (i, m) in mnemonics.iter().enumerate() {
+                    let path = dir.join(format!("share_{}.txt", i + 1));
+                    write_share(&path, m)?;
+                    println!("Share {} written to: {}", i + 1, path.display());
+                }
+            } else {
+                for (i, m) in mnemonics.iter().enumerate() {
+                    println!("Share {}: {}", i + 1, m);
+                }
+            }
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,718 @@
+//! SLIP-39 Shamir secret sharing of a master secret.
+//!
+//! Implements the share format, RS1024 checksum, GF(256) secret sharing and the
+//! four-round Feistel encryption described in
+//! <https://github.com/satoshilabs/slips/blob/master/slip-0039.md>.
+//! Secrets and intermediate shares are held in `Zeroizing` buffers so they are wiped
+//! as soon as a split or recovery finishes.
+
+use eyre::{eyre, Result};
+use hmac::{Hmac, Mac};
+use rand::{rngs::OsRng, RngCore};
+use sha2::Sha256;
+use std::collections::BTreeMap;
+use std::sync::OnceLock;
+use zeroize::Zeroizing;
+
+const WORDLIST: &str = include_str!("wordlists/slip39_en.txt");
+
+const RADIX_BITS: usize = 10;
+const RADIX: u32 = 1 << RADIX_BITS;
+const ID_LENGTH_BITS: u32 = 15;
+const ITERATION_EXP_LENGTH_BITS: u32 = 4;
+const CHECKSUM_LENGTH_WORDS: usize = 3;
+/// identifier/exponent (2 words) + share parameters (2 words) + checksum (3 words)
+const METADATA_LENGTH_WORDS: usize = 2 + 2 + CHECKSUM_LENGTH_WORDS;
+const MIN_STRENGTH_BITS: usize = 128;
+const MIN_MNEMONIC_LENGTH_WORDS: usize = METADATA_LENGTH_WORDS + MIN_STRENGTH_BITS.div_ceil(RADIX_BITS);
+const MAX_SHARE_COUNT: u8 = 16;
+
+const CUSTOMIZATION_STRING_ORIG: &[u8] = b"shamir";
+const CUSTOMIZATION_STRING_EXTENDABLE: &[u8] = b"shamir_extendable";
+
+const BASE_ITERATION_COUNT: u32 = 10_000;
+const ROUND_COUNT: u8 = 4;
+
+const SECRET_INDEX: u8 = 255;
+const DIGEST_INDEX: u8 = 254;
+const DIGEST_LENGTH_BYTES: usize = 4;
+
+fn wordlist() -> &'static [&'static str] {
+    static WORDS: OnceLock<Vec<&'static str>> = OnceLock::new();
+    WORDS.get_or_init(|| WORDLIST.lines().map(str::trim).filter(|w| !w.is_empty()).collect())
+}
+
+fn word_index(word: &str) -> Result<u16> {
+    let word = word.to_ascii_lowercase();
+    wordlist()
+        .binary_search(&word.as_str())
+        .map(|i| i as u16)
+        .map_err(|_| eyre!("Invalid mnemonic word {:?}", word))
+}
+
+/// One decoded SLIP-39 share (a single mnemonic phrase).
+#[derive(Clone)]
+pub struct Share {
+    pub identifier: u16,
+    pub extendable: bool,
+    pub iteration_exponent: u8,
+    pub group_index: u8,
+    pub group_threshold: u8,
+    pub group_count: u8,
+    pub index: u8,
+    pub member_threshold: u8,
+    pub value: Zeroizing<Vec<u8>>,
+}
+
+impl Share {
+    /// Decode a mnemonic phrase, verifying its word list membership, checksum and padding.
+    pub fn from_mnemonic(mnemonic: &str) -> Result<Self> {
+        let indices = mnemonic
+            .split_whitespace()
+            .map(word_index)
+            .collect::<Result<Vec<u16>>>()?;
+
+        if indices.len() < MIN_MNEMONIC_LENGTH_WORDS {
+            return Err(eyre!(
+                "Invalid mnemonic length. The length of each mnemonic must be at least {} words",
+                MIN_MNEMONIC_LENGTH_WORDS
+            ));
+        }
+
+        let padding_len = (RADIX_BITS * (indices.len() - METADATA_LENGTH_WORDS)) % 16;
+        if padding_len > 8 {
+            return Err(eyre!("Invalid mnemonic length"));
+        }
+
+        let id_exp = ((indices[0] as u32) << RADIX_BITS) | indices[1] as u32;
+        let identifier = (id_exp >> (ITERATION_EXP_LENGTH_BITS + 1)) as u16;
+        let extendable = (id_exp >> ITERATION_EXP_LENGTH_BITS) & 1 == 1;
+        let iteration_exponent = (id_exp & ((1 << ITERATION_EXP_LENGTH_BITS) - 1)) as u8;
+
+        if !rs1024_verify_checksum(&indices, customization_string(extendable)) {
+            return Err(eyre!(
+                "Invalid mnemonic checksum for \"{} ...\"",
+                mnemonic.split_whitespace().take(3).collect::<Vec<_>>().join(" ")
+            ));
+        }
+
+        let params = ((indices[2] as u32) << RADIX_BITS) | indices[3] as u32;
+        let nibble = |shift: u32| ((params >> shift) & 0xF) as u8;
+        let group_index = nibble(16);
+        let group_threshold = nibble(12) + 1;
+        let group_count = nibble(8) + 1;
+        let index = nibble(4);
+        let member_threshold = nibble(0) + 1;
+
+        if group_count < group_threshold {
+            return Err(eyre!(
+                "Invalid mnemonic: group threshold ({}) cannot be greater than group count ({})",
+                group_threshold,
+                group_count
+            ));
+        }
+
+        let value_words = &indices[4..indices.len() - CHECKSUM_LENGTH_WORDS];
+        let value = Zeroizing::new(
+            words_to_bytes(value_words, padding_len).ok_or_else(|| eyre!("Invalid mnemonic padding"))?,
+        );
+
+        Ok(Share {
+            identifier,
+            extendable,
+            iteration_exponent,
+            group_index,
+            group_threshold,
+            group_count,
+            index,
+            member_threshold,
+            value,
+        })
+    }
+
+    /// Encode the share as a space separated mnemonic phrase.
+    pub fn to_mnemonic(&self) -> String {
+        let id_exp = ((self.identifier as u32) << (ITERATION_EXP_LENGTH_BITS + 1))
+            | ((self.extendable as u32) << ITERATION_EXP_LENGTH_BITS)
+            | self.iteration_exponent as u32;
+        let params = ((self.group_index as u32) << 16)
+            | (((self.group_threshold - 1) as u32) << 12)
+            | (((self.group_count - 1) as u32) << 8)
+            | ((self.index as u32) << 4)
+            | (self.member_threshold - 1) as u32;
+
+        let mut indices: Vec<u16> = vec![
+            (id_exp >> RADIX_BITS) as u16,
+            (id_exp % RADIX) as u16,
+            (params >> RADIX_BITS) as u16,
+            (params % RADIX) as u16,
+        ];
+        indices.extend(bytes_to_words(&self.value));
+        let checksum = rs1024_create_checksum(&indices, customization_string(self.extendable));
+        indices.extend(checksum);
+
+        let words = wordlist();
+        indices.iter().map(|&i| words[i as usize]).collect::<Vec<_>>().join(" ")
+    }
+
+    fn common_parameters(&self) -> (u16, bool, u8, u8, u8) {
+        (
+            self.identifier,
+            self.extendable,
+            self.iteration_exponent,
+            self.group_threshold,
+            self.group_count,
+        )
+    }
+}
+
+/// Collects shares one at a time and reports when enough have been entered to recover
+/// the master secret. Each share is checked for consistency with the ones already held.
+#[derive(Default)]
+pub struct ShareSet {
+    groups: BTreeMap<u8, Vec<Share>>,
+}
+
+impl ShareSet {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a share to the set. Rejects shares from another split, duplicates and shares
+    /// for a group that is already complete.
+    pub fn add(&mut self, share: Share) -> Result<()> {
+        if let Some(first) = self.groups.values().flatten().next() {
+            if first.common_parameters() != share.common_parameters() {
+                return Err(eyre!(
+                    "Share does not belong to the same secret as the previous shares \
+                     (identifier, iteration exponent or group parameters differ)"
+                ));
+            }
+            if first.value.len() != share.value.len() {
+                return Err(eyre!("Share value length differs from the previous shares"));
+            }
+        }
+
+        let group = self.groups.get(&share.group_index).map(Vec::as_slice).unwrap_or_default();
+        if let Some(member) = group.first() {
+            if member.member_threshold != share.member_threshold {
+                return Err(eyre!(
+                    "Share has a different member threshold than other shares in group {}",
+                    share.group_index + 1
+                ));
+            }
+            if group.len() >= member.member_threshold as usize {
+                return Err(eyre!("Group {} already has enough shares", share.group_index + 1));
+            }
+        } else if self.complete_groups() >= share.group_threshold as usize {
+            return Err(eyre!("Enough groups have already been entered"));
+        }
+        if group.iter().any(|s| s.index == share.index) {
+            return Err(eyre!(
+                "Share {} of group {} was already entered",
+                share.index + 1,
+                share.group_index + 1
+            ));
+        }
+
+        self.groups.entry(share.group_index).or_default().push(share);
+        Ok(())
+    }
+
+    fn complete_groups(&self) -> usize {
+        self.groups
+            .values()
+            .filter(|g| g.first().is_some_and(|s| g.len() >= s.member_threshold as usize))
+            .count()
+    }
+
+    /// True once `group_threshold` groups each hold `member_threshold` shares.
+    pub fn is_complete(&self) -> bool {
+        match self.groups.values().flatten().next() {
+            Some(s) => self.complete_groups() >= s.group_threshold as usize,
+            None => false,
+        }
+    }
+
+    /// Human readable progress, e.g. "group 1: 1/2 shares; groups complete: 0/1".
+    pub fn progress(&self) -> String {
+        let Some(any) = self.groups.values().flatten().next() else {
+            return "no shares entered".to_string();
+        };
+        let mut parts: Vec<String> = self
+            .groups
+            .iter()
+            .map(|(gi, g)| format!("group {}: {}/{} shares", gi + 1, g.len(), g[0].member_threshold))
+            .collect();
+        parts.push(format!(
+            "groups complete: {}/{}",
+            self.complete_groups(),
+            any.group_threshold
+        ));
+        parts.join("; ")
+    }
+
+    /// Recover the master secret. Fails if the set is incomplete, the digest does not
+    /// verify, or the passphrase is not printable ASCII.
+    pub fn recover(&self, passphrase: &[u8]) -> Result<Zeroizing<Vec<u8>>> {
+        if !self.is_complete() {
+            return Err(eyre!("Insufficient shares to recover the secret ({})", self.progress()));
+        }
+        check_passphrase(passphrase)?;
+
+        let mut group_shares: Vec<(u8, Zeroizing<Vec<u8>>)> = Vec::new();
+        for (gi, members) in &self.groups {
+            let threshold = members[0].member_threshold;
+            if members.len() < threshold as usize {
+                continue;
+            }
+            let shares: Vec<(u8, &[u8])> = members.iter().map(|s| (s.index, s.value.as_slice())).collect();
+            group_shares.push((*gi, recover_secret(threshold, &shares)?));
+        }
+
+        let first = self.groups.values().flatten().next().expect("complete set is non-empty");
+        let shares: Vec<(u8, &[u8])> = group_shares.iter().map(|(i, v)| (*i, v.as_slice())).collect();
+        let ems = recover_secret(first.group_threshold, &shares)?;
+        Ok(decrypt(
+            &ems,
+            passphrase,
+            first.iteration_exponent,
+            first.identifier,
+            first.extendable,
+        ))
+    }
+}
+
+/// Recover the master secret from a complete list of mnemonics.
+pub fn combine_mnemonics<S: AsRef<str>>(mnemonics: &[S], passphrase: &[u8]) -> Result<Zeroizing<Vec<u8>>> {
+    if mnemonics.is_empty() {
+        return Err(eyre!("The list of mnemonics is empty"));
+    }
+    let mut set = ShareSet::new();
+    for m in mnemonics {
+        set.add(Share::from_mnemonic(m.as_ref())?)?;
+    }
+    set.recover(passphrase)
+}
+
+/// Split a master secret into mnemonic shares.
+///
+/// `groups` lists `(member_threshold, member_count)` per group; `group_threshold` of them
+/// are needed for recovery. Returns one list of mnemonics per group.
+pub fn generate_mnemonics(
+    group_threshold: u8,
+    groups: &[(u8, u8)],
+    master_secret: &[u8],
+    passphrase: &[u8],
+    extendable: bool,
+    iteration_exponent: u8,
+) -> Result<Vec<Vec<String>>> {
+    if master_secret.len() * 8 < MIN_STRENGTH_BITS {
+        return Err(eyre!(
+            "The length of the master secret must be at least {} bytes",
+            MIN_STRENGTH_BITS / 8
+        ));
+    }
+    if !master_secret.len().is_multiple_of(2) {
+        return Err(eyre!("The length of the master secret in bytes must be an even number"));
+    }
+    check_passphrase(passphrase)?;
+    if iteration_exponent >= 1 << ITERATION_EXP_LENGTH_BITS {
+        return Err(eyre!("Iteration exponent must be below {}", 1 << ITERATION_EXP_LENGTH_BITS));
+    }
+    if groups.is_empty() || groups.len() > MAX_SHARE_COUNT as usize {
+        return Err(eyre!("The number of groups must be between 1 and {}", MAX_SHARE_COUNT));
+    }
+    if group_threshold == 0 || group_threshold as usize > groups.len() {
+        return Err(eyre!(
+            "The group threshold ({}) must be between 1 and the number of groups ({})",
+            group_threshold,
+            groups.len()
+        ));
+    }
+    for &(threshold, count) in groups {
+        if threshold == 1 && count > 1 {
+            return Err(eyre!(
+                "Creating multiple member shares with member threshold 1 is not allowed. Use 1-of-1 member sharing instead"
+            ));
+        }
+        if threshold == 0 || threshold > count || count > MAX_SHARE_COUNT {
+            return Err(eyre!(
+                "Invalid member sharing {}-of-{}: threshold must be between 1 and the share count (at most {})",
+                threshold,
+                count,
+                MAX_SHARE_COUNT
+            ));
+        }
+    }
+
+    let identifier = (OsRng.next_u32() & ((1 << ID_LENGTH_BITS) - 1)) as u16;
+    let ems = encrypt(master_secret, passphrase, iteration_exponent, identifier, extendable);
+
+    let group_secrets = split_secret(group_threshold, groups.len() as u8, &ems)?;
+    let mut out = Vec::with_capacity(groups.len());
+    for ((group_index, group_secret), &(member_threshold, member_count)) in group_secrets.iter().zip(groups) {
+        let members = split_secret(member_threshold, member_count, group_secret)?;
+        out.push(
+            members
+                .into_iter()
+                .map(|(index, value)| {
+                    Share {
+                        identifier,
+                        extendable,
+                        iteration_exponent,
+                        group_index: *group_index,
+                        group_threshold,
+                        group_count: groups.len() as u8,
+                        index,
+                        member_threshold,
+                        value,
+                    }
+                    .to_mnemonic()
+                })
+                .collect(),
+        );
+    }
+    Ok(out)
+}
+
+fn check_passphrase(passphrase: &[u8]) -> Result<()> {
+    if passphrase.iter().all(|c| (32..=126).contains(c)) {
+        Ok(())
+    } else {
+        Err(eyre!("The passphrase must contain only printable ASCII characters"))
+    }
+}
+
+fn customization_string(extendable: bool) -> &'static [u8] {
+    if extendable {
+        CUSTOMIZATION_STRING_EXTENDABLE
+    } else {
+        CUSTOMIZATION_STRING_ORIG
+    }
+}
+
+// ---------------------------------------------------------------------------
+// RS1024 checksum
+// ---------------------------------------------------------------------------
+
+fn rs1024_polymod(values: impl IntoIterator<Item = u32>) -> u32 {
+    const GEN: [u32; 10] = [
+        0x00E0_E040,
+        0x01C1_C080,
+        0x0383_8100,
+        0x0707_0200,
+        0x0E0E_0009,
+        0x1C0C_2412,
+        0x3808_6C24,
+        0x3090_FC48,
+        0x21B1_F890,
+        0x03F3_F120,
+    ];
+    let mut chk: u32 = 1;
+    for v in values {
+        let b = chk >> 20;
+        chk = ((chk & 0xFFFFF) << 10) ^ v;
+        for (i, g) in GEN.iter().enumerate() {
+            if (b >> i) & 1 == 1 {
+                chk ^= g;
+            }
+        }
+    }
+    chk
+}
+
+fn rs1024_create_checksum(data: &[u16], custom: &[u8]) -> [u16; CHECKSUM_LENGTH_WORDS] {
+    let values = custom
+        .iter()
+        .map(|&c| c as u32)
+        .chain(data.iter().map(|&d| d as u32))
+        .chain(std::iter::repeat_n(0, CHECKSUM_LENGTH_WORDS));
+    let polymod = rs1024_polymod(values) ^ 1;
+    let mut out = [0u16; CHECKSUM_LENGTH_WORDS];
+    for (i, word) in out.iter_mut().enumerate() {
+        *word = ((polymod >> (10 * (CHECKSUM_LENGTH_WORDS - 1 - i))) & 1023) as u16;
+    }
+    out
+}
+
+fn rs1024_verify_checksum(data: &[u16], custom: &[u8]) -> bool {
+    let values = custom.iter().map(|&c| c as u32).chain(data.iter().map(|&d| d as u32));
+    rs1024_polymod(values) == 1
+}
+
+// ---------------------------------------------------------------------------
+// Word <-> byte packing
+// ---------------------------------------------------------------------------
+
+/// Unpack 10-bit words into bytes, requiring the leading `padding_len` bits to be zero.
+fn words_to_bytes(words: &[u16], padding_len: usize) -> Option<Vec<u8>> {
+    let bits: Vec<u8> = words
+        .iter()
+        .flat_map(|&w| (0..RADIX_BITS).rev().map(move |i| ((w >> i) & 1) as u8))
+        .collect();
+    let (padding, value_bits) = bits.split_at(padding_len);
+    if padding.iter().any(|&b| b != 0) {
+        return None;
+    }
+    Some(
+        value_bits
+            .chunks(8)
+            .map(|byte| byte.iter().fold(0u8, |acc, &b| (acc << 1) | b))
+            .collect(),
+    )
+}
+
+/// Pack bytes into 10-bit words, left padding with zero bits.
+fn bytes_to_words(bytes: &[u8]) -> Vec<u16> {
+    let word_count = (bytes.len() * 8).div_ceil(RADIX_BITS);
+    let padding = word_count * RADIX_BITS - bytes.len() * 8;
+    let bits: Vec<u16> = std::iter::repeat_n(0u16, padding)
+        .chain(bytes.iter().flat_map(|&b| (0..8).rev().map(move |i| ((b >> i) & 1) as u16)))
+        .collect();
+    bits.chunks(RADIX_BITS)
+        .map(|w| w.iter().fold(0u16, |acc, &b| (acc << 1) | b))
+        .collect()
+}
+
+// ---------------------------------------------------------------------------
+// Shamir secret sharing over GF(256)
+// ---------------------------------------------------------------------------
+
+struct Gf256 {
+    exp: [u8; 255],
+    log: [u8; 256],
+}
+
+fn gf256() -> &'static Gf256 {
+    static TABLES: OnceLock<Gf256> = OnceLock::new();
+    TABLES.get_or_init(|| {
+        let mut exp = [0u8; 255];
+        let mut log = [0u8; 256];
+        let mut poly: u16 = 1;
+        for (i, e) in exp.iter_mut().enumerate() {
+            *e = poly as u8;
+            log[poly as usize] = i as u8;
+            // Multiply by the generator x + 1 and reduce by the Rijndael polynomial.
+            poly = (poly << 1) ^ poly;
+            if poly & 0x100 != 0 {
+                poly ^= 0x11B;
+            }
+        }
+        Gf256 { exp, log }
+    })
+}
+
+/// Evaluate the polynomial through `shares` at `x` using Lagrange interpolation.
+fn interpolate(shares: &[(u8, &[u8])], x: u8) -> Result<Zeroizing<Vec<u8>>> {
+    let xs: Vec<u8> = shares.iter().map(|(i, _)| *i).collect();
+    let mut sorted = xs.clone();
+    sorted.sort_unstable();
+    sorted.dedup();
+    if sorted.len() != xs.len() {
+        return Err(eyre!("Invalid set of shares. Share indices must be unique"));
+    }
+    let len = shares[0].1.len();
+    if shares.iter().any(|(_, v)| v.len() != len) {
+        return Err(eyre!("Invalid set of shares. All share values must have the same length"));
+    }
+    if let Some((_, v)) = shares.iter().find(|(i, _)| *i == x) {
+        return Ok(Zeroizing::new(v.to_vec()));
+    }
+
+    let gf = gf256();
+    let log = |v: u8| gf.log[v as usize] as i32;
+    let log_prod: i32 = xs.iter().map(|&xi| log(xi ^ x)).sum();
+
+    let mut result = Zeroizing::new(vec![0u8; len]);
+    for (xi, value) in shares {
+        let denom: i32 = xs.iter().map(|&xj| log(xi ^ xj)).sum();
+        let log_basis = (log_prod - log(xi ^ x) - denom).rem_euclid(255);
+        for (r, &v) in result.iter_mut().zip(value.iter()) {
+            if v != 0 {
+                *r ^= gf.exp[((log(v) + log_basis) % 255) as usize];
+            }
+        }
+    }
+    Ok(result)
+}
+
+fn create_digest(random_data: &[u8], shared_secret: &[u8]) -> [u8; DIGEST_LENGTH_BYTES] {
+    let mut mac = Hmac::<Sha256>::new_from_slice(random_data).expect("HMAC accepts any key length");
+    mac.update(shared_secret);
+    let full = mac.finalize().into_bytes();
+    let mut out = [0u8; DIGEST_LENGTH_BYTES];
+    out.copy_from_slice(&full[..DIGEST_LENGTH_BYTES]);
+    out
+}
+
+fn split_secret(threshold: u8, share_count: u8, secret: &[u8]) -> Result<Vec<(u8, Zeroizing<Vec<u8>>)>> {
+    if threshold == 0 || threshold > share_count || share_count > MAX_SHARE_COUNT {
+        return Err(eyre!("Invalid threshold {} for {} shares", threshold, share_count));
+    }
+    if threshold == 1 {
+        return Ok((0..share_count).map(|i| (i, Zeroizing::new(secret.to_vec()))).collect());
+    }
+
+    let random_share_count = threshold - 2;
+    let mut shares: Vec<(u8, Zeroizing<Vec<u8>>)> = (0..random_share_count)
+        .map(|i| {
+            let mut v = Zeroizing::new(vec![0u8; secret.len()]);
+            OsRng.fill_bytes(&mut v);
+            (i, v)
+        })
+        .collect();
+
+    let mut random_part = Zeroizing::new(vec![0u8; secret.len() - DIGEST_LENGTH_BYTES]);
+    OsRng.fill_bytes(&mut random_part);
+    let mut digest_share = Zeroizing::new(create_digest(&random_part, secret).to_vec());
+    digest_share.extend_from_slice(&random_part);
+
+    let mut base: Vec<(u8, &[u8])> = shares.iter().map(|(i, v)| (*i, v.as_slice())).collect();
+    base.push((DIGEST_INDEX, &digest_share));
+    base.push((SECRET_INDEX, secret));
+
+    let derived = (random_share_count..share_count)
+        .map(|i| Ok((i, interpolate(&base, i)?)))
+        .collect::<Result<Vec<_>>>()?;
+    shares.extend(derived);
+    Ok(shares)
+}
+
+fn recover_secret(threshold: u8, shares: &[(u8, &[u8])]) -> Result<Zeroizing<Vec<u8>>> {
+    if threshold == 1 {
+        return Ok(Zeroizing::new(shares[0].1.to_vec()));
+    }
+    let shared_secret = interpolate(shares, SECRET_INDEX)?;
+    let digest_share = interpolate(shares, DIGEST_INDEX)?;
+    let (digest, random_part) = digest_share.split_at(DIGEST_LENGTH_BYTES);
+    if digest != create_digest(random_part, &shared_secret) {
+        return Err(eyre!("Invalid digest of the shared secret"));
+    }
+    Ok(shared_secret)
+}
+
+// ---------------------------------------------------------------------------
+// Feistel encryption of the master secret
+// ---------------------------------------------------------------------------
+
+fn round_function(i: u8, passphrase: &[u8], e: u8, salt: &[u8], r: &[u8]) -> Zeroizing<Vec<u8>> {
+    let mut password = Zeroizing::new(Vec::with_capacity(1 + passphrase.len()));
+    password.push(i);
+    password.extend_from_slice(passphrase);
+    let mut full_salt = salt.to_vec();
+    full_salt.extend_from_slice(r);
+    let mut out = Zeroizing::new(vec![0u8; r.len()]);
+    let rounds = (BASE_ITERATION_COUNT << e) / ROUND_COUNT as u32;
+    pbkdf2::pbkdf2_hmac::<Sha256>(&password, &full_salt, rounds, &mut out);
+    out
+}
+
+fn salt(identifier: u16, extendable: bool) -> Vec<u8> {
+    if extendable {
+        Vec::new()
+    } else {
+        let mut s = CUSTOMIZATION_STRING_ORIG.to_vec();
+        s.extend_from_slice(&identifier.to_be_bytes());
+        s
+    }
+}
+
+fn feistel(input: &[u8], passphrase: &[u8], e: u8, identifier: u16, extendable: bool, rounds: &[u8]) -> Zeroizing<Vec<u8>> {
+    let half = input.len() / 2;
+    let mut l = Zeroizing::new(input[..half].to_vec());
+    let mut r = Zeroizing::new(input[half..].to_vec());
+    let salt = salt(identifier, extendable);
+    for &i in rounds {
+        let f = round_function(i, passphrase, e, &salt, &r);
+        let new_r = Zeroizing::new(l.iter().zip(f.iter()).map(|(a, b)| a ^ b).collect::<Vec<u8>>());
+        l = r;
+        r = new_r;
+    }
+    let mut out = Zeroizing::new(Vec::with_capacity(input.len()));
+    out.extend_from_slice(&r);
+    out.extend_from_slice(&l);
+    out
+}
+
+fn encrypt(master_secret: &[u8], passphrase: &[u8], e: u8, identifier: u16, extendable: bool) -> Zeroizing<Vec<u8>> {
+    feistel(master_secret, passphrase, e, identifier, extendable, &[0, 1, 2, 3])
+}
+
+fn decrypt(ems: &[u8], passphrase: &[u8], e: u8, identifier: u16, extendable: bool) -> Zeroizing<Vec<u8>> {
+    feistel(ems, passphrase, e, identifier, extendable, &[3, 2, 1, 0])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Reference vectors from the SLIP-39 specification (passphrase "TREZOR").
+    const PASSPHRASE: &[u8] = b"TREZOR";
+
+    #[test]
+    fn wordlist_is_sorted_and_complete() {
+        let words = wordlist();
+        assert_eq!(words.len(), RADIX as usize);
+        assert!(words.windows(2).all(|w| w[0] < w[1]));
+    }
+
+    #[test]
+    fn recovers_single_share_vector() {
+        let m = "duckling enlarge academic academic agency result length solution fridge kidney coal piece deal husband erode duke ajar critical decision keyboard";
+        let secret = combine_mnemonics(&[m], PASSPHRASE).unwrap();
+        assert_eq!(hex::encode(&*secret), "bb54aac4b89dc868ba37d9cc21b2cece");
+    }
+
+    #[test]
+    fn rejects_bad_checksum_and_padding() {
+        let bad_checksum = "duckling enlarge academic academic agency result length solution fridge kidney coal piece deal husband erode duke ajar critical decision kidney";
+        let bad_padding = "duckling enlarge academic academic email result length solution fridge kidney coal piece deal husband erode duke ajar music cargo fitness";
+        assert!(Share::from_mnemonic(bad_checksum).is_err());
+        assert!(Share::from_mnemonic(bad_padding).is_err());
+    }
+
+    #[test]
+    fn recovers_two_of_three_vector() {
+        let shares = [
+            "shadow pistol academic always adequate wildlife fancy gross oasis cylinder mustang wrist rescue view short owner flip making coding armed",
+            "shadow pistol academic acid actress prayer class unknown daughter sweater depict flip twice unkind craft early superior advocate guest smoking",
+        ];
+        let secret = combine_mnemonics(&shares, PASSPHRASE).unwrap();
+        assert_eq!(hex::encode(&*secret), "b43ceb7e57a0ea8766221624d01b0864");
+        assert!(combine_mnemonics(&shares[..1], PASSPHRASE).is_err());
+    }
+
+    #[test]
+    fn recovers_multi_group_vector_and_rejects_bad_digest() {
+        let shares = [
+            "eraser senior beard romp adorn nuclear spill corner cradle style ancient family general leader ambition exchange unusual garlic promise voice",
+            "eraser senior acrobat romp bishop medical gesture pumps secret alive ultimate quarter priest subject class dictate spew material endless market",
+        ];
+        let secret = combine_mnemonics(&shares, PASSPHRASE).unwrap();
+        assert_eq!(hex::encode(&*secret), "7c3397a292a5941682d7a4ae2d898d11");
+
+        let invalid_digest = [
+            "guilt walnut academic acid deliver remove equip listen vampire tactics nylon rhythm failure husband fatigue alive blind enemy teaspoon rebound",
+            "guilt walnut academic agency brave hamster hobo declare herd taste alpha slim criminal mild arcade formal romp branch pink ambition",
+        ];
+        assert!(combine_mnemonics(&invalid_digest, PASSPHRASE).is_err());
+    }
+
+    #[test]
+    fn split_then_combine_round_trips() {
+        let secret = hex::decode("989baf9dcaad5b10ca33dfd8cc75e42477025dce88ae83e75a230086a0e00e92").unwrap();
+        let groups = generate_mnemonics(1, &[(3, 5)], &secret, b"", true, 0).unwrap();
+        assert_eq!(groups.len(), 1);
+        assert_eq!(groups[0].len(), 5);
+
+        let picked = [&groups[0][4], &groups[0][0], &groups[0][2]];
+        let recovered = combine_mnemonics(&picked, b"").unwrap();
+        assert_eq!(&*recovered, &secret);
+
+        let mut set = ShareSet::new();
+        set.add(Share::from_mnemonic(&groups[0][1]).unwrap()).unwrap();
+        assert!(set.add(Share::from_mnemonic(&groups[0][1]).unwrap()).is_err(), "duplicate must be rejected");
+        assert!(!set.is_complete());
+    }
+}
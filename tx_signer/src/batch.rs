@@ -0,0 +1,483 @@
+//! `tx_signer batch`: sign a directory of unsigned payloads with one key unlock.
+//!
+//! Payloads are checked in file name order. A file that cannot be parsed, has no matching key,
+//! conflicts with the nonce ledger or reuses a nonce of an earlier file in the batch is
+//! reported and skipped. The first policy or spending limit violation stops the batch: that
+//! file and every later one are left unsigned. After one confirmation for the whole batch,
+//! signatures are computed in parallel. Each one is verified and recorded in the spending and
+//! nonce ledgers before its file is written, and a `manifest.json` with per-file status,
+//! nonces and transaction hashes is written next to the signed files.
+
+use crate::key_source::{KeyArgs, LoadedKey};
+use crate::keyring::Keyring;
+use crate::nonce_guard::{self, NonceCheck, NonceLedger};
+use crate::policy::{self, Policy};
+use crate::review::{batch_digest, confirm_on_tty, skip_confirmation, Reviewer};
+use crate::spending::Ledger;
+use crate::verify::verify_signed;
+use crate::{envelope_for, parse_unsigned, split_envelope, unsigned_to_typed, UnsignedTx};
+use ethers::types::{transaction::eip2718::TypedTransaction, Address, Signature, H256, U256};
+use eyre::{eyre, Result};
+use serde::Serialize;
+use tx_envelope::Format;
+use std::collections::HashMap;
+use std::{fs, path::{Path, PathBuf}};
+
+pub const MANIFEST_FILE: &str = "manifest.json";
+
+#[derive(clap::Args, Debug)]
+pub struct BatchArgs {
+    #[command(flatten)]
+    key: KeyArgs,
+
+    /// Directory of unsigned payloads (hex or envelope JSON), signed in file name order
+    #[arg(long)]
+    input_dir: PathBuf,
+
+    /// Directory for the signed transactions (same file names) and manifest.json
+    #[arg(long)]
+    output_dir: PathBuf,
+
+    /// Signing policy (TOML) every transaction must satisfy; the batch stops at the first violation
+    #[arg(long)]
+    policy: Option<PathBuf>,
+
+    /// Create the spending state file named by the policy, or accept it while it has no recorded spends
+    #[arg(long, requires = "policy")]
+    init_spending_state: bool,
+
+    /// Ledger of signed (chain, sender, nonce) payloads [default: ~/.tx_signer/nonce_ledger.jsonl]
+    #[arg(long)]
+    nonce_ledger: Option<PathBuf>,
+
+    /// Address labels for the review screen (TOML table of "0xaddress" = "label")
+    #[arg(long)]
+    address_book: Option<PathBuf>,
+
+    /// JSON ABI used to decode calldata on the review screen (repeatable)
+    #[arg(long)]
+    abi: Vec<PathBuf>,
+
+    /// Skip the typed confirmation; needs a --policy with allow_unattended = true
+    #[arg(long, requires = "policy")]
+    yes: bool,
+
+    /// Also write a signed-output envelope per payload as <file>.envelope.json or .cbor
+    #[arg(long, value_enum)]
+    envelope_format: Option<Format>,
+}
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Status {
+    Signed,
+    Failed,
+    /// Not attempted because the batch stopped at a policy violation.
+    Skipped,
+}
+
+#[derive(Serialize, Debug)]
+pub struct ManifestEntry {
+    pub file: String,
+    pub status: Status,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub from: Option<Address>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub chain_id: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub nonce: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tx_hash: Option<H256>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct Manifest {
+    pub signed: usize,
+    pub failed: usize,
+    pub skipped: usize,
+    /// File whose policy violation stopped the batch.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stopped_at: Option<String>,
+    pub transactions: Vec<ManifestEntry>,
+}
+
+/// One key for every payload, or a keyring choosing per sender.
+enum Keys {
+    Single(LoadedKey),
+    Ring(Keyring, Option<Address>),
+}
+
+impl Keys {
+    /// Unlock (once) the key for a payload whose envelope names `sender`, if any.
+    fn unlock(&mut self, sender: Option<Address>) -> Result<Address> {
+        match self {
+            Keys::Single(key) => match sender {
+                Some(sender) if sender != key.address() => Err(eyre!(
+                    "Payload is for sender {:?}, but the key belongs to {:?}",
+                    sender,
+                    key.address()
+                )),
+                _ => Ok(key.address()),
+            },
+            Keys::Ring(keyring, default) => {
+                let sender = sender
+                    .or(*default)
+                    .ok_or_else(|| eyre!("No sender: use a payload envelope or --from with --keyring"))?;
+                Ok(keyring.key_for(sender)?.address())
+            }
+        }
+    }
+
+    fn get(&self, address: Address) -> &LoadedKey {
+        match self {
+            Keys::Single(key) => key,
+            Keys::Ring(keyring, _) => keyring.get(address).expect("key unlocked while preparing"),
+        }
+    }
+}
+
+struct Prepared {
+    idx: usize,
+    payload: Vec<u8>,
+    utx: UnsignedTx,
+    typed: TypedTransaction,
+    from: Address,
+    nonce_check: NonceCheck,
+}
+
+fn input_files(dir: &Path) -> Result<Vec<PathBuf>> {
+    let mut files: Vec<PathBuf> = fs::read_dir(dir)
+        .map_err(|e| eyre!("Cannot read input directory {}: {}", dir.display(), e))?
+        .map(|entry| entry.map(|e| e.path()))
+        .collect::<std::io::Result<Vec<_>>>()?
+        .into_iter()
+        .filter(|p| p.is_file() && !p.file_name().and_then(|n| n.to_str()).is_some_and(|n| n.starts_with('.')))
+        .collect();
+    files.sort();
+    if files.is_empty() {
+        return Err(eyre!("No payloads in {}", dir.display()));
+    }
+    Ok(files)
+}
+
+fn read_payload(path: &Path) -> Result<(Vec<u8>, UnsignedTx, Option<Address>)> {
+    let (hex_payload, sender) = split_envelope(&fs::read_to_string(path)?)?;
+    let payload = hex::decode(hex_payload.trim().trim_start_matches("0x"))?;
+    let utx = parse_unsigned(&payload)?;
+    Ok((payload, utx, sender))
+}
+
+/// Sign every job, spreading them over the available cores. PKCS#11 keys serialize on
+/// their session, so they are safe to share as well.
+fn sign_all(jobs: &[(&LoadedKey, &TypedTransaction)]) -> Vec<Result<Signature>> {
+    let workers = std::thread::available_parallelism().map_or(1, |n| n.get());
+    let chunk = jobs.len().div_ceil(workers).max(1);
+    std::thread::scope(|scope| {
+        let handles: Vec<_> = jobs
+            .chunks(chunk)
+            .map(|chunk| scope.spawn(move || chunk.iter().map(|(key, tx)| key.sign_transaction(tx)).collect::<Vec<_>>()))
+            .collect();
+        handles.into_iter().flat_map(|h| h.join().expect("signing thread panicked")).collect()
+    })
+}
+
+impl Manifest {
+    fn new(files: &[PathBuf]) -> Self {
+        let transactions = files
+            .iter()
+            .map(|p| ManifestEntry {
+                file: p.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default(),
+                status: Status::Skipped,
+                from: None,
+                chain_id: None,
+                nonce: None,
+                tx_hash: None,
+                error: None,
+            })
+            .collect();
+        Manifest { signed: 0, failed: 0, skipped: 0, stopped_at: None, transactions }
+    }
+
+    fn fail(&mut self, idx: usize, error: &eyre::Report) {
+        let entry = &mut self.transactions[idx];
+        println!("FAILED  {}: {}", entry.file, error);
+        entry.status = Status::Failed;
+        entry.error = Some(error.to_string());
+    }
+
+    /// Mark `idx` as the violating file; it and everything after it stay unsigned.
+    fn stop(&mut self, idx: usize, error: &eyre::Report) {
+        self.fail(idx, error);
+        println!("Policy violation in {}; stopping the batch", self.transactions[idx].file);
+        self.stopped_at = Some(self.transactions[idx].file.clone());
+    }
+
+    fn finish(&mut self) {
+        let count = |s| self.transactions.iter().filter(|t| t.status == s).count();
+        (self.signed, self.failed, self.skipped) = (count(Status::Signed), count(Status::Failed), count(Status::Skipped));
+    }
+}
+
+pub fn run(mut args: BatchArgs) -> Result<()> {
+    let files = input_files(&args.input_dir)?;
+    fs::create_dir_all(&args.output_dir)?;
+    println!("Batch of {} payloads from {}", files.len(), args.input_dir.display());
+
+    let mut keys = match args.key.keyring.take() {
+        Some(dir) => Keys::Ring(Keyring::open(&dir, args.key.keyring_password_env.take())?, args.key.from),
+        None => Keys::Single(args.key.load()?),
+    };
+    let policy = args.policy.as_deref().map(Policy::load).transpose()?;
+    let nonce_ledger_path = args
+        .nonce_ledger
+        .clone()
+        .or_else(nonce_guard::default_ledger_path)
+        .ok_or_else(|| eyre!("Cannot locate a nonce ledger: set HOME or pass --nonce-ledger"))?;
+    let mut nonce_ledger = NonceLedger::open(&nonce_ledger_path)?;
+    let mut manifest = Manifest::new(&files);
+
+    // 1) Parse, select the key and run the stateless checks, in order.
+    let mut prepared = Vec::new();
+    // The nonce ledger is only written after signing, so it cannot see conflicts within the batch.
+    let mut batch_nonces: HashMap<(U256, Address, U256), usize> = HashMap::new();
+    for (idx, path) in files.iter().enumerate() {
+        let step = (|| -> Result<Prepared> {
+            let (payload, utx, sender) = read_payload(path)?;
+            let from = keys.unlock(sender)?;
+            let entry = &mut manifest.transactions[idx];
+            (entry.from, entry.chain_id, entry.nonce) = (Some(from), Some(utx.chain_id_u64()?), Some(utx.nonce().low_u64()));
+            let typed = unsigned_to_typed(&utx);
+            let nonce_check = nonce_ledger.check(&utx, from, typed.sighash(), false)?;
+            let slot = (utx.chain_id(), from, utx.nonce());
+            if let Some(&earlier) = batch_nonces.get(&slot) {
+                return Err(eyre!("Nonce {} for {:?} is already used by {} in this batch", utx.nonce(), from, manifest.transactions[earlier].file));
+            }
+            batch_nonces.insert(slot, idx);
+            Ok(Prepared { idx, payload, utx, typed, from, nonce_check })
+        })();
+        match step {
+            Ok(p) => {
+                if let Some(policy) = &policy
+                    && let Err(e) = policy::enforce(policy, &p.utx)
+                {
+                    manifest.stop(idx, &e);
+                    break;
+                }
+                prepared.push(p);
+            }
+            Err(e) => manifest.fail(idx, &e),
+        }
+    }
+
+    // 2) Review and one confirmation for the whole batch.
+    if !prepared.is_empty() {
+        let reviewer = Reviewer::new(args.address_book.as_deref(), &args.abi)?;
+        for p in &prepared {
+            println!("[{}]\n{}", manifest.transactions[p.idx].file, reviewer.render(&p.utx, p.from));
+        }
+        if args.yes {
+            skip_confirmation(policy.as_ref())?;
+        } else {
+            let digest = format!("{:x}", batch_digest(prepared.iter().map(|p| &p.utx)));
+            println!("Batch digest over every recipient and amount above: 0x{digest}");
+            confirm_on_tty(
+                &format!("Type the last 4 hex characters of the batch digest to sign all {} transactions: ", prepared.len()),
+                &digest[digest.len() - 4..],
+            )?;
+        }
+    }
+
+    // 3) Spending budgets accumulate over the batch, so they are checked and reserved in order.
+    // The nonce ledger is unsigned, so it never exempts a payload from the spending check.
+    let mut spending = match policy.as_ref().and_then(|p| p.spending.as_ref()) {
+        Some(limits) => Some((limits, Ledger::open(&limits.state_file, args.init_spending_state)?)),
+        None => None,
+    };
+    let mut approved = Vec::new();
+    for p in prepared {
+        if let Some((limits, ledger)) = &mut spending {
+            let sighash = p.typed.sighash();
+            if let Err(e) = limits.check(ledger, &p.utx, p.from, sighash, None).and_then(|_| limits.reserve(ledger, &p.utx, p.from, sighash)) {
+                manifest.stop(p.idx, &e);
+                break;
+            }
+        }
+        approved.push(p);
+    }
+
+    // 4) Sign in parallel. Each signature is verified and recorded in the ledgers before its
+    // file is written, so nothing unsigned or unverified is ever recorded.
+    let jobs: Vec<(&LoadedKey, &TypedTransaction)> = approved.iter().map(|p| (keys.get(p.from), &p.typed)).collect();
+    for (p, sig) in approved.iter().zip(sign_all(&jobs)) {
+        let written = sig.and_then(|sig| {
+            let raw = p.typed.rlp_signed(&sig);
+            let tx_hash = verify_signed(&raw, &p.utx, p.from)?;
+            if let Some((limits, ledger)) = &mut spending {
+                limits.record(ledger, &p.utx, keys.get(p.from), p.from, p.typed.sighash(), None)?;
+            }
+            if p.nonce_check != NonceCheck::AlreadySigned {
+                nonce_ledger.record(&p.utx, p.from, p.typed.sighash())?;
+            }
+            let file = &manifest.transactions[p.idx].file;
+            fs::write(args.output_dir.join(file), hex::encode(&raw))?;
+            if let Some(format) = args.envelope_format {
+                let path = args.output_dir.join(format!("{file}.envelope.{}", format.extension()));
+                envelope_for(raw, &p.utx, p.from, &p.payload)?.write(&path, format)?;
+            }
+            Ok(tx_hash)
+        });
+        match written {
+            Ok(tx_hash) => {
+                let entry = &mut manifest.transactions[p.idx];
+                println!("OK      {}: nonce {} tx {:?}", entry.file, p.utx.nonce(), tx_hash);
+                entry.status = Status::Signed;
+                entry.tx_hash = Some(tx_hash);
+            }
+            Err(e) => manifest.fail(p.idx, &e),
+        }
+    }
+
+    manifest.finish();
+    let manifest_path = args.output_dir.join(MANIFEST_FILE);
+    fs::write(&manifest_path, serde_json::to_string_pretty(&manifest)?)?;
+    println!(
+        "Signed {}, failed {}, skipped {}; manifest written to {}",
+        manifest.signed,
+        manifest.failed,
+        manifest.skipped,
+        manifest_path.display()
+    );
+    if manifest.failed + manifest.skipped > 0 {
+        return Err(eyre!("Batch incomplete: {} of {} payloads not signed", manifest.failed + manifest.skipped, files.len()));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::Parser;
+    use ethers::signers::{LocalWallet, Signer};
+    use ethers::utils::parse_units;
+    use std::str::FromStr;
+
+    const KEY: &str = "4c0883a69102937d6231471b5ecb4765d5e97f8e4dc6e8fa6a4de3b8a3a2f55b";
+
+    #[derive(Parser)]
+    struct Cli {
+        #[command(flatten)]
+        args: BatchArgs,
+    }
+
+    fn transfer(chain_id: u64, nonce: u64, value_eth: &str) -> UnsignedTx {
+        UnsignedTx::Legacy {
+            nonce: nonce.into(),
+            gas_price: 1_000_000_000u64.into(),
+            gas_limit: 21_000u64.into(),
+            to: Some(Address::repeat_byte(0x11)),
+            value: parse_units(value_eth, "ether").unwrap().into(),
+            data: vec![],
+            chain_id: chain_id.into(),
+        }
+    }
+
+    /// Write the payloads and a policy into a fresh directory and run the batch with `--yes`.
+    fn run_batch(name: &str, payloads: &[(&str, UnsignedTx)], policy: &str) -> (PathBuf, Result<()>, serde_json::Value) {
+        let dir = std::env::temp_dir().join(format!("tx_signer_batch_{}_{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("in")).unwrap();
+        for (file, utx) in payloads {
+            fs::write(dir.join("in").join(file), hex::encode(unsigned_to_typed(utx).rlp())).unwrap();
+        }
+        fs::write(dir.join("key"), KEY).unwrap();
+        fs::write(dir.join("policy.toml"), format!("allow_unattended = true\n{policy}").replace("STATE", &dir.join("spending.jsonl").display().to_string())).unwrap();
+        let path = |p: &str| dir.join(p).display().to_string();
+        let cli = Cli::try_parse_from([
+            "batch",
+            "--private-key-file", &path("key"),
+            "--input-dir", &path("in"),
+            "--output-dir", &path("out"),
+            "--policy", &path("policy.toml"),
+            "--nonce-ledger", &path("nonces.jsonl"),
+            "--init-spending-state",
+            "--yes",
+        ])
+        .unwrap();
+        let result = run(cli.args);
+        let manifest = serde_json::from_str(&fs::read_to_string(dir.join("out").join(MANIFEST_FILE)).unwrap()).unwrap();
+        (dir, result, manifest)
+    }
+
+    fn statuses(manifest: &serde_json::Value) -> Vec<&str> {
+        manifest["transactions"].as_array().unwrap().iter().map(|t| t["status"].as_str().unwrap()).collect()
+    }
+
+    #[test]
+    fn refuses_nonce_reuse_and_stops_at_policy_violation() {
+        let payloads = [
+            ("01", transfer(137, 0, "0.1")),
+            ("02", transfer(137, 0, "0.2")),
+            ("03", transfer(137, 1, "0.1")),
+            ("04", transfer(1, 2, "0.1")),
+            ("05", transfer(137, 2, "0.1")),
+        ];
+        let (dir, result, manifest) = run_batch("policy", &payloads, "allowed_chain_ids = [137]");
+        assert!(result.is_err());
+        assert_eq!(statuses(&manifest), ["signed", "failed", "signed", "failed", "skipped"]);
+        assert!(manifest["transactions"][1]["error"].as_str().unwrap().contains("already used by 01"), "{manifest}");
+        assert_eq!(manifest["stopped_at"], "04");
+        assert!(!dir.join("out/02").exists() && !dir.join("out/05").exists());
+
+        // Only the signed payloads reached the nonce ledger.
+        let ledger = fs::read_to_string(dir.join("nonces.jsonl")).unwrap();
+        assert_eq!(ledger.lines().count(), 2, "{ledger}");
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn spending_budget_accumulates_and_stops_the_batch() {
+        let payloads = [
+            ("01", transfer(137, 0, "0.4")),
+            ("02", transfer(137, 1, "0.4")),
+            ("03", transfer(137, 2, "0.4")),
+            ("04", transfer(137, 3, "0.1")),
+        ];
+        let policy = "[spending]\nstate_file = \"STATE\"\n[[spending.limit]]\nchain_id = 137\nasset = \"native\"\ndaily = \"1\"";
+        let (dir, result, manifest) = run_batch("spending", &payloads, policy);
+        assert!(result.is_err());
+        assert_eq!(statuses(&manifest), ["signed", "signed", "failed", "skipped"]);
+        assert_eq!(manifest["stopped_at"], "03");
+
+        let limits = Policy::load(&dir.join("policy.toml")).unwrap().spending.unwrap();
+        let ledger = Ledger::open(&limits.state_file, false).unwrap();
+        let from = LocalWallet::from_str(KEY).unwrap().address();
+        assert!(limits.check(&ledger, &transfer(137, 9, "0.2"), from, H256::zero(), None).is_ok());
+        assert!(limits.check(&ledger, &transfer(137, 9, "0.3"), from, H256::zero(), None).is_err());
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn parallel_signing_matches_sequential() {
+        let wallet = LocalWallet::from_str("4c0883a69102937d6231471b5ecb4765d5e97f8e4dc6e8fa6a4de3b8a3a2f55b").unwrap();
+        let key = LoadedKey::Local(wallet.clone());
+        let txs: Vec<TypedTransaction> = (0..20u64)
+            .map(|nonce| {
+                unsigned_to_typed(&UnsignedTx::Legacy {
+                    nonce: nonce.into(),
+                    gas_price: 1_000_000_000u64.into(),
+                    gas_limit: 21_000u64.into(),
+                    to: Some(Address::repeat_byte(0x11)),
+                    value: 1u64.into(),
+                    data: vec![],
+                    chain_id: 137u64.into(),
+                })
+            })
+            .collect();
+        let jobs: Vec<_> = txs.iter().map(|tx| (&key, tx)).collect();
+        for (tx, sig) in txs.iter().zip(sign_all(&jobs)) {
+            assert_eq!(sig.unwrap(), wallet.sign_transaction_sync(tx).unwrap());
+        }
+    }
+}
@@ -0,0 +1,67 @@
+//! The ERC-20 calls the signer understands: `transfer`, `transferFrom`, `approve` and
+//! `increaseAllowance`. The review screen, the policy and the spending limits all read the
+//! token counterparty and amount of a call from here.
+
+use ethers::types::{Address, U256};
+use ethers::utils::id;
+
+/// Calldata word holding the counterparty, word holding the amount, and who the counterparty is.
+fn layout(data: &[u8]) -> Option<(usize, usize, &'static str)> {
+    let selector = data.get(..4)?;
+    if selector == id("transfer(address,uint256)") {
+        Some((0, 1, "token recipient"))
+    } else if selector == id("transferFrom(address,address,uint256)") {
+        Some((1, 2, "token recipient"))
+    } else if selector == id("approve(address,uint256)") || selector == id("increaseAllowance(address,uint256)") {
+        Some((0, 1, "spender"))
+    } else {
+        None
+    }
+}
+
+fn word(data: &[u8], index: usize) -> Option<&[u8]> {
+    data.get(4 + index * 32..4 + (index + 1) * 32)
+}
+
+/// Recipient of `transfer`/`transferFrom`, or spender of `approve`/`increaseAllowance`.
+/// `None` for other or malformed calldata.
+pub fn counterparty(data: &[u8]) -> Option<(Address, &'static str)> {
+    let (index, _, party) = layout(data)?;
+    let word = word(data, index)?;
+    word[..12].iter().all(|&b| b == 0).then(|| (Address::from_slice(&word[12..]), party))
+}
+
+/// Amount the call moves or lets the spender move. An unlimited approval is `U256::MAX`.
+pub fn amount(data: &[u8]) -> Option<U256> {
+    let (_, index, _) = layout(data)?;
+    word(data, index).map(U256::from_big_endian)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ethers::abi::{encode, Token};
+
+    fn call(signature: &str, args: &[Token]) -> Vec<u8> {
+        [&id(signature)[..], &encode(args)].concat()
+    }
+
+    #[test]
+    fn decodes_counterparty_and_amount() {
+        let (owner, payee) = (Address::repeat_byte(0x11), Address::repeat_byte(0x22));
+        let transfer_from = call(
+            "transferFrom(address,address,uint256)",
+            &[Token::Address(owner), Token::Address(payee), Token::Uint(1_234_567u64.into())],
+        );
+        assert_eq!(counterparty(&transfer_from), Some((payee, "token recipient")));
+        assert_eq!(amount(&transfer_from), Some(U256::from(1_234_567u64)));
+
+        let approve = call("increaseAllowance(address,uint256)", &[Token::Address(payee), Token::Uint(U256::MAX)]);
+        assert_eq!(counterparty(&approve), Some((payee, "spender")));
+        assert_eq!(amount(&approve), Some(U256::MAX));
+
+        assert_eq!(counterparty(&id("approve(address,uint256)")), None);
+        assert_eq!(amount(&id("approve(address,uint256)")), None);
+        assert_eq!(amount(&call("balanceOf(address)", &[Token::Address(owner)])), None);
+    }
+}
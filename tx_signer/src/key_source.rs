@@ -0,0 +1,555 @@
+//! Private key sources for the signer.
+//!
+//! Key material is read straight into a `SecretBytes` buffer, which is mlock'ed (so it is
+//! never swapped out), excluded from core dumps where supported, and zeroized on drop. That
+//! includes the terminal prompt and environment variables on Unix; only `--private-key`
+//! (argv) goes through an ordinary `String` first, which is wiped once copied.
+//! The hex form is decoded into a second locked buffer and only then handed to k256.
+//! With `--pkcs11-module` no key material is read at all; signing happens on the token.
+//! With `--keyring` the key is picked by sender address from a directory of keys.
+
+use crate::keyring::Keyring;
+use crate::pkcs11::Pkcs11Key;
+use coins_bip32::prelude::{DerivedXPriv, Parent, SigningKey};
+use cryptoki::types::AuthPin;
+use ethers::signers::{to_eip155_v, LocalWallet, Signer};
+use ethers::types::{transaction::eip2718::TypedTransaction, Address, Signature, H256};
+use eyre::{eyre, Result};
+use std::{fs::File, io::Read, path::PathBuf};
+use tx_keygen::slip39::{Share, ShareSet};
+use zeroize::{Zeroize, Zeroizing};
+
+/// Upper bound for anything we accept as a hex key (64 hex chars plus 0x and whitespace).
+const MAX_KEY_INPUT_BYTES: usize = 4096;
+
+/// Fixed-size heap buffer that is locked in RAM and wiped on drop.
+/// The allocation never grows, so no unlocked copies are left behind by reallocation.
+pub struct SecretBytes {
+    buf: Box<[u8]>,
+    len: usize,
+    locked: bool,
+}
+
+impl SecretBytes {
+    pub fn zeroed(capacity: usize) -> Self {
+        let buf = vec![0u8; capacity].into_boxed_slice();
+        let locked = lock_memory(&buf);
+        SecretBytes { buf, len: 0, locked }
+    }
+
+    /// Read at most `MAX_KEY_INPUT_BYTES` from `reader` directly into a locked buffer.
+    pub fn read_from(reader: impl Read) -> Result<Self> {
+        Self::read_until(reader, None)
+    }
+
+    /// Like [`SecretBytes::read_from`], but stop at the first newline (which is dropped).
+    /// Reads one byte at a time so nothing past the line is consumed.
+    pub fn read_line_from(reader: impl Read) -> Result<Self> {
+        Self::read_until(reader, Some(b'\n'))
+    }
+
+    fn read_until(mut reader: impl Read, stop: Option<u8>) -> Result<Self> {
+        let mut secret = SecretBytes::zeroed(MAX_KEY_INPUT_BYTES + 1);
+        loop {
+            let end = if stop.is_some() { secret.len + 1 } else { secret.buf.len() };
+            let n = reader.read(&mut secret.buf[secret.len..end])?;
+            if n == 0 {
+                break;
+            }
+            secret.len += n;
+            if stop.is_some_and(|stop| secret.buf[secret.len - 1] == stop) {
+                secret.len -= 1;
+                break;
+            }
+            if secret.len > MAX_KEY_INPUT_BYTES {
+                return Err(eyre!("Key input exceeds {} bytes", MAX_KEY_INPUT_BYTES));
+            }
+        }
+        Ok(secret)
+    }
+
+    /// Copy a string into a locked buffer and wipe the original.
+    pub fn from_string(mut s: String) -> Self {
+        let mut secret = SecretBytes::zeroed(s.len());
+        secret.buf.copy_from_slice(s.as_bytes());
+        secret.len = s.len();
+        s.zeroize();
+        secret
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.buf[..self.len]
+    }
+
+    /// Decode a hex private key (optional 0x prefix, surrounding whitespace ignored)
+    /// into a locked 32-byte buffer.
+    pub fn decode_hex_key(&self) -> Result<SecretBytes> {
+        let text = self.as_bytes().trim_ascii();
+        let text = text.strip_prefix(b"0x").unwrap_or(text);
+        if text.len() != 64 {
+            return Err(eyre!("Private key must be 32 bytes of hex (got {} hex chars)", text.len()));
+        }
+        let mut key = SecretBytes::zeroed(32);
+        hex::decode_to_slice(text, &mut key.buf).map_err(|e| eyre!("Invalid private key hex: {}", e))?;
+        key.len = 32;
+        Ok(key)
+    }
+}
+
+impl Drop for SecretBytes {
+    fn drop(&mut self) {
+        self.buf.zeroize();
+        if self.locked {
+            unlock_memory(&self.buf);
+        }
+    }
+}
+
+#[cfg(unix)]
+fn lock_memory(buf: &[u8]) -> bool {
+    if buf.is_empty() {
+        return false;
+    }
+    // SAFETY: the range is a live allocation owned by the caller.
+    let ok = unsafe { libc::mlock(buf.as_ptr().cast(), buf.len()) } == 0;
+    if !ok {
+        eprintln!("Warning: mlock failed ({}); key buffer may be swapped", std::io::Error::last_os_error());
+    }
+    #[cfg(target_os = "linux")]
+    // SAFETY: as above; MADV_DONTDUMP only affects core dump contents. It operates on
+    // whole pages, so round the start down to a page boundary.
+    unsafe {
+        let page = libc::sysconf(libc::_SC_PAGESIZE) as usize;
+        let start = buf.as_ptr() as usize & !(page - 1);
+        let len = buf.as_ptr() as usize + buf.len() - start;
+        libc::madvise(start as *mut libc::c_void, len, libc::MADV_DONTDUMP);
+    }
+    ok
+}
+
+#[cfg(unix)]
+fn unlock_memory(buf: &[u8]) {
+    // SAFETY: the range was locked by `lock_memory` and is still allocated.
+    unsafe {
+        libc::munlock(buf.as_ptr().cast(), buf.len());
+    }
+}
+
+#[cfg(not(unix))]
+fn lock_memory(_buf: &[u8]) -> bool {
+    false
+}
+
+#[cfg(not(unix))]
+fn unlock_memory(_buf: &[u8]) {}
+
+/// Key source flags shared by every signing command.
+#[derive(clap::Args, Debug)]
+pub struct KeyArgs {
+    /// Private key (hex) on the command line. Refused unless --insecure-argv-key is given.
+    #[arg(long, group = "key")]
+    pub private_key: Option<String>,
+
+    /// Allow --private-key even though argv is visible to other processes
+    #[arg(long, requires = "private_key")]
+    pub insecure_argv_key: bool,
+
+    /// Read the private key (hex) from a file; "-" reads stdin
+    #[arg(long, group = "key")]
+    pub private_key_file: Option<PathBuf>,
+
+    /// Read the private key (hex) from an inherited file descriptor
+    #[arg(long, group = "key")]
+    pub private_key_fd: Option<i32>,
+
+    /// Read the private key (hex) from this environment variable, then unset it
+    #[arg(long, group = "key")]
+    pub private_key_env: Option<String>,
+
+    /// Reconstruct the seed from SLIP-39 share phrases entered one at a time on the terminal
+    #[arg(long, group = "key")]
+    pub slip39: bool,
+
+    /// BIP-32 derivation path applied to the recovered SLIP-39 seed
+    #[arg(long, default_value = "m/44'/60'/0'/0/0", requires = "slip39")]
+    pub derivation_path: String,
+
+    /// Sign on a PKCS#11 token (HSM) through this module, e.g. /usr/lib/softhsm/libsofthsm2.so
+    #[arg(long, group = "key", requires = "key_label")]
+    pub pkcs11_module: Option<PathBuf>,
+
+    /// Label of the secp256k1 key pair on the PKCS#11 token
+    #[arg(long, requires = "pkcs11_module")]
+    pub key_label: Option<String>,
+
+    /// Read the PKCS#11 user PIN from this environment variable instead of prompting
+    #[arg(long, requires = "pkcs11_module")]
+    pub pkcs11_pin_env: Option<String>,
+
+    /// Directory of keystores and derivation paths; the key matching the sender is used
+    #[arg(long, group = "key")]
+    pub keyring: Option<PathBuf>,
+
+    /// Read the keyring password from this environment variable instead of prompting
+    #[arg(long, requires = "keyring")]
+    pub keyring_password_env: Option<String>,
+
+    /// Address that must sign. Selects the key from --keyring; any other key source is refused
+    /// if its address differs
+    #[arg(long)]
+    pub from: Option<Address>,
+}
+
+impl KeyArgs {
+    /// Load the key and make sure it belongs to the expected sender (`--from`), if one is set.
+    pub fn load(&mut self) -> Result<LoadedKey> {
+        let key = self.source()?.load()?;
+        if let Some(from) = self.from
+            && key.address() != from
+        {
+            return Err(eyre!("Key belongs to {:?}, but the sender is {:?}; refusing to sign", key.address(), from));
+        }
+        Ok(key)
+    }
+
+    /// Pick the key source, refusing argv keys without --insecure-argv-key.
+    pub fn source(&mut self) -> Result<KeySource> {
+        if let Some(key) = self.private_key.take() {
+            if !self.insecure_argv_key {
+                let mut key = key;
+                key.zeroize();
+                return Err(eyre!(
+                    "Refusing --private-key: command-line arguments are visible to other processes and shell history. \
+                     Use --private-key-file, --private-key-fd, --private-key-env or the terminal prompt, \
+                     or pass --insecure-argv-key to override"
+                ));
+            }
+            eprintln!("Warning: private key was passed on the command line (--insecure-argv-key)");
+            return Ok(KeySource::Argv(key));
+        }
+        Ok(if let Some(path) = self.private_key_file.take() {
+            KeySource::File(path)
+        } else if let Some(fd) = self.private_key_fd {
+            KeySource::Fd(fd)
+        } else if let Some(name) = self.private_key_env.take() {
+            KeySource::Env(name)
+        } else if let Some(module) = self.pkcs11_module.take() {
+            KeySource::Pkcs11 {
+                module,
+                label: self.key_label.take().ok_or_else(|| eyre!("--pkcs11-module needs --key-label"))?,
+                pin_env: self.pkcs11_pin_env.take(),
+            }
+        } else if let Some(dir) = self.keyring.take() {
+            KeySource::Keyring {
+                dir,
+                password_env: self.keyring_password_env.take(),
+                sender: self.from.ok_or_else(|| eyre!("--keyring needs the sender: pass --from or a payload envelope"))?,
+            }
+        } else if self.slip39 {
+            KeySource::Slip39 { derivation_path: self.derivation_path.clone() }
+        } else {
+            KeySource::Prompt
+        })
+    }
+}
+
+/// Where the signing key comes from.
+pub enum KeySource {
+    /// `--private-key` on the command line (only with `--insecure-argv-key`)
+    Argv(String),
+    /// `--private-key-file`; `-` reads stdin
+    File(PathBuf),
+    /// `--private-key-fd`, an inherited file descriptor
+    Fd(i32),
+    /// `--private-key-env`, removed from the environment once read
+    Env(String),
+    /// `--slip39` share entry
+    Slip39 { derivation_path: String },
+    /// `--pkcs11-module` and `--key-label`; the key stays on the token
+    Pkcs11 { module: PathBuf, label: String, pin_env: Option<String> },
+    /// `--keyring`; the key whose address is `sender`
+    Keyring { dir: PathBuf, password_env: Option<String>, sender: Address },
+    /// Hidden prompt on the controlling terminal
+    Prompt,
+}
+
+/// A signing key ready for use: in process memory, or held by a PKCS#11 token.
+pub enum LoadedKey {
+    Local(LocalWallet),
+    Pkcs11(Pkcs11Key),
+}
+
+impl LoadedKey {
+    pub fn address(&self) -> Address {
+        match self {
+            LoadedKey::Local(wallet) => wallet.address(),
+            LoadedKey::Pkcs11(key) => key.address(),
+        }
+    }
+
+    /// Sign a 32-byte digest; `v` is 27/28.
+    pub fn sign_hash(&self, hash: H256) -> Result<Signature> {
+        match self {
+            LoadedKey::Local(wallet) => Ok(wallet.sign_hash(hash)?),
+            LoadedKey::Pkcs11(key) => key.sign_hash(hash),
+        }
+    }
+
+    /// Sign a transaction; `v` is EIP-155 encoded, as `LocalWallet::sign_transaction_sync` does.
+    pub fn sign_transaction(&self, tx: &TypedTransaction) -> Result<Signature> {
+        let chain_id = tx.chain_id().ok_or_else(|| eyre!("Transaction has no chain ID"))?.as_u64();
+        let mut sig = self.sign_hash(tx.sighash())?;
+        sig.v = to_eip155_v((sig.v - 27) as u8, chain_id);
+        Ok(sig)
+    }
+}
+
+impl KeySource {
+    pub fn load(self) -> Result<LoadedKey> {
+        let raw = match self {
+            KeySource::Argv(s) => SecretBytes::from_string(s),
+            KeySource::File(path) if path.as_os_str() == "-" => SecretBytes::read_from(std::io::stdin().lock())?,
+            KeySource::File(path) => {
+                warn_if_world_readable(&path);
+                SecretBytes::read_from(File::open(&path)?)?
+            }
+            KeySource::Fd(fd) => read_fd(fd)?,
+            KeySource::Env(name) => take_env_secret(&name)?,
+            KeySource::Slip39 { derivation_path } => return wallet_from_slip39_prompt(&derivation_path).map(LoadedKey::Local),
+            KeySource::Pkcs11 { module, label, pin_env } => {
+                let pin = match pin_env {
+                    Some(name) => take_env(&name)?,
+                    None => rpassword::prompt_password(format!("PKCS#11 user PIN for {label:?}: "))?,
+                };
+                let key = Pkcs11Key::open(&module, &label, AuthPin::from(pin))?;
+                println!("Using PKCS#11 key {:?} from {}", label, module.display());
+                return Ok(LoadedKey::Pkcs11(key));
+            }
+            KeySource::Keyring { dir, password_env, sender } => {
+                return Keyring::open(&dir, password_env)?.take(sender);
+            }
+            KeySource::Prompt => prompt_secret("Private key (hex): ")?,
+        };
+        wallet_from_secret(&raw.decode_hex_key()?).map(LoadedKey::Local)
+    }
+}
+
+/// Read a secret from the environment and remove it, so child processes never see it.
+pub fn take_env(name: &str) -> Result<String> {
+    let value = std::env::var(name).map_err(|_| eyre!("Environment variable {} is not set", name))?;
+    // SAFETY: the signer is single-threaded at this point.
+    unsafe { std::env::remove_var(name) };
+    Ok(value)
+}
+
+/// Read a key from the environment straight into a locked buffer, wipe the value in the
+/// environment block and remove the variable, so neither child processes nor a later look at
+/// process memory find it.
+#[cfg(unix)]
+fn take_env_secret(name: &str) -> Result<SecretBytes> {
+    let c_name = std::ffi::CString::new(name).map_err(|_| eyre!("Invalid environment variable name {:?}", name))?;
+    // SAFETY: the signer is single-threaded at this point, so the environment does not change
+    // while we hold the pointer. It points at a NUL-terminated value in writable memory.
+    let secret = unsafe {
+        let value = libc::getenv(c_name.as_ptr());
+        if value.is_null() {
+            return Err(eyre!("Environment variable {} is not set", name));
+        }
+        let len = libc::strlen(value);
+        let mut secret = SecretBytes::zeroed(len);
+        std::ptr::copy_nonoverlapping(value.cast::<u8>(), secret.buf.as_mut_ptr(), len);
+        secret.len = len;
+        std::ptr::write_bytes(value, 0, len);
+        secret
+    };
+    // SAFETY: as above.
+    unsafe { std::env::remove_var(name) };
+    Ok(secret)
+}
+
+/// Without direct access to the environment block the value passes through a `String`,
+/// which is wiped once copied.
+#[cfg(not(unix))]
+fn take_env_secret(name: &str) -> Result<SecretBytes> {
+    Ok(SecretBytes::from_string(take_env(name)?))
+}
+
+/// Prompt on the controlling terminal with echo off and read the answer straight into a
+/// locked buffer.
+#[cfg(unix)]
+fn prompt_secret(prompt: &str) -> Result<SecretBytes> {
+    use std::io::Write;
+    use std::os::fd::AsRawFd;
+    let mut tty = std::fs::OpenOptions::new().read(true).write(true).open("/dev/tty")?;
+    let fd = tty.as_raw_fd();
+    let mut saved = std::mem::MaybeUninit::<libc::termios>::uninit();
+    // SAFETY: `fd` is an open terminal and `saved` is written by tcgetattr before it is read.
+    if unsafe { libc::tcgetattr(fd, saved.as_mut_ptr()) } != 0 {
+        return Err(eyre!("Cannot read terminal settings: {}", std::io::Error::last_os_error()));
+    }
+    // SAFETY: initialized by the successful tcgetattr above.
+    let saved = unsafe { saved.assume_init() };
+    let mut silent = saved;
+    silent.c_lflag &= !libc::ECHO;
+    silent.c_lflag |= libc::ECHONL;
+    tty.write_all(prompt.as_bytes())?;
+    tty.flush()?;
+    // SAFETY: both settings come from tcgetattr on the same terminal.
+    if unsafe { libc::tcsetattr(fd, libc::TCSANOW, &silent) } != 0 {
+        return Err(eyre!("Cannot turn off terminal echo: {}", std::io::Error::last_os_error()));
+    }
+    let line = SecretBytes::read_line_from(&mut tty);
+    // SAFETY: restores the settings read above, whether or not the read succeeded.
+    unsafe { libc::tcsetattr(fd, libc::TCSANOW, &saved) };
+    line
+}
+
+/// Without termios the answer passes through rpassword's `String`, which is wiped once copied.
+#[cfg(not(unix))]
+fn prompt_secret(prompt: &str) -> Result<SecretBytes> {
+    Ok(SecretBytes::from_string(rpassword::prompt_password(prompt)?))
+}
+
+#[cfg(unix)]
+fn read_fd(fd: i32) -> Result<SecretBytes> {
+    use std::os::fd::FromRawFd;
+    if fd < 0 {
+        return Err(eyre!("Invalid file descriptor {}", fd));
+    }
+    // Taking ownership closes the descriptor, and later prompts still need the standard streams.
+    if fd <= 2 {
+        return Err(eyre!("--private-key-fd {} is a standard stream; use --private-key-file - to read stdin", fd));
+    }
+    // SAFETY: the caller hands this descriptor to us via --private-key-fd; we take
+    // ownership and close it after reading.
+    let file = unsafe { File::from_raw_fd(fd) };
+    SecretBytes::read_from(file)
+}
+
+#[cfg(not(unix))]
+fn read_fd(_fd: i32) -> Result<SecretBytes> {
+    Err(eyre!("--private-key-fd is only supported on Unix"))
+}
+
+#[cfg(unix)]
+fn warn_if_world_readable(path: &std::path::Path) {
+    use std::os::unix::fs::PermissionsExt;
+    if let Ok(meta) = std::fs::metadata(path)
+        && meta.permissions().mode() & 0o077 != 0
+    {
+        eprintln!("Warning: key file {} is accessible by group/others", path.display());
+    }
+}
+
+#[cfg(not(unix))]
+fn warn_if_world_readable(_path: &std::path::Path) {}
+
+fn wallet_from_secret(key: &SecretBytes) -> Result<LocalWallet> {
+    let signing_key = SigningKey::from_slice(key.as_bytes()).map_err(|_| eyre!("Invalid secp256k1 private key"))?;
+    Ok(LocalWallet::from(signing_key))
+}
+
+/// Prompt for SLIP-39 shares until the threshold is met, then derive the signing key.
+/// The recovered seed is wiped when this function returns.
+fn wallet_from_slip39_prompt(derivation_path: &str) -> Result<LocalWallet> {
+    wallet_from_seed(&slip39_seed_prompt()?, derivation_path)
+}
+
+/// Prompt for SLIP-39 shares until the threshold is met and recover the seed.
+/// Each share is checksum-verified as it is entered.
+pub fn slip39_seed_prompt() -> Result<Zeroizing<Vec<u8>>> {
+    let mut set = ShareSet::new();
+    let mut n = 1;
+    while !set.is_complete() {
+        let phrase = Zeroizing::new(rpassword::prompt_password(format!("SLIP-39 share {n} (empty to abort): "))?);
+        if phrase.trim().is_empty() {
+            return Err(eyre!("Share entry aborted"));
+        }
+        match Share::from_mnemonic(&phrase).and_then(|share| set.add(share)) {
+            Ok(()) => {
+                println!("Share {n} accepted ({})", set.progress());
+                n += 1;
+            }
+            Err(e) => println!("Share rejected: {e}"),
+        }
+    }
+
+    let passphrase = Zeroizing::new(rpassword::prompt_password("SLIP-39 passphrase (empty for none): ")?);
+    set.recover(passphrase.as_bytes())
+}
+
+/// Derive a BIP-32 child key from a raw seed.
+pub fn wallet_from_seed(seed: &[u8], derivation_path: &str) -> Result<LocalWallet> {
+    let xpriv = DerivedXPriv::root_from_seed(seed, None)?.derive_path(derivation_path)?;
+    let key: &SigningKey = xpriv.as_ref();
+    Ok(LocalWallet::from(key.clone()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ethers::signers::Signer;
+    use ethers::types::Address;
+    use std::str::FromStr;
+
+    const KEY: &str = "4c0883a69102937d6231471b5ecb4765d5e97f8e4dc6e8fa6a4de3b8a3a2f55b";
+
+    #[test]
+    fn hex_key_from_reader_accepts_prefix_and_whitespace() {
+        let input = format!("  0x{KEY}\n");
+        let raw = SecretBytes::read_from(input.as_bytes()).unwrap();
+        let wallet = wallet_from_secret(&raw.decode_hex_key().unwrap()).unwrap();
+        let expected = LocalWallet::from_str(KEY).unwrap();
+        assert_eq!(wallet.address(), expected.address());
+    }
+
+    #[test]
+    fn rejects_short_and_oversized_input() {
+        let raw = SecretBytes::from_string("abcd".to_string());
+        assert!(raw.decode_hex_key().is_err());
+        let huge = vec![b'a'; MAX_KEY_INPUT_BYTES + 10];
+        assert!(SecretBytes::read_from(huge.as_slice()).is_err());
+        assert!(SecretBytes::read_line_from(huge.as_slice()).is_err());
+    }
+
+    #[test]
+    fn line_reader_stops_at_the_newline() {
+        let input = format!("{KEY}\nnot read");
+        let mut reader = input.as_bytes();
+        let raw = SecretBytes::read_line_from(&mut reader).unwrap();
+        assert_eq!(raw.as_bytes(), KEY.as_bytes());
+        assert_eq!(reader, b"not read");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn env_key_is_wiped_and_removed() {
+        let name = "TX_SIGNER_TEST_ENV_KEY";
+        // SAFETY: no other test reads this variable.
+        unsafe { std::env::set_var(name, KEY) };
+        let raw = take_env_secret(name).unwrap();
+        assert_eq!(raw.as_bytes(), KEY.as_bytes());
+        assert!(std::env::var_os(name).is_none());
+        assert!(take_env_secret(name).is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn standard_streams_are_not_key_fds() {
+        for fd in [-1, 0, 1, 2] {
+            assert!(read_fd(fd).is_err(), "fd {fd}");
+        }
+    }
+
+    #[test]
+    fn seed_derivation_matches_known_address() {
+        use ethers::signers::coins_bip39::{English, Mnemonic};
+        let phrase = "test test test test test test test test test test test junk";
+        let seed = Mnemonic::<English>::new_from_phrase(phrase).unwrap().to_seed(None).unwrap();
+        let wallet = wallet_from_seed(&seed, "m/44'/60'/0'/0/0").unwrap();
+        assert_eq!(
+            wallet.address(),
+            Address::from_str("0xf39Fd6e51aad88F6F4ce6aB8827279cffFb92266").unwrap()
+        );
+    }
+}
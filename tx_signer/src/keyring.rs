@@ -0,0 +1,247 @@
+//! `--keyring DIR`: many cold keys, each picked by the sender address it signs for.
+//!
+//! The directory holds Web3 Secret Storage keystores (any file name, e.g. geth's
+//! `UTC--<date>--<address>`) sharing one password, and optionally `derivation_paths.toml`
+//! mapping `"0xaddress" = "m/44'/60'/0'/0/3"` for keys derived from a SLIP-39 seed.
+//! Only the key for the requested sender is unlocked; a sender with no key is refused.
+
+use crate::key_source::{slip39_seed_prompt, take_env, wallet_from_seed, LoadedKey};
+use ethers::signers::{LocalWallet, Signer};
+use ethers::types::Address;
+use eyre::{eyre, Result};
+use std::collections::{BTreeMap, HashMap};
+use std::{fs, path::{Path, PathBuf}, str::FromStr};
+use zeroize::Zeroizing;
+
+pub const DERIVATION_PATHS_FILE: &str = "derivation_paths.toml";
+
+struct Keystore {
+    path: PathBuf,
+    /// Address from the keystore's `address` field or file name; `None` until decrypted.
+    address: Option<Address>,
+}
+
+pub struct Keyring {
+    dir: PathBuf,
+    keystores: Vec<Keystore>,
+    derived: Vec<(Address, String)>,
+    password_env: Option<String>,
+    password: Option<Zeroizing<String>>,
+    unlocked: HashMap<Address, LoadedKey>,
+}
+
+/// Address a keystore declares, from its JSON `address` field or a trailing 40-hex file name.
+fn declared_address(path: &Path, json: &serde_json::Value) -> Option<Address> {
+    let from_json = json.get("address").and_then(|a| a.as_str()).map(str::to_string);
+    let name = path.file_name()?.to_str()?;
+    let from_name = name.strip_suffix(".json").unwrap_or(name).rsplit("--").next().map(str::to_string);
+    [from_json, from_name]
+        .into_iter()
+        .flatten()
+        .find_map(|a| Address::from_str(a.trim_start_matches("0x")).ok())
+}
+
+impl Keyring {
+    /// Index the keyring directory. Nothing is decrypted yet.
+    pub fn open(dir: &Path, password_env: Option<String>) -> Result<Self> {
+        let mut keystores = Vec::new();
+        let mut derived = Vec::new();
+        let mut entries: Vec<PathBuf> = fs::read_dir(dir)
+            .map_err(|e| eyre!("Cannot read keyring {}: {}", dir.display(), e))?
+            .map(|entry| entry.map(|e| e.path()))
+            .collect::<std::io::Result<_>>()?;
+        entries.sort();
+
+        for path in entries {
+            let name = path.file_name().and_then(|n| n.to_str()).unwrap_or_default();
+            if name.starts_with('.') || !path.is_file() {
+                continue;
+            }
+            if name == DERIVATION_PATHS_FILE {
+                let table: BTreeMap<String, String> = toml::from_str(&fs::read_to_string(&path)?)
+                    .map_err(|e| eyre!("Invalid {}: {}", path.display(), e))?;
+                for (address, derivation_path) in table {
+                    let address = Address::from_str(&address)
+                        .map_err(|_| eyre!("Invalid address {:?} in {}", address, path.display()))?;
+                    derived.push((address, derivation_path));
+                }
+                continue;
+            }
+            let json: serde_json::Value = serde_json::from_str(&fs::read_to_string(&path)?)
+                .map_err(|e| eyre!("{} is not a keystore: {}", path.display(), e))?;
+            if json.get("crypto").or_else(|| json.get("Crypto")).is_none() {
+                return Err(eyre!("{} is not a keystore (no \"crypto\" section)", path.display()));
+            }
+            let address = declared_address(&path, &json);
+            keystores.push(Keystore { path, address });
+        }
+        if keystores.is_empty() && derived.is_empty() {
+            return Err(eyre!("Keyring {} holds no keys", dir.display()));
+        }
+
+        Ok(Keyring {
+            dir: dir.to_path_buf(),
+            keystores,
+            derived,
+            password_env,
+            password: None,
+            unlocked: HashMap::new(),
+        })
+    }
+
+    fn password(&mut self) -> Result<&str> {
+        if self.password.is_none() {
+            let password = match self.password_env.take() {
+                Some(name) => take_env(&name)?,
+                None => rpassword::prompt_password(format!("Keyring password for {}: ", self.dir.display()))?,
+            };
+            self.password = Some(Zeroizing::new(password));
+        }
+        Ok(self.password.as_deref().expect("password set above"))
+    }
+
+    fn decrypt(&mut self, idx: usize) -> Result<Address> {
+        let path = self.keystores[idx].path.clone();
+        let wallet = LocalWallet::decrypt_keystore(&path, self.password()?)
+            .map_err(|e| eyre!("Cannot decrypt keystore {}: {}", path.display(), e))?;
+        let address = wallet.address();
+        if let Some(declared) = self.keystores[idx].address
+            && declared != address
+        {
+            return Err(eyre!("Keystore {} is named for {:?} but holds {:?}", path.display(), declared, address));
+        }
+        self.keystores[idx].address = Some(address);
+        self.unlocked.insert(address, LoadedKey::Local(wallet));
+        Ok(address)
+    }
+
+    /// Recover the seed once and derive every listed path, checking each declared address.
+    fn derive_all(&mut self) -> Result<()> {
+        println!("Keyring {} lists derived keys; enter the SLIP-39 shares", self.dir.display());
+        let seed = slip39_seed_prompt()?;
+        for (address, derivation_path) in &self.derived {
+            let wallet = wallet_from_seed(&seed, derivation_path)?;
+            if wallet.address() != *address {
+                return Err(eyre!(
+                    "{} derives {:?}, but {} lists it for {:?}",
+                    derivation_path,
+                    wallet.address(),
+                    DERIVATION_PATHS_FILE,
+                    address
+                ));
+            }
+            self.unlocked.insert(*address, LoadedKey::Local(wallet));
+        }
+        Ok(())
+    }
+
+    /// The key for `sender`, unlocking it on first use. Refuses when no key matches.
+    pub fn key_for(&mut self, sender: Address) -> Result<&LoadedKey> {
+        if !self.unlocked.contains_key(&sender) {
+            self.unlock(sender)?;
+        }
+        Ok(&self.unlocked[&sender])
+    }
+
+    /// A key that was already unlocked by [`Keyring::key_for`].
+    pub fn get(&self, address: Address) -> Option<&LoadedKey> {
+        self.unlocked.get(&address)
+    }
+
+    /// Like [`Keyring::key_for`], handing over ownership of the key.
+    pub fn take(mut self, sender: Address) -> Result<LoadedKey> {
+        self.key_for(sender)?;
+        Ok(self.unlocked.remove(&sender).expect("unlocked above"))
+    }
+
+    fn unlock(&mut self, sender: Address) -> Result<()> {
+        if self.derived.iter().any(|(address, _)| *address == sender) {
+            return self.derive_all();
+        }
+        if let Some(idx) = self.keystores.iter().position(|k| k.address == Some(sender)) {
+            self.decrypt(idx)?;
+            return Ok(());
+        }
+        // Keystores that do not declare their address have to be decrypted to find out. One
+        // under a different password must not keep the others from being tried.
+        let mut skipped = Vec::new();
+        for idx in 0..self.keystores.len() {
+            if self.keystores[idx].address.is_some() {
+                continue;
+            }
+            match self.decrypt(idx) {
+                Ok(address) if address == sender => return Ok(()),
+                Ok(_) => {}
+                Err(e) => {
+                    eprintln!("Warning: skipping keystore: {}", e);
+                    skipped.push(self.keystores[idx].path.display().to_string());
+                }
+            }
+        }
+
+        let known: Vec<String> = self
+            .keystores
+            .iter()
+            .filter_map(|k| k.address)
+            .chain(self.derived.iter().map(|(address, _)| *address))
+            .map(|a| format!("{a:?}"))
+            .collect();
+        let unreadable = if skipped.is_empty() { String::new() } else { format!("; could not decrypt: {}", skipped.join(", ")) };
+        Err(eyre!(
+            "No key in keyring {} matches sender {:?}; refusing to sign (keyring holds: {}{})",
+            self.dir.display(),
+            sender,
+            known.join(", "),
+            unreadable
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KEYS: [&str; 2] = [
+        "4c0883a69102937d6231471b5ecb4765d5e97f8e4dc6e8fa6a4de3b8a3a2f55b",
+        "ac0974bec39a17e36ba4a6b4d238ff944bacb478cbed5efcae784d7bf4f2ff80",
+    ];
+
+    #[test]
+    fn selects_key_by_sender_and_refuses_unknown() {
+        let dir = std::env::temp_dir().join(format!("tx_signer_keyring_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let mut rng = ethers::core::rand::thread_rng();
+        let a = LocalWallet::from_str(KEYS[0]).unwrap();
+        let b = LocalWallet::from_str(KEYS[1]).unwrap();
+        // One keystore named geth-style after its address, one with an opaque name.
+        let named = format!("UTC--2024-01-01T00-00-00Z--{}", hex::encode(a.address()));
+        LocalWallet::encrypt_keystore(&dir, &mut rng, hex::decode(KEYS[0]).unwrap(), "pw", Some(&named)).unwrap();
+        LocalWallet::encrypt_keystore(&dir, &mut rng, hex::decode(KEYS[1]).unwrap(), "pw", Some("cold-b.json")).unwrap();
+
+        let mut keyring = Keyring::open(&dir, None).unwrap();
+        keyring.password = Some(Zeroizing::new("pw".to_string()));
+        assert_eq!(keyring.key_for(a.address()).unwrap().address(), a.address());
+        assert_eq!(keyring.unlocked.len(), 1, "only the named keystore is decrypted");
+        assert_eq!(keyring.key_for(b.address()).unwrap().address(), b.address());
+        let err = keyring.key_for(Address::repeat_byte(0x11)).err().unwrap().to_string();
+        assert!(err.contains("refusing to sign"), "{err}");
+
+        // An opaque keystore under another password is skipped, not fatal.
+        let c = LocalWallet::new(&mut rng);
+        LocalWallet::encrypt_keystore(&dir, &mut rng, c.signer().to_bytes(), "other", Some("aaa-other.json")).unwrap();
+        let mut keyring = Keyring::open(&dir, None).unwrap();
+        keyring.password = Some(Zeroizing::new("pw".to_string()));
+        assert_eq!(keyring.key_for(b.address()).unwrap().address(), b.address());
+        let err = keyring.key_for(c.address()).err().unwrap().to_string();
+        assert!(err.contains("could not decrypt") && err.contains("aaa-other.json"), "{err}");
+        fs::remove_file(dir.join("aaa-other.json")).unwrap();
+
+        // A keystore whose name claims another address is rejected.
+        fs::rename(dir.join("cold-b.json"), dir.join(format!("UTC--x--{}", hex::encode(Address::repeat_byte(0x22))))).unwrap();
+        let mut keyring = Keyring::open(&dir, None).unwrap();
+        keyring.password = Some(Zeroizing::new("pw".to_string()));
+        assert!(keyring.key_for(Address::repeat_byte(0x22)).is_err());
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
@@ -1,6 +1,20 @@
-use clap::Parser;
+mod assemble;
+mod batch;
+mod erc20;
+mod key_source;
+mod keyring;
+mod message;
+mod nonce_guard;
+mod pkcs11;
+mod policy;
+mod review;
+mod siwe;
+mod spending;
+mod typed_data;
+mod verify;
+
+use clap::{Parser, Subcommand};
 use eyre::{eyre, Result};
-use ethers::signers::{LocalWallet, Signer};
 use ethers::types::{
     transaction::eip2718::TypedTransaction, Address, Bytes, NameOrAddress, Signature, TransactionRequest, U256, U64,
 };
@@ -10,20 +24,33 @@ use qrcode::{render::unicode, EcLevel, QrCode};
 use image::{GrayImage, Luma};
 use quircs::Quirc;
 use rqrr::PreparedImage;
+use key_source::KeyArgs;
+use nonce_guard::{NonceCheck, NonceLedger};
+use policy::Policy;
+use review::Reviewer;
+use spending::Ledger;
+use tx_envelope::{Format, SignedEnvelope};
 
-use std::{fs, path::Path, str::FromStr};
+use std::{fs, path::{Path, PathBuf}, str::FromStr, time::{SystemTime, UNIX_EPOCH}};
 
 #[derive(Parser, Debug)]
 #[command(name = "tx_signer")]
 #[command(about = "Signs an Ethereum/Polygon transaction", arg_required_else_help = true)]
+#[command(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
 struct Args {
+    #[command(subcommand)]
+    command: Option<Command>,
+
     /// Signed transaction file output (hex, ready for eth_sendRawTransaction)
-    #[arg(long)]
-    output: String,
+    #[arg(long, required_unless_present = "hash_only")]
+    output: Option<String>,
 
-    /// Private key (hex, no 0x)
-    #[arg(long)]
-    private_key: String,
+    /// Print the signing hash (keccak of the preimage) for an external signer and exit
+    #[arg(long, conflicts_with = "key")]
+    hash_only: bool,
+
+    #[command(flatten)]
+    key: KeyArgs,
 
     /// Input unsigned transaction (hex file)
     #[arg(long, required_unless_present = "input_qr")]
@@ -36,6 +63,60 @@ struct Args {
     /// Print signed transaction as QR code
     #[arg(long)]
     qr: bool,
+
+    /// Signing policy (TOML) the transaction must satisfy before it is signed
+    #[arg(long)]
+    policy: Option<PathBuf>,
+
+    /// Create the spending state file named by the policy, or accept it while it has no recorded spends
+    #[arg(long, requires = "policy")]
+    init_spending_state: bool,
+
+    /// Override signature (hex) from a policy override signer, lifting spending limits for this tx
+    #[arg(long, requires = "policy")]
+    spending_override: Option<String>,
+
+    /// Ledger of signed (chain, sender, nonce) payloads [default: ~/.tx_signer/nonce_ledger.jsonl]
+    #[arg(long)]
+    nonce_ledger: Option<PathBuf>,
+
+    /// Allow re-signing a used nonce as a fee bump (same recipient, value and data, higher fees)
+    #[arg(long)]
+    replace: bool,
+
+    /// Address labels for the review screen (TOML table of "0xaddress" = "label")
+    #[arg(long)]
+    address_book: Option<PathBuf>,
+
+    /// JSON ABI used to decode calldata on the review screen (repeatable)
+    #[arg(long)]
+    abi: Vec<PathBuf>,
+
+    /// Skip the typed confirmation; needs a --policy with allow_unattended = true
+    #[arg(long, requires = "policy")]
+    yes: bool,
+
+    /// Also write a signed-output envelope (raw tx, tx hash, sender, nonce, chain ID, unsigned payload hash)
+    #[arg(long)]
+    envelope: Option<PathBuf>,
+
+    /// Envelope encoding
+    #[arg(long, value_enum, default_value = "json", requires = "envelope")]
+    envelope_format: Format,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Sign a message with EIP-191 personal_sign (version 0x45)
+    Message(message::MessageArgs),
+    /// Sign EIP-712 typed structured data (eth_signTypedData_v4 JSON)
+    TypedData(typed_data::TypedDataArgs),
+    /// Sign a Sign-In with Ethereum (EIP-4361) message after checking it against expectations
+    Siwe(siwe::SiweArgs),
+    /// Combine an external signature with an unsigned payload into the signed raw tx
+    Assemble(assemble::AssembleArgs),
+    /// Sign every payload in a directory with a single key unlock
+    Batch(batch::BatchArgs),
 }
 
 fn save_qr_to_png(qr_data: &str, filename: &str) -> Result<()> {
@@ -75,7 +156,7 @@ enum UnsignedTx {
         nonce: U256,
         gas_price: U256,
         gas_limit: U256,
-        to: Address,
+        to: Option<Address>,
         value: U256,
         data: Vec<u8>,
         chain_id: U256,
@@ -86,16 +167,89 @@ enum UnsignedTx {
         max_priority_fee: U256,
         max_fee: U256,
         gas_limit: U256,
-        to: Address,
+        to: Option<Address>,
         value: U256,
         data: Vec<u8>,
         // accessList enforced empty
     },
 }
 
+impl UnsignedTx {
+    fn chain_id(&self) -> U256 {
+        match self {
+            UnsignedTx::Legacy { chain_id, .. } | UnsignedTx::Eip1559 { chain_id, .. } => *chain_id,
+        }
+    }
+
+    /// Chain ID as the `u64` the ledgers and envelopes store; an error rather than a truncation.
+    fn chain_id_u64(&self) -> Result<u64> {
+        u64::try_from(self.chain_id()).map_err(|_| eyre!("Chain ID {} does not fit in 64 bits", self.chain_id()))
+    }
+
+    fn nonce(&self) -> U256 {
+        match self {
+            UnsignedTx::Legacy { nonce, .. } | UnsignedTx::Eip1559 { nonce, .. } => *nonce,
+        }
+    }
+
+    /// Recipient; `None` for contract creation.
+    fn to(&self) -> Option<Address> {
+        match self {
+            UnsignedTx::Legacy { to, .. } | UnsignedTx::Eip1559 { to, .. } => *to,
+        }
+    }
+
+    fn value(&self) -> U256 {
+        match self {
+            UnsignedTx::Legacy { value, .. } | UnsignedTx::Eip1559 { value, .. } => *value,
+        }
+    }
+
+    fn data(&self) -> &[u8] {
+        match self {
+            UnsignedTx::Legacy { data, .. } | UnsignedTx::Eip1559 { data, .. } => data,
+        }
+    }
+
+    fn gas_limit(&self) -> U256 {
+        match self {
+            UnsignedTx::Legacy { gas_limit, .. } | UnsignedTx::Eip1559 { gas_limit, .. } => *gas_limit,
+        }
+    }
+
+    /// Highest price per gas the tx may pay: gasPrice (legacy) or maxFeePerGas (type-2).
+    fn max_fee_per_gas(&self) -> U256 {
+        match self {
+            UnsignedTx::Legacy { gas_price, .. } => *gas_price,
+            UnsignedTx::Eip1559 { max_fee, .. } => *max_fee,
+        }
+    }
+
+    fn max_priority_fee(&self) -> Option<U256> {
+        match self {
+            UnsignedTx::Legacy { .. } => None,
+            UnsignedTx::Eip1559 { max_priority_fee, .. } => Some(*max_priority_fee),
+        }
+    }
+
+    /// Worst-case fee: gasLimit * max price per gas.
+    fn max_fee_cost(&self) -> U256 {
+        self.gas_limit().saturating_mul(self.max_fee_per_gas())
+    }
+}
+
+/// Decode the `to` field; an empty string means contract creation.
+fn decode_to(item: rlp::Rlp) -> Result<Option<Address>> {
+    if item.is_empty() {
+        Ok(None)
+    } else {
+        Ok(Some(item.as_val()?))
+    }
+}
+
 fn parse_unsigned(bytes: &[u8]) -> Result<UnsignedTx> {
     // Detect type-2 by leading 0x02
-    if let Some((&0x02, rest)) = bytes.split_first() {
+    let utx = if let Some((&0x02, rest)) = bytes.split_first() {
         let r = rlp::Rlp::new(rest);
         if !r.is_list() || r.item_count()? != 9 {
             return Err(eyre!("EIP-1559 signing payload must be RLP list of 9 items"));
@@ -105,7 +259,7 @@ fn parse_unsigned(bytes: &[u8]) -> Result<UnsignedTx> {
         let max_priority_fee: U256 = r.val_at(2)?;
         let max_fee: U256 = r.val_at(3)?;
         let gas_limit: U256 = r.val_at(4)?;
-        let to: Address = r.val_at(5)?;
+        let to = decode_to(r.at(5)?)?;
         let value: U256 = r.val_at(6)?;
         let data_vec: Vec<u8> = r.val_at(7)?;
         // accessList at 8; enforce empty list
@@ -114,7 +268,7 @@ fn parse_unsigned(bytes: &[u8]) -> Result<UnsignedTx> {
             return Err(eyre!("Only empty accessList is supported in unsigned payload"));
         }
 
-        Ok(UnsignedTx::Eip1559 {
+        UnsignedTx::Eip1559 {
             chain_id,
             nonce,
             max_priority_fee,
@@ -123,7 +277,7 @@ fn parse_unsigned(bytes: &[u8]) -> Result<UnsignedTx> {
             to,
             value,
             data: data_vec,
-        })
+        }
     } else {
         // Legacy EIP-155 preimage: 9 items
         let r = rlp::Rlp::new(bytes);
@@ -136,7 +290,7 @@ fn parse_unsigned(bytes: &[u8]) -> Result<UnsignedTx> {
         let nonce: U256 = r.val_at(0)?;
         let gas_price: U256 = r.val_at(1)?;
         let gas_limit: U256 = r.val_at(2)?;
-        let to: Address = r.val_at(3)?;
+        let to = decode_to(r.at(3)?)?;
         let value: U256 = r.val_at(4)?;
         let data_vec: Vec<u8> = r.val_at(5)?;
         let chain_id: U256 = r.val_at(6)?;
@@ -146,7 +300,7 @@ fn parse_unsigned(bytes: &[u8]) -> Result<UnsignedTx> {
             return Err(eyre!("Expected trailing r,s = 0,0 in legacy preimage"));
         }
 
-        Ok(UnsignedTx::Legacy {
+        UnsignedTx::Legacy {
             nonce,
             gas_price,
             gas_limit,
@@ -154,8 +308,11 @@ fn parse_unsigned(bytes: &[u8]) -> Result<UnsignedTx> {
             value,
             data: data_vec,
             chain_id,
-        })
-    }
+        }
+    };
+    // The signature's `v` and every ledger keep the chain ID as a u64.
+    utx.chain_id_u64()?;
+    Ok(utx)
 }
 
 fn unsigned_to_typed(utx: &UnsignedTx) -> TypedTransaction {
@@ -170,7 +327,7 @@ fn unsigned_to_typed(utx: &UnsignedTx) -> TypedTransaction {
             chain_id,
         } => {
             let req = TransactionRequest {
-                to: Some(NameOrAddress::Address(*to)),
+                to: to.map(NameOrAddress::Address),
                 value: Some(*value),
                 gas_price: Some(*gas_price),
                 gas: Some(*gas_limit),
@@ -179,7 +336,7 @@ fn unsigned_to_typed(utx: &UnsignedTx) -> TypedTransaction {
                 data: Some(Bytes::from(data.clone())),
                 ..Default::default()
             };
-            TypedTransaction::Legacy(req.into())
+            TypedTransaction::Legacy(req)
         }
         UnsignedTx::Eip1559 {
             chain_id,
@@ -199,10 +356,12 @@ fn unsigned_to_typed(utx: &UnsignedTx) -> TypedTransaction {
                 .max_priority_fee_per_gas(*max_priority_fee)
                 .max_fee_per_gas(*max_fee)
                 .gas(*gas_limit)
-                .to(*to)
                 .value(*value)
                 .data(Bytes::from(data.clone()))
                 .access_list(AccessList::default());
+            if let Some(to) = to {
+                tx1559 = tx1559.to(*to);
+            }
             TypedTransaction::Eip1559(tx1559)
         }
     }
@@ -214,14 +373,12 @@ fn decode_qr_from_file(path: &Path) -> Result<String> {
     let (width, height) = img.dimensions();
     let pixels = img.as_raw();
     let mut decoder = Quirc::default();
-    for code_result in decoder.identify(width as usize, height as usize, pixels) {
-        if let Ok(code) = code_result {
-            if let Ok(decoded) = code.decode() {
-                if let Ok(text) = String::from_utf8(decoded.payload.clone()) {
-                    println!("Decoded via quircs");
-                    return Ok(text);
-                }
-            }
+    for code in decoder.identify(width as usize, height as usize, pixels).flatten() {
+        if let Ok(decoded) = code.decode()
+            && let Ok(text) = String::from_utf8(decoded.payload.clone())
+        {
+            println!("Decoded via quircs");
+            return Ok(text);
         }
     }
 
@@ -249,36 +406,121 @@ fn read_unsigned_hex(args: &Args) -> Result<String> {
     }
 }
 
-fn wallet_from_hex_no0x(hexkey: &str) -> Result<LocalWallet> {
-    let mut s = String::with_capacity(2 + hexkey.len());
-    s.push_str("0x");
-    s.push_str(hexkey);
-    Ok(LocalWallet::from_str(&s)?)
+/// Unsigned payloads are bare hex, or a JSON envelope `{"from": "0x..", "payload": "0x.."}`
+/// naming the account expected to sign them.
+#[derive(serde::Deserialize)]
+struct UnsignedEnvelope {
+    from: Address,
+    payload: String,
 }
 
-fn main() -> Result<()> {
-    let args = Args::parse();
+fn split_envelope(text: &str) -> Result<(String, Option<Address>)> {
+    let text = text.trim();
+    if !text.starts_with('{') {
+        return Ok((text.to_string(), None));
+    }
+    let envelope: UnsignedEnvelope =
+        serde_json::from_str(text).map_err(|e| eyre!("Invalid unsigned payload envelope: {}", e))?;
+    Ok((envelope.payload, Some(envelope.from)))
+}
 
-    // 1) Wallet
-    let wallet = wallet_from_hex_no0x(&args.private_key)?;
-    let addr = wallet.address();
-    println!("Using wallet for: {addr:?}");
+/// Structured form of a signed transaction for `--envelope`, linked to its unsigned payload.
+fn envelope_for(raw: Bytes, utx: &UnsignedTx, from: Address, unsigned_payload: &[u8]) -> Result<SignedEnvelope> {
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+    Ok(SignedEnvelope::new(raw, from, utx.nonce().low_u64(), utx.chain_id_u64()?, unsigned_payload, now))
+}
+
+fn main() -> Result<()> {
+    let mut args = Args::parse();
+    if let Some(command) = args.command.take() {
+        return match command {
+            Command::Message(message_args) => message::run(message_args),
+            Command::TypedData(typed_args) => typed_data::run(typed_args),
+            Command::Siwe(siwe_args) => siwe::run(siwe_args),
+            Command::Assemble(assemble_args) => assemble::run(assemble_args),
+            Command::Batch(batch_args) => batch::run(batch_args),
+        };
+    }
 
-    // 2) Read unsigned hex (file or QR)
-    let unsigned_hex = read_unsigned_hex(&args)?;
+    // 1) Read unsigned hex (file or QR)
+    let (unsigned_hex, envelope_from) = split_envelope(&read_unsigned_hex(&args)?)?;
     let unsigned_hex = unsigned_hex.trim().trim_start_matches("0x");
     let unsigned_bytes = hex::decode(unsigned_hex)?;
     println!("Unsigned payload length: {} bytes", unsigned_bytes.len());
-
-    // 3) Parse unsigned → TypedTransaction
     let utx = parse_unsigned(&unsigned_bytes)?;
+
+    if args.hash_only {
+        let sighash = unsigned_to_typed(&utx).sighash();
+        println!("Chain ID: {}, nonce: {}", utx.chain_id(), utx.nonce());
+        println!("Signing hash: {sighash:?}");
+        if let Some(output) = &args.output {
+            fs::write(output, format!("{sighash:?}"))?;
+            println!("Signing hash written to: {output}");
+        }
+        return Ok(());
+    }
+    let output = args.output.clone().ok_or_else(|| eyre!("--output is required"))?;
+
+    // 2) Wallet, for the sender named by the envelope or --from
+    if let Some(from) = envelope_from {
+        if args.key.from.is_some_and(|f| f != from) {
+            return Err(eyre!("--from {:?} contradicts the payload envelope sender {:?}", args.key.from.unwrap(), from));
+        }
+        args.key.from = Some(from);
+    }
+    let wallet = args.key.load()?;
+    let addr = wallet.address();
+    println!("Using wallet for: {addr:?}");
+
+    // 3) Policy and guards
+    let policy = args.policy.as_deref().map(Policy::load).transpose()?;
+    if let Some(policy) = &policy {
+        policy::enforce(policy, &utx)?;
+    }
     let typed: TypedTransaction = unsigned_to_typed(&utx);
 
-    // 4) Sign
-    let sig: Signature = wallet.sign_transaction_sync(&typed)?;
+    let nonce_ledger_path = args
+        .nonce_ledger
+        .clone()
+        .or_else(nonce_guard::default_ledger_path)
+        .ok_or_else(|| eyre!("Cannot locate a nonce ledger: set HOME or pass --nonce-ledger"))?;
+    let mut nonce_ledger = NonceLedger::open(&nonce_ledger_path)?;
+    let nonce_check = nonce_ledger.check(&utx, addr, typed.sighash(), args.replace)?;
+    match nonce_check {
+        NonceCheck::Fresh => {}
+        NonceCheck::AlreadySigned => println!("Nonce {} was already signed for this exact payload", utx.nonce()),
+        NonceCheck::Replacement => println!("Signing fee-bump replacement for nonce {}", utx.nonce()),
+    }
+
+    // Spending budgets are checked before signing and recorded before any output is released,
+    // whatever the (unsigned) nonce ledger says. Re-signs and fee bumps are credited by the
+    // signed spending ledger itself.
+    let spending = match policy.as_ref().and_then(|p| p.spending.as_ref()) {
+        Some(limits) => {
+            let ledger = Ledger::open(&limits.state_file, args.init_spending_state)?;
+            let override_sig = args.spending_override.as_deref().map(Signature::from_str).transpose()?;
+            let override_by = limits.check(&ledger, &utx, addr, typed.sighash(), override_sig.as_ref())?;
+            Some((limits, ledger, override_by))
+        }
+        None => None,
+    };
+
+    // 4) Review and confirm
+    Reviewer::new(args.address_book.as_deref(), &args.abi)?.confirm(&utx, addr, args.yes, policy.as_ref())?;
+
+    // 5) Sign
+    let sig: Signature = wallet.sign_transaction(&typed)?;
     let signed_raw = typed.rlp_signed(&sig);
+    let tx_hash = verify::verify_signed(&signed_raw, &utx, addr)?;
+    println!("Self-verification passed; transaction hash: {tx_hash:?}");
+    if let Some((limits, mut ledger, override_by)) = spending {
+        limits.record(&mut ledger, &utx, &wallet, addr, typed.sighash(), override_by)?;
+    }
+    if nonce_check != NonceCheck::AlreadySigned {
+        nonce_ledger.record(&utx, addr, typed.sighash())?;
+    }
 
-    // 5) Output
+    // 6) Output
     let signed_hex = hex::encode(&signed_raw);
     if args.qr {
         let qr = QrCode::new(signed_hex.as_bytes())?;
@@ -287,14 +529,19 @@ fn main() -> Result<()> {
         save_qr_to_png(&signed_hex, "signed_qr.png")?;
     }
 
-    fs::write(&args.output, &signed_hex)?;
-    println!("Signed transaction (hex) written to: {}", args.output);
+    fs::write(&output, &signed_hex)?;
+    println!("Signed transaction (hex) written to: {}", output);
+    if let Some(path) = &args.envelope {
+        envelope_for(signed_raw, &utx, addr, &unsigned_bytes)?.write(path, args.envelope_format)?;
+        println!("Signed envelope ({:?}) written to: {}", args.envelope_format, path.display());
+    }
     Ok(())
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use ethers::signers::LocalWallet;
     use ethers::utils::parse_units;
 
     #[test]
@@ -331,6 +578,37 @@ mod tests {
         assert!(sig.v == 37u64 || sig.v == 38u64);
     }
 
+    #[test]
+    fn parse_contract_creation_preimage() {
+        let mut s = rlp::RlpStream::new_list(9);
+        s.append(&U256::zero());
+        s.append(&U256::from(1_000_000_000u64));
+        s.append(&U256::from(100_000u64));
+        s.append_empty_data();
+        s.append(&U256::zero());
+        s.append(&vec![0x60u8, 0x80]);
+        s.append(&U256::one());
+        s.append(&0u8);
+        s.append(&0u8);
+
+        let utx = parse_unsigned(&s.out()).unwrap();
+        assert_eq!(utx.to(), None);
+        assert!(unsigned_to_typed(&utx).to().is_none());
+    }
+
+    #[test]
+    fn chain_id_above_u64_is_refused() {
+        let mut s = rlp::RlpStream::new_list(9);
+        s.append(&(U256::from(1) << 64));
+        for field in [0u64, 1, 10, 21_000] {
+            s.append(&field);
+        }
+        s.append(&Address::repeat_byte(0x11)).append(&1u64).append(&Vec::<u8>::new()).begin_list(0);
+        let payload = [&[2u8][..], &s.out()].concat();
+        let err = parse_unsigned(&payload).err().unwrap();
+        assert!(err.to_string().contains("64 bits"), "{err}");
+    }
+
     #[test]
     fn sign_1559_from_payload() {
         // Minimal type-2 signing payload (empty data/accessList)
@@ -356,7 +634,7 @@ mod tests {
         s.append_raw(&empty, 1);
 
         let mut bytes = vec![0x02];
-        bytes.extend_from_slice(&s.out().to_vec());
+        bytes.extend_from_slice(&s.out());
 
         let utx = parse_unsigned(&bytes).unwrap();
         let typed = unsigned_to_typed(&utx);
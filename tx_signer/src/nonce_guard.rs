@@ -0,0 +1,212 @@
+//! Nonce equivocation guard.
+//!
+//! Every signature is recorded in a local JSON-lines ledger keyed by
+//! `(chain_id, from, nonce)`. Signing a different payload for a nonce that was already used
+//! is refused, unless `--replace` is given and the new payload is a pure fee bump: same
+//! recipient, value and calldata, with a higher max price per gas and no lower tip.
+
+use crate::UnsignedTx;
+use ethers::types::{Address, H256, U256};
+use ethers::utils::keccak256;
+use eyre::{eyre, Result};
+use serde::{Deserialize, Serialize};
+use std::{
+    fs::{self, OpenOptions},
+    io::Write,
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct NonceEntry {
+    timestamp: u64,
+    chain_id: U256,
+    from: Address,
+    nonce: U256,
+    signing_hash: H256,
+    to: Option<Address>,
+    value: U256,
+    data_hash: H256,
+    max_fee_per_gas: U256,
+    max_priority_fee: Option<U256>,
+}
+
+impl NonceEntry {
+    fn new(tx: &UnsignedTx, from: Address, signing_hash: H256) -> Self {
+        NonceEntry {
+            timestamp: SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0),
+            chain_id: tx.chain_id(),
+            from,
+            nonce: tx.nonce(),
+            signing_hash,
+            to: tx.to(),
+            value: tx.value(),
+            data_hash: H256::from(keccak256(tx.data())),
+            max_fee_per_gas: tx.max_fee_per_gas(),
+            max_priority_fee: tx.max_priority_fee(),
+        }
+    }
+}
+
+/// What the guard decided for a payload.
+#[derive(Debug, PartialEq, Eq)]
+pub enum NonceCheck {
+    /// First signature for this nonce.
+    Fresh,
+    /// The identical payload was signed before; signing again yields the same transaction.
+    AlreadySigned,
+    /// Accepted fee-bump replacement of an earlier payload.
+    Replacement,
+}
+
+pub struct NonceLedger {
+    path: PathBuf,
+    entries: Vec<NonceEntry>,
+}
+
+/// `$HOME/.tx_signer/nonce_ledger.jsonl`
+pub fn default_ledger_path() -> Option<PathBuf> {
+    std::env::var_os("HOME").map(|home| Path::new(&home).join(".tx_signer").join("nonce_ledger.jsonl"))
+}
+
+impl NonceLedger {
+    pub fn open(path: &Path) -> Result<Self> {
+        let entries = if path.exists() {
+            fs::read_to_string(path)?
+                .lines()
+                .enumerate()
+                .filter(|(_, l)| !l.trim().is_empty())
+                .map(|(i, l)| {
+                    serde_json::from_str(l)
+                        .map_err(|e| eyre!("Nonce ledger {} line {}: {}", path.display(), i + 1, e))
+                })
+                .collect::<Result<Vec<NonceEntry>>>()?
+        } else {
+            Vec::new()
+        };
+        Ok(NonceLedger { path: path.to_path_buf(), entries })
+    }
+
+    /// Decide whether `tx` may be signed. `replace` opts in to fee-bump replacements.
+    pub fn check(&self, tx: &UnsignedTx, from: Address, signing_hash: H256, replace: bool) -> Result<NonceCheck> {
+        let candidate = NonceEntry::new(tx, from, signing_hash);
+        let previous: Vec<&NonceEntry> = self
+            .entries
+            .iter()
+            .filter(|e| e.chain_id == candidate.chain_id && e.from == from && e.nonce == candidate.nonce)
+            .collect();
+
+        let Some(latest) = previous.last() else {
+            return Ok(NonceCheck::Fresh);
+        };
+        if previous.iter().any(|e| e.signing_hash == signing_hash) {
+            return Ok(NonceCheck::AlreadySigned);
+        }
+        if !replace {
+            return Err(eyre!(
+                "Nonce {} for {:?} on chain {} was already used to sign a different payload (signing hash {:?}). \
+                 Refusing to sign a conflicting transaction; pass --replace for a fee bump",
+                candidate.nonce,
+                from,
+                candidate.chain_id,
+                latest.signing_hash
+            ));
+        }
+
+        let mut problems = Vec::new();
+        if candidate.to != latest.to {
+            problems.push(format!("recipient changed from {:?} to {:?}", latest.to, candidate.to));
+        }
+        if candidate.value != latest.value {
+            problems.push(format!("value changed from {} to {}", latest.value, candidate.value));
+        }
+        if candidate.data_hash != latest.data_hash {
+            problems.push("calldata changed".to_string());
+        }
+        if candidate.max_fee_per_gas <= latest.max_fee_per_gas {
+            problems.push(format!(
+                "max price per gas {} is not higher than the previous {}",
+                candidate.max_fee_per_gas, latest.max_fee_per_gas
+            ));
+        }
+        if let (Some(new_tip), Some(old_tip)) = (candidate.max_priority_fee, latest.max_priority_fee)
+            && new_tip < old_tip
+        {
+            problems.push(format!("priority fee {} is lower than the previous {}", new_tip, old_tip));
+        }
+        if problems.is_empty() {
+            Ok(NonceCheck::Replacement)
+        } else {
+            Err(eyre!(
+                "--replace refused for nonce {}: not a pure fee bump ({})",
+                candidate.nonce,
+                problems.join("; ")
+            ))
+        }
+    }
+
+    /// Append the signed payload to the ledger.
+    pub fn record(&mut self, tx: &UnsignedTx, from: Address, signing_hash: H256) -> Result<()> {
+        if let Some(dir) = self.path.parent()
+            && !dir.as_os_str().is_empty()
+        {
+            fs::create_dir_all(dir)?;
+        }
+        let entry = NonceEntry::new(tx, from, signing_hash);
+        let mut f = OpenOptions::new().create(true).append(true).open(&self.path)?;
+        writeln!(f, "{}", serde_json::to_string(&entry)?)?;
+        f.sync_all()?;
+        self.entries.push(entry);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tx(to: u8, value: u64, gas_price: u64) -> UnsignedTx {
+        UnsignedTx::Legacy {
+            nonce: 7u64.into(),
+            gas_price: gas_price.into(),
+            gas_limit: 21_000u64.into(),
+            to: Some(Address::repeat_byte(to)),
+            value: value.into(),
+            data: vec![],
+            chain_id: 1u64.into(),
+        }
+    }
+
+    #[test]
+    fn refuses_equivocation_and_allows_fee_bump() {
+        let path = std::env::temp_dir().join(format!("tx_signer_nonces_{}.jsonl", std::process::id()));
+        let _ = fs::remove_file(&path);
+        let from = Address::repeat_byte(0xaa);
+        let mut ledger = NonceLedger::open(&path).unwrap();
+
+        let original = tx(0x11, 100, 10);
+        let h1 = H256::repeat_byte(1);
+        assert_eq!(ledger.check(&original, from, h1, false).unwrap(), NonceCheck::Fresh);
+        ledger.record(&original, from, h1).unwrap();
+
+        let ledger = NonceLedger::open(&path).unwrap();
+        assert_eq!(ledger.check(&original, from, h1, false).unwrap(), NonceCheck::AlreadySigned);
+
+        // Swapped recipient on the same nonce is refused, with or without --replace.
+        let swapped = tx(0x22, 100, 20);
+        assert!(ledger.check(&swapped, from, H256::repeat_byte(2), false).is_err());
+        assert!(ledger.check(&swapped, from, H256::repeat_byte(2), true).is_err());
+
+        // A pure fee bump needs --replace and a higher price.
+        let bump = tx(0x11, 100, 20);
+        assert!(ledger.check(&bump, from, H256::repeat_byte(3), false).is_err());
+        assert_eq!(ledger.check(&bump, from, H256::repeat_byte(3), true).unwrap(), NonceCheck::Replacement);
+        let cheaper = tx(0x11, 100, 5);
+        assert!(ledger.check(&cheaper, from, H256::repeat_byte(4), true).is_err());
+
+        // Another sender's nonce space is independent.
+        assert_eq!(ledger.check(&swapped, Address::repeat_byte(0xbb), H256::repeat_byte(2), false).unwrap(), NonceCheck::Fresh);
+
+        let _ = fs::remove_file(&path);
+    }
+}
@@ -0,0 +1,214 @@
+//! PKCS#11 token backend (`--pkcs11-module lib.so --key-label cold1`).
+//!
+//! The private key never leaves the token: it performs a raw `CKM_ECDSA` signature over the
+//! 32-byte signing hash. The signature is normalized to low-s and the recovery id is found
+//! locally by matching the recovered address against the token's public key.
+
+use crate::assemble::normalize_s;
+use cryptoki::context::{CInitializeArgs, CInitializeFlags, Pkcs11};
+use cryptoki::mechanism::Mechanism;
+use cryptoki::object::{Attribute, AttributeType, KeyType, ObjectClass, ObjectHandle};
+use cryptoki::session::{Session, UserType};
+use cryptoki::types::AuthPin;
+use ethers::types::{Address, Signature, H256, U256};
+use ethers::utils::keccak256;
+use eyre::{eyre, Result};
+use std::path::Path;
+use std::sync::Mutex;
+
+/// DER encoding of the secp256k1 curve OID (1.3.132.0.10), as found in `CKA_EC_PARAMS`.
+pub const SECP256K1_EC_PARAMS: [u8; 7] = [0x06, 0x05, 0x2b, 0x81, 0x04, 0x00, 0x0a];
+
+pub struct Pkcs11Key {
+    /// Sessions are not thread-safe; the lock serializes concurrent signers onto the token.
+    session: Mutex<Session>,
+    key: ObjectHandle,
+    address: Address,
+}
+
+/// X || Y from `CKA_EC_POINT`, which tokens return either DER-wrapped or raw.
+fn uncompressed_point(ec_point: &[u8]) -> Result<[u8; 64]> {
+    let point = match ec_point {
+        [0x04, 0x41, rest @ ..] if rest.len() == 65 => rest,
+        raw => raw,
+    };
+    match point {
+        [0x04, xy @ ..] if xy.len() == 64 => Ok(xy.try_into().expect("64 bytes")),
+        _ => Err(eyre!("Unsupported EC point encoding ({} bytes); expected an uncompressed point", ec_point.len())),
+    }
+}
+
+fn label_template(class: ObjectClass, label: &str) -> Vec<Attribute> {
+    vec![
+        Attribute::Class(class),
+        Attribute::KeyType(KeyType::EC),
+        Attribute::Label(label.as_bytes().to_vec()),
+    ]
+}
+
+impl Pkcs11Key {
+    /// Load `module`, find the token holding a secp256k1 key pair labeled `label` and log in.
+    pub fn open(module: &Path, label: &str, pin: AuthPin) -> Result<Self> {
+        let ctx = Pkcs11::new(module).map_err(|e| eyre!("Cannot load PKCS#11 module {}: {}", module.display(), e))?;
+        ctx.initialize(CInitializeArgs::new(CInitializeFlags::OS_LOCKING_OK))?;
+
+        // Locate the key by its public half first, so we only log in to the one token.
+        let mut found = Vec::new();
+        for slot in ctx.get_slots_with_token()? {
+            let session = ctx.open_ro_session(slot)?;
+            let public = session.find_objects(&label_template(ObjectClass::PUBLIC_KEY, label))?;
+            if let Some(&public) = public.first() {
+                found.push((slot, session, public));
+            }
+        }
+        let (slot, session, public) = match found.len() {
+            0 => return Err(eyre!("No token holds an EC public key labeled {:?}", label)),
+            1 => found.pop().expect("one entry"),
+            n => return Err(eyre!("{} tokens hold a key labeled {:?}; labels must be unique", n, label)),
+        };
+
+        let attrs = session.get_attributes(public, &[AttributeType::EcParams, AttributeType::EcPoint])?;
+        let (mut params, mut point) = (None, None);
+        for attr in attrs {
+            match attr {
+                Attribute::EcParams(p) => params = Some(p),
+                Attribute::EcPoint(p) => point = Some(p),
+                _ => {}
+            }
+        }
+        if params.as_deref() != Some(&SECP256K1_EC_PARAMS[..]) {
+            return Err(eyre!("Key {:?} is not on the secp256k1 curve", label));
+        }
+        let xy = uncompressed_point(&point.ok_or_else(|| eyre!("Key {:?} has no CKA_EC_POINT", label))?)?;
+        let address = Address::from_slice(&keccak256(xy)[12..]);
+
+        session
+            .login(UserType::User, Some(&pin))
+            .map_err(|e| eyre!("PKCS#11 login to slot {} failed: {}", slot, e))?;
+        let key = *session
+            .find_objects(&label_template(ObjectClass::PRIVATE_KEY, label))?
+            .first()
+            .ok_or_else(|| eyre!("No EC private key labeled {:?} on the token", label))?;
+
+        Ok(Pkcs11Key { session: Mutex::new(session), key, address })
+    }
+
+    pub fn address(&self) -> Address {
+        self.address
+    }
+
+    /// Raw ECDSA over `hash` on the token; returns a low-s signature with `v` = 27/28.
+    pub fn sign_hash(&self, hash: H256) -> Result<Signature> {
+        let raw = self.session.lock().expect("PKCS#11 session lock").sign(&Mechanism::Ecdsa, self.key, hash.as_bytes())?;
+        if raw.len() != 64 {
+            return Err(eyre!("Token returned a {}-byte ECDSA signature, expected 64 (r || s)", raw.len()));
+        }
+        let r = U256::from_big_endian(&raw[..32]);
+        let (s, _) = normalize_s(U256::from_big_endian(&raw[32..]), 0);
+        (0..2)
+            .map(|recid| Signature { r, s, v: 27 + recid })
+            .find(|sig| sig.recover(hash).ok() == Some(self.address))
+            .ok_or_else(|| eyre!("Token signature does not recover to the key's address {:?}", self.address))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ethers::signers::{LocalWallet, Signer};
+    use std::str::FromStr;
+
+    #[test]
+    fn decodes_ec_point_encodings() {
+        let wallet = LocalWallet::from_str("4c0883a69102937d6231471b5ecb4765d5e97f8e4dc6e8fa6a4de3b8a3a2f55b").unwrap();
+        let raw = wallet.signer().verifying_key().to_encoded_point(false).as_bytes().to_vec();
+        let mut der = vec![0x04, 0x41];
+        der.extend(&raw);
+        for encoded in [&raw, &der] {
+            let xy = uncompressed_point(encoded).unwrap();
+            assert_eq!(Address::from_slice(&keccak256(xy)[12..]), wallet.address());
+        }
+        assert!(uncompressed_point(&raw[..33]).is_err());
+    }
+
+    /// End-to-end against SoftHSMv2, enabled with `--features softhsm-tests`. Fails if
+    /// SoftHSMv2 is missing. The token lives in a fresh directory whose config is handed to
+    /// [`softhsm_child`] through its process environment.
+    #[test]
+    #[cfg_attr(not(feature = "softhsm-tests"), ignore = "needs SoftHSMv2; run with --features softhsm-tests")]
+    fn signs_with_softhsm() {
+        let module = std::env::var("SOFTHSM2_MODULE").ok().or_else(|| {
+            ["/usr/lib/softhsm/libsofthsm2.so", "/usr/lib/x86_64-linux-gnu/softhsm/libsofthsm2.so", "/usr/local/lib/softhsm/libsofthsm2.so"]
+                .into_iter()
+                .find(|p| Path::new(p).exists())
+                .map(String::from)
+        });
+        let module = module.expect("SoftHSMv2 not found: install softhsm2 or set SOFTHSM2_MODULE");
+
+        let dir = std::env::temp_dir().join(format!("tx_signer_softhsm_{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("tokens")).unwrap();
+        let conf = dir.join("softhsm2.conf");
+        std::fs::write(&conf, format!("directories.tokendir = {}\nobjectstore.backend = file\n", dir.join("tokens").display())).unwrap();
+
+        let child = format!("{}::softhsm_child", module_path!().split_once("::").unwrap().1);
+        let output = std::process::Command::new(std::env::current_exe().unwrap())
+            .args([child.as_str(), "--exact", "--ignored", "--nocapture", "--test-threads=1"])
+            .env("SOFTHSM2_CONF", &conf)
+            .env("SOFTHSM2_MODULE", &module)
+            .output()
+            .unwrap();
+        let _ = std::fs::remove_dir_all(&dir);
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        assert!(output.status.success() && stdout.contains("1 passed"), "{stdout}{}", String::from_utf8_lossy(&output.stderr));
+    }
+
+    /// Body of [`signs_with_softhsm`], run in a child process with `SOFTHSM2_CONF` set.
+    #[test]
+    #[ignore = "spawned by signs_with_softhsm"]
+    fn softhsm_child() {
+        let module = std::env::var("SOFTHSM2_MODULE").expect("run through signs_with_softhsm");
+        assert!(std::env::var_os("SOFTHSM2_CONF").is_some(), "run through signs_with_softhsm");
+
+        // Provision a token and import a known secp256k1 key.
+        let secret = hex::decode("4c0883a69102937d6231471b5ecb4765d5e97f8e4dc6e8fa6a4de3b8a3a2f55b").unwrap();
+        let wallet = LocalWallet::from_bytes(&secret).unwrap();
+        let point = wallet.signer().verifying_key().to_encoded_point(false).as_bytes().to_vec();
+        let mut der_point = vec![0x04, 0x41];
+        der_point.extend(&point);
+        {
+            let ctx = Pkcs11::new(&module).unwrap();
+            ctx.initialize(CInitializeArgs::new(CInitializeFlags::OS_LOCKING_OK)).unwrap();
+            let slot = ctx.get_all_slots().unwrap()[0];
+            ctx.init_token(slot, &AuthPin::from("so-pin".to_string()), "tx_signer test").unwrap();
+            let slot = ctx.get_slots_with_token().unwrap()[0];
+            let session = ctx.open_rw_session(slot).unwrap();
+            session.login(UserType::So, Some(&AuthPin::from("so-pin".to_string()))).unwrap();
+            session.init_pin(&AuthPin::from("1234".to_string())).unwrap();
+            session.logout().unwrap();
+            session.login(UserType::User, Some(&AuthPin::from("1234".to_string()))).unwrap();
+            let common = |class| {
+                let mut t = label_template(class, "cold1");
+                t.extend([Attribute::Token(true), Attribute::EcParams(SECP256K1_EC_PARAMS.to_vec())]);
+                t
+            };
+            let mut private = common(ObjectClass::PRIVATE_KEY);
+            private.extend([Attribute::Value(secret.clone()), Attribute::Sign(true), Attribute::Private(true)]);
+            let mut public = common(ObjectClass::PUBLIC_KEY);
+            public.extend([Attribute::EcPoint(der_point), Attribute::Private(false)]);
+            session.create_object(&private).unwrap();
+            session.create_object(&public).unwrap();
+            drop(session);
+            ctx.finalize().unwrap();
+        }
+
+        let key = Pkcs11Key::open(Path::new(&module), "cold1", AuthPin::from("1234".to_string())).unwrap();
+        assert_eq!(key.address(), wallet.address());
+        for i in 0..8u8 {
+            let hash = H256::from(keccak256([i]));
+            let sig = key.sign_hash(hash).unwrap();
+            assert_eq!(sig.recover(hash).unwrap(), wallet.address());
+            let (low_s, _) = normalize_s(sig.s, 0);
+            assert_eq!(sig.s, low_s);
+        }
+    }
+}
@@ -0,0 +1,397 @@
+//! Signing policy loaded from `--policy policy.toml` and evaluated against the parsed
+//! unsigned transaction before anything is signed.
+//!
+//! ```toml
+//! allowed_chain_ids = [1, 137]
+//! recipient_allowlist = ["0x000000000000000000000000000000000000dEaD"]
+//! recipient_denylist = []
+//! max_value = "1.5"               # native units (ETH, POL, ...)
+//! max_gas_price_gwei = "200"      # gasPrice (legacy) or maxFeePerGas (type-2)
+//! max_priority_fee_gwei = "5"
+//! max_fee = "0.05"                # gasLimit * max price per gas, native units
+//! allowed_selectors = ["0xa9059cbb", "approve(address,uint256)"]
+//! allow_contract_creation = false
+//! allow_unattended = false        # whether --yes may skip the typed confirmation
+//!
+//! [[token_cap]]                   # per-call cap on an ERC-20 amount
+//! token = "0xA0b86991c6218b36c1d19D4a2e9Eb0cE3606eB48"
+//! decimals = 6
+//! max = "10000"
+//! ```
+//!
+//! The recipient lists apply to `to` and, for ERC-20 `transfer`, `transferFrom`, `approve`
+//! and `increaseAllowance`, to the token recipient or spender as well. Rules that are
+//! omitted are not enforced, except contract creation which is denied unless explicitly
+//! allowed. Rolling spending budgets live in an optional `[spending]`
+//! section, see `spending.rs`.
+
+use crate::erc20;
+use crate::spending::{SpendingConfig, SpendingLimits};
+use crate::UnsignedTx;
+use ethers::types::{Address, U256};
+use ethers::utils::{format_units, id, parse_units};
+use eyre::{eyre, Result};
+use serde::Deserialize;
+use std::{fmt, fs, path::Path};
+
+#[derive(Deserialize, Debug, Default)]
+#[serde(deny_unknown_fields)]
+struct PolicyFile {
+    allowed_chain_ids: Option<Vec<u64>>,
+    recipient_allowlist: Option<Vec<Address>>,
+    #[serde(default)]
+    recipient_denylist: Vec<Address>,
+    max_value: Option<String>,
+    max_gas_price_gwei: Option<String>,
+    max_priority_fee_gwei: Option<String>,
+    max_fee: Option<String>,
+    allowed_selectors: Option<Vec<String>>,
+    #[serde(default)]
+    allow_contract_creation: bool,
+    #[serde(default)]
+    allow_unattended: bool,
+    #[serde(default, rename = "token_cap")]
+    token_caps: Vec<TokenCapConfig>,
+    spending: Option<SpendingConfig>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+struct TokenCapConfig {
+    token: Address,
+    #[serde(default = "default_decimals")]
+    decimals: u32,
+    max: String,
+}
+
+fn default_decimals() -> u32 {
+    18
+}
+
+/// Largest amount a single ERC-20 call to `token` may move or approve, in base units.
+#[derive(Debug)]
+struct TokenCap {
+    token: Address,
+    decimals: u32,
+    max: U256,
+}
+
+/// Compiled policy with amounts converted to wei and selectors to bytes.
+#[derive(Debug, Default)]
+pub struct Policy {
+    allowed_chain_ids: Option<Vec<u64>>,
+    recipient_allowlist: Option<Vec<Address>>,
+    recipient_denylist: Vec<Address>,
+    max_value: Option<U256>,
+    max_gas_price: Option<U256>,
+    max_priority_fee: Option<U256>,
+    max_fee: Option<U256>,
+    allowed_selectors: Option<Vec<[u8; 4]>>,
+    allow_contract_creation: bool,
+    /// Lets `--yes` skip the typed confirmation.
+    pub allow_unattended: bool,
+    token_caps: Vec<TokenCap>,
+    pub spending: Option<SpendingLimits>,
+}
+
+/// A single failed rule.
+#[derive(Debug)]
+pub struct Violation {
+    pub rule: &'static str,
+    pub detail: String,
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.rule, self.detail)
+    }
+}
+
+fn parse_amount(field: &str, value: Option<String>, unit: &str) -> Result<Option<U256>> {
+    value
+        .map(|v| {
+            parse_units(&v, unit)
+                .map(Into::into)
+                .map_err(|e| eyre!("Invalid {} {:?}: {}", field, v, e))
+        })
+        .transpose()
+}
+
+/// Accept either a 4-byte hex selector or a function signature such as `transfer(address,uint256)`.
+fn parse_selector(s: &str) -> Result<[u8; 4]> {
+    if s.contains('(') {
+        return Ok(id(s));
+    }
+    let bytes = hex::decode(s.trim_start_matches("0x")).map_err(|e| eyre!("Invalid selector {:?}: {}", s, e))?;
+    bytes
+        .try_into()
+        .map_err(|_| eyre!("Selector {:?} must be exactly 4 bytes", s))
+}
+
+fn fmt_native(v: U256) -> String {
+    format_units(v, "ether").unwrap_or_else(|_| v.to_string())
+}
+
+fn fmt_gwei(v: U256) -> String {
+    format_units(v, "gwei").unwrap_or_else(|_| v.to_string())
+}
+
+impl Policy {
+    pub fn load(path: &Path) -> Result<Self> {
+        let text = fs::read_to_string(path)?;
+        Self::from_toml(&text).map_err(|e| eyre!("Policy {}: {}", path.display(), e))
+    }
+
+    pub fn from_toml(text: &str) -> Result<Self> {
+        let raw: PolicyFile = toml::from_str(text)?;
+        Ok(Policy {
+            allowed_chain_ids: raw.allowed_chain_ids,
+            recipient_allowlist: raw.recipient_allowlist,
+            recipient_denylist: raw.recipient_denylist,
+            max_value: parse_amount("max_value", raw.max_value, "ether")?,
+            max_gas_price: parse_amount("max_gas_price_gwei", raw.max_gas_price_gwei, "gwei")?,
+            max_priority_fee: parse_amount("max_priority_fee_gwei", raw.max_priority_fee_gwei, "gwei")?,
+            max_fee: parse_amount("max_fee", raw.max_fee, "ether")?,
+            allowed_selectors: raw
+                .allowed_selectors
+                .map(|v| v.iter().map(|s| parse_selector(s)).collect::<Result<Vec<_>>>())
+                .transpose()?,
+            allow_contract_creation: raw.allow_contract_creation,
+            allow_unattended: raw.allow_unattended,
+            token_caps: raw
+                .token_caps
+                .into_iter()
+                .map(|c| {
+                    let max = parse_units(&c.max, c.decimals).map_err(|e| eyre!("Invalid token_cap max {:?}: {}", c.max, e))?;
+                    Ok(TokenCap { token: c.token, decimals: c.decimals, max: max.into() })
+                })
+                .collect::<Result<Vec<_>>>()?,
+            spending: raw.spending.map(SpendingConfig::compile).transpose()?,
+        })
+    }
+
+    /// Check every rule and return all violations (empty when the tx is allowed).
+    pub fn evaluate(&self, tx: &UnsignedTx) -> Vec<Violation> {
+        let mut out = Vec::new();
+        let mut fail = |rule: &'static str, detail: String| out.push(Violation { rule, detail });
+
+        if let Some(allowed) = &self.allowed_chain_ids {
+            let chain_id = tx.chain_id();
+            if !allowed.iter().any(|&c| U256::from(c) == chain_id) {
+                fail("allowed_chain_ids", format!("chain {} is not in {:?}", chain_id, allowed));
+            }
+        }
+
+        match tx.to() {
+            None => {
+                if !self.allow_contract_creation {
+                    fail("allow_contract_creation", "contract creation is not allowed".to_string());
+                }
+            }
+            Some(to) => {
+                for (address, party) in std::iter::once((to, "recipient")).chain(erc20::counterparty(tx.data())) {
+                    if self.recipient_denylist.contains(&address) {
+                        fail("recipient_denylist", format!("{} {:?} is denylisted", party, address));
+                    }
+                    if let Some(allow) = &self.recipient_allowlist
+                        && !allow.contains(&address)
+                    {
+                        fail("recipient_allowlist", format!("{} {:?} is not allowlisted", party, address));
+                    }
+                }
+            }
+        }
+
+        if let Some(cap) = self.max_value
+            && tx.value() > cap
+        {
+            fail(
+                "max_value",
+                format!("value {} exceeds cap {}", fmt_native(tx.value()), fmt_native(cap)),
+            );
+        }
+
+        if let Some(cap) = self.token_caps.iter().find(|c| tx.to() == Some(c.token))
+            && let Some(amount) = erc20::amount(tx.data())
+            && amount > cap.max
+        {
+            let fmt = |v: U256| format_units(v, cap.decimals).unwrap_or_else(|_| v.to_string());
+            fail(
+                "token_cap",
+                format!("token amount {} exceeds cap {} for {:?}", fmt(amount), fmt(cap.max), cap.token),
+            );
+        }
+
+        if let Some(cap) = self.max_gas_price
+            && tx.max_fee_per_gas() > cap
+        {
+            fail(
+                "max_gas_price_gwei",
+                format!("price per gas {} gwei exceeds cap {} gwei", fmt_gwei(tx.max_fee_per_gas()), fmt_gwei(cap)),
+            );
+        }
+
+        if let (Some(cap), Some(tip)) = (self.max_priority_fee, tx.max_priority_fee())
+            && tip > cap
+        {
+            fail(
+                "max_priority_fee_gwei",
+                format!("priority fee {} gwei exceeds cap {} gwei", fmt_gwei(tip), fmt_gwei(cap)),
+            );
+        }
+
+        if let Some(cap) = self.max_fee
+            && tx.max_fee_cost() > cap
+        {
+            fail(
+                "max_fee",
+                format!("max fee cost {} exceeds cap {}", fmt_native(tx.max_fee_cost()), fmt_native(cap)),
+            );
+        }
+
+        if let Some(selectors) = &self.allowed_selectors
+            && tx.to().is_some()
+            && !tx.data().is_empty()
+        {
+            match tx.data().get(..4) {
+                Some(sel) if selectors.iter().any(|s| s == sel) => {}
+                Some(sel) => fail("allowed_selectors", format!("function selector 0x{} is not allowed", hex::encode(sel))),
+                None => fail(
+                    "allowed_selectors",
+                    format!("calldata of {} bytes is too short for a selector", tx.data().len()),
+                ),
+            }
+        }
+
+        out
+    }
+}
+
+/// Print the violation report to stderr and turn it into an error.
+pub fn enforce(policy: &Policy, tx: &UnsignedTx) -> Result<()> {
+    let violations = policy.evaluate(tx);
+    if violations.is_empty() {
+        println!("Policy check passed");
+        return Ok(());
+    }
+    eprintln!("Policy violations:");
+    for v in &violations {
+        eprintln!("  - {v}");
+    }
+    Err(eyre!("Refusing to sign: {} policy rule(s) failed", violations.len()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ethers::abi::Token;
+    use std::str::FromStr;
+
+    fn tx(chain_id: u64, to: Option<&str>, value_eth: &str, data: Vec<u8>) -> UnsignedTx {
+        UnsignedTx::Eip1559 {
+            chain_id: chain_id.into(),
+            nonce: 0u64.into(),
+            max_priority_fee: parse_units("2", "gwei").unwrap().into(),
+            max_fee: parse_units("50", "gwei").unwrap().into(),
+            gas_limit: 100_000u64.into(),
+            to: to.map(|a| Address::from_str(a).unwrap()),
+            value: parse_units(value_eth, "ether").unwrap().into(),
+            data,
+        }
+    }
+
+    const DEAD: &str = "0x000000000000000000000000000000000000dEaD";
+    const BEEF: &str = "0xdeadbeefdeadbeefdeadbeefdeadbeefdeadbeef";
+
+    #[test]
+    fn allows_matching_transaction() {
+        let policy = Policy::from_toml(&format!(
+            r#"
+            allowed_chain_ids = [1]
+            recipient_allowlist = ["{DEAD}"]
+            max_value = "1"
+            max_gas_price_gwei = "100"
+            max_fee = "0.01"
+            allowed_selectors = ["transfer(address,uint256)"]
+            "#
+        ))
+        .unwrap();
+        let data = transfer(DEAD, 5);
+        assert!(policy.evaluate(&tx(1, Some(DEAD), "0.5", data)).is_empty());
+    }
+
+    fn transfer(recipient: &str, amount: u64) -> Vec<u8> {
+        let args = [Token::Address(Address::from_str(recipient).unwrap()), Token::Uint(amount.into())];
+        [&id("transfer(address,uint256)")[..], &ethers::abi::encode(&args)].concat()
+    }
+
+    #[test]
+    fn recipient_lists_and_token_caps_apply_to_erc20_calldata() {
+        const TOKEN: &str = "0xA0b86991c6218b36c1d19D4a2e9Eb0cE3606eB48";
+        let policy = Policy::from_toml(&format!(
+            r#"
+            recipient_allowlist = ["{TOKEN}", "{DEAD}"]
+            recipient_denylist = ["{BEEF}"]
+            [[token_cap]]
+            token = "{TOKEN}"
+            decimals = 6
+            max = "100"
+            "#
+        ))
+        .unwrap();
+        assert!(policy.evaluate(&tx(1, Some(TOKEN), "0", transfer(DEAD, 100_000_000))).is_empty());
+
+        // The token contract is allowlisted, the token recipient is not.
+        let rules = |data| policy.evaluate(&tx(1, Some(TOKEN), "0", data)).iter().map(|v| v.rule).collect::<Vec<_>>();
+        assert_eq!(rules(transfer(BEEF, 1)), ["recipient_denylist", "recipient_allowlist"]);
+        assert_eq!(rules(transfer("0x0000000000000000000000000000000000000001", 1)), ["recipient_allowlist"]);
+        assert_eq!(rules(transfer(DEAD, 100_000_001)), ["token_cap"]);
+    }
+
+    #[test]
+    fn reports_every_failed_rule() {
+        let policy = Policy::from_toml(&format!(
+            r#"
+            allowed_chain_ids = [137]
+            recipient_denylist = ["{BEEF}"]
+            max_value = "1"
+            max_priority_fee_gwei = "1"
+            max_fee = "0.001"
+            allowed_selectors = ["0xa9059cbb"]
+            "#
+        ))
+        .unwrap();
+        let rules: Vec<&str> = policy
+            .evaluate(&tx(1, Some(BEEF), "2", vec![0x12, 0x34, 0x56, 0x78]))
+            .iter()
+            .map(|v| v.rule)
+            .collect();
+        assert_eq!(
+            rules,
+            [
+                "allowed_chain_ids",
+                "recipient_denylist",
+                "max_value",
+                "max_priority_fee_gwei",
+                "max_fee",
+                "allowed_selectors"
+            ]
+        );
+    }
+
+    #[test]
+    fn contract_creation_denied_by_default() {
+        let policy = Policy::from_toml("").unwrap();
+        let v = policy.evaluate(&tx(1, None, "0", vec![0x60, 0x80]));
+        assert_eq!(v.len(), 1);
+        assert_eq!(v[0].rule, "allow_contract_creation");
+
+        let policy = Policy::from_toml("allow_contract_creation = true").unwrap();
+        assert!(policy.evaluate(&tx(1, None, "0", vec![0x60, 0x80])).is_empty());
+    }
+
+    #[test]
+    fn rejects_unknown_keys() {
+        assert!(Policy::from_toml("max_valu = \"1\"").is_err());
+    }
+}
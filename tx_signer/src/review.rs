@@ -0,0 +1,346 @@
+//! "What you sign is what you see" review screen.
+//!
+//! Renders the decoded unsigned transaction (recipient with checksum and label, value in
+//! native units, decoded calldata, worst-case fee, chain and nonce) and requires the
+//! operator to type the last 4 hex characters of the recipient before signing. For ERC-20
+//! `transfer`, `transferFrom` and `approve` that is the token recipient or spender, not the
+//! token contract.
+
+use crate::erc20;
+use crate::policy::Policy;
+use crate::UnsignedTx;
+use ethers::abi::{Abi, Function, Token};
+use ethers::types::{Address, Chain, H256, U256};
+use ethers::utils::{format_units, keccak256, to_checksum};
+use eyre::{eyre, Result};
+use std::{
+    collections::HashMap,
+    fs,
+    io::{BufRead, BufReader, Write},
+    path::{Path, PathBuf},
+};
+
+/// Calls decoded without any user supplied ABI.
+const BUILTIN_FUNCTIONS: &[&str] = &[
+    "function transfer(address to, uint256 amount)",
+    "function approve(address spender, uint256 amount)",
+    "function transferFrom(address from, address to, uint256 amount)",
+];
+
+pub struct Reviewer {
+    labels: HashMap<Address, String>,
+    functions: Vec<Function>,
+}
+
+impl Reviewer {
+    /// `address_book` is a TOML table of `"0xaddress" = "label"`; `abi_files` are JSON ABIs
+    /// used to decode calldata in addition to the ERC-20 built-ins.
+    pub fn new(address_book: Option<&Path>, abi_files: &[PathBuf]) -> Result<Self> {
+        let labels = match address_book {
+            Some(path) => toml::from_str::<HashMap<Address, String>>(&fs::read_to_string(path)?)
+                .map_err(|e| eyre!("Address book {}: {}", path.display(), e))?,
+            None => HashMap::new(),
+        };
+
+        let mut functions: Vec<Function> = ethers::abi::parse_abi(BUILTIN_FUNCTIONS)?.functions().cloned().collect();
+        for path in abi_files {
+            let abi: Abi = serde_json::from_str(&fs::read_to_string(path)?)
+                .map_err(|e| eyre!("ABI {}: {}", path.display(), e))?;
+            functions.extend(abi.functions().cloned());
+        }
+        Ok(Reviewer { labels, functions })
+    }
+
+    /// Checksummed address followed by its address-book label.
+    pub fn address(&self, a: &Address) -> String {
+        match self.labels.get(a) {
+            Some(label) => format!("{} ({})", to_checksum(a, None), label),
+            None => format!("{} (unlabeled)", to_checksum(a, None)),
+        }
+    }
+
+    fn token(&self, t: &Token) -> String {
+        match t {
+            Token::Address(a) => self.address(a),
+            Token::Uint(v) | Token::Int(v) => v.to_string(),
+            Token::Bool(b) => b.to_string(),
+            Token::String(s) => format!("{:?}", s),
+            Token::Bytes(b) | Token::FixedBytes(b) => format!("0x{}", hex::encode(b)),
+            Token::Array(items) | Token::FixedArray(items) | Token::Tuple(items) => {
+                format!("[{}]", items.iter().map(|i| self.token(i)).collect::<Vec<_>>().join(", "))
+            }
+        }
+    }
+
+    fn calldata(&self, data: &[u8]) -> Vec<String> {
+        if data.is_empty() {
+            return vec!["(none, plain transfer)".to_string()];
+        }
+        let Some(selector) = data.get(..4) else {
+            return vec![format!("0x{} ({} bytes, no selector)", hex::encode(data), data.len())];
+        };
+        let decoded = self
+            .functions
+            .iter()
+            .filter(|f| f.short_signature() == selector)
+            .find_map(|f| f.decode_input(&data[4..]).ok().map(|tokens| (f, tokens)));
+        match decoded {
+            Some((f, tokens)) => {
+                let mut lines = vec![format!("{} (0x{})", f.signature(), hex::encode(selector))];
+                for (param, token) in f.inputs.iter().zip(&tokens) {
+                    let name = if param.name.is_empty() { "arg" } else { &param.name };
+                    lines.push(format!("  {}: {} = {}", name, param.kind, self.token(token)));
+                }
+                lines
+            }
+            None => vec![
+                format!("UNKNOWN function selector 0x{}", hex::encode(selector)),
+                format!("  {} bytes of arguments: 0x{}", data.len() - 4, hex::encode(&data[4..])),
+            ],
+        }
+    }
+
+    /// The review screen as printed before confirmation.
+    pub fn render(&self, tx: &UnsignedTx, from: Address) -> String {
+        let chain_id = tx.chain_id();
+        let (chain, symbol) = chain_name(chain_id);
+        let gwei = |v: U256| format_units(v, "gwei").unwrap_or_else(|_| v.to_string());
+        let native = |v: U256| format!("{} {}", format_units(v, "ether").unwrap_or_else(|_| v.to_string()), symbol);
+
+        let mut out = vec![
+            "==================== REVIEW TRANSACTION ====================".to_string(),
+            format!("Chain:        {} ({})", chain_id, chain),
+            format!("From:         {}", self.address(&from)),
+            match tx.to() {
+                Some(to) => format!("To:           {}", self.address(&to)),
+                None => "To:           CONTRACT CREATION".to_string(),
+            },
+            format!("Value:        {}", native(tx.value())),
+            format!("Nonce:        {}", tx.nonce()),
+            format!("Gas limit:    {}", tx.gas_limit()),
+        ];
+        match tx.max_priority_fee() {
+            Some(tip) => {
+                out.push(format!("Max fee:      {} gwei per gas", gwei(tx.max_fee_per_gas())));
+                out.push(format!("Priority fee: {} gwei per gas", gwei(tip)));
+            }
+            None => out.push(format!("Gas price:    {} gwei", gwei(tx.max_fee_per_gas()))),
+        }
+        out.push(format!("Max fee cost: {}", native(tx.max_fee_cost())));
+        out.push(format!("Max total:    {}", native(tx.value().saturating_add(tx.max_fee_cost()))));
+        let mut calldata = self.calldata(tx.data()).into_iter();
+        out.push(format!("Calldata:     {}", calldata.next().unwrap_or_default()));
+        out.extend(calldata.map(|l| format!("              {l}")));
+        out.push("============================================================".to_string());
+        out.join("\n")
+    }
+
+    /// Print the review and require the typed confirmation token, unless `assume_yes` and
+    /// the policy allows unattended signing.
+    pub fn confirm(&self, tx: &UnsignedTx, from: Address, assume_yes: bool, policy: Option<&Policy>) -> Result<()> {
+        println!("{}", self.render(tx, from));
+        if assume_yes {
+            return skip_confirmation(policy);
+        }
+
+        let (expected, party) = confirmation_token(tx);
+        let prompt = match tx.to() {
+            Some(_) => format!("Type the last 4 hex characters of the {} to sign: ", party),
+            None => format!("Type \"{}\" to sign the contract creation: ", expected),
+        };
+        confirm_on_tty(&prompt, &expected)
+    }
+}
+
+/// `--yes`: allowed only when the policy opts in with `allow_unattended = true`.
+pub fn skip_confirmation(policy: Option<&Policy>) -> Result<()> {
+    if !policy.is_some_and(|p| p.allow_unattended) {
+        return Err(eyre!("--yes needs a policy with allow_unattended = true; nothing was signed"));
+    }
+    println!("Confirmation skipped (--yes, policy allows unattended signing)");
+    Ok(())
+}
+
+/// Ask on the controlling terminal and require `expected` (case-insensitive) as the answer.
+pub fn confirm_on_tty(prompt: &str, expected: &str) -> Result<()> {
+    let mut tty = fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open("/dev/tty")
+        .map_err(|e| eyre!("No terminal available for confirmation: {}", e))?;
+    tty.write_all(prompt.as_bytes())?;
+    tty.flush()?;
+    let mut answer = String::new();
+    BufReader::new(tty).read_line(&mut answer)?;
+
+    if answer.trim().eq_ignore_ascii_case(expected) {
+        Ok(())
+    } else {
+        Err(eyre!("Confirmation did not match; nothing was signed"))
+    }
+}
+
+/// What the operator must type, and whose address it comes from: the last 4 hex chars of the
+/// token recipient or spender for ERC-20 calls, of `to` otherwise, or "create".
+fn confirmation_token(tx: &UnsignedTx) -> (String, &'static str) {
+    let Some(to) = tx.to() else {
+        return ("create".to_string(), "contract creation");
+    };
+    let (address, party) = erc20::counterparty(tx.data()).unwrap_or((to, "recipient"));
+    let hex = format!("{:x}", address);
+    (hex[hex.len() - 4..].to_string(), party)
+}
+
+/// Digest over the chain, target, counterparty and amounts of every transaction in a batch,
+/// in order. Typing its tail confirms the batch as a whole, the way a counterparty's tail
+/// confirms a single transaction.
+pub fn batch_digest<'a>(txs: impl IntoIterator<Item = &'a UnsignedTx>) -> H256 {
+    let mut preimage = Vec::new();
+    let word = |v: U256| {
+        let mut bytes = [0u8; 32];
+        v.to_big_endian(&mut bytes);
+        bytes
+    };
+    for tx in txs {
+        let to = tx.to().unwrap_or_default();
+        let (party, _) = erc20::counterparty(tx.data()).unwrap_or((to, "recipient"));
+        preimage.extend(word(tx.chain_id()));
+        preimage.extend(to.as_bytes());
+        preimage.extend(party.as_bytes());
+        preimage.extend(word(tx.value()));
+        preimage.extend(word(erc20::amount(tx.data()).unwrap_or_default()));
+    }
+    keccak256(preimage).into()
+}
+
+pub fn chain_name(chain_id: U256) -> (String, &'static str) {
+    match Chain::try_from(chain_id) {
+        Ok(chain) => {
+            let symbol = match chain {
+                Chain::Polygon | Chain::PolygonMumbai | Chain::PolygonAmoy => "POL",
+                Chain::BinanceSmartChain => "BNB",
+                Chain::Gnosis => "xDAI",
+                Chain::Avalanche => "AVAX",
+                _ => "ETH",
+            };
+            (chain.to_string(), symbol)
+        }
+        Err(_) => ("UNKNOWN CHAIN".to_string(), "native units"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ethers::utils::{id, parse_units};
+    use std::str::FromStr;
+
+    #[test]
+    fn renders_decoded_erc20_transfer_with_labels() {
+        let token = Address::from_str("0x2791Bca1f2de4661ED88A30C99A7a9449Aa84174").unwrap();
+        let payee = Address::from_str("0x000000000000000000000000000000000000dEaD").unwrap();
+        let mut labels = HashMap::new();
+        labels.insert(token, "USDC.e".to_string());
+        labels.insert(payee, "Payroll".to_string());
+        let reviewer = Reviewer { labels, ..Reviewer::new(None, &[]).unwrap() };
+
+        let mut data = id("transfer(address,uint256)").to_vec();
+        data.extend(ethers::abi::encode(&[Token::Address(payee), Token::Uint(1_500_000u64.into())]));
+        let tx = UnsignedTx::Eip1559 {
+            chain_id: 137u64.into(),
+            nonce: 4u64.into(),
+            max_priority_fee: parse_units("30", "gwei").unwrap().into(),
+            max_fee: parse_units("100", "gwei").unwrap().into(),
+            gas_limit: 60_000u64.into(),
+            to: Some(token),
+            value: U256::zero(),
+            data,
+        };
+
+        let screen = reviewer.render(&tx, Address::repeat_byte(0xaa));
+        assert!(screen.contains("137 (polygon)"), "{screen}");
+        assert!(screen.contains("0x2791Bca1f2de4661ED88A30C99A7a9449Aa84174 (USDC.e)"), "{screen}");
+        assert!(screen.contains("transfer(address,uint256) (0xa9059cbb)"), "{screen}");
+        assert!(screen.contains("to: address = 0x000000000000000000000000000000000000dEaD (Payroll)"), "{screen}");
+        assert!(screen.contains("amount: uint256 = 1500000"), "{screen}");
+        assert!(screen.contains("Max fee cost: 0.006000000000000000 POL"), "{screen}");
+        assert_eq!(confirmation_token(&tx), ("dead".to_string(), "token recipient"));
+    }
+
+    #[test]
+    fn confirmation_token_follows_erc20_counterparty() {
+        let token = Address::from_str("0x2791Bca1f2de4661ED88A30C99A7a9449Aa84174").unwrap();
+        let owner = Address::from_str("0x1111111111111111111111111111111111111111").unwrap();
+        let payee = Address::from_str("0x000000000000000000000000000000000000bEEF").unwrap();
+        let call = |signature: &str, args: &[Token]| {
+            let mut data = id(signature).to_vec();
+            data.extend(ethers::abi::encode(args));
+            UnsignedTx::Legacy {
+                nonce: 0u64.into(),
+                gas_price: 1u64.into(),
+                gas_limit: 60_000u64.into(),
+                to: Some(token),
+                value: U256::zero(),
+                data,
+                chain_id: 1u64.into(),
+            }
+        };
+        let amount = Token::Uint(U256::MAX);
+
+        let from_tx = call("transferFrom(address,address,uint256)", &[Token::Address(owner), Token::Address(payee), amount.clone()]);
+        assert_eq!(confirmation_token(&from_tx), ("beef".to_string(), "token recipient"));
+        let approve = call("approve(address,uint256)", &[Token::Address(payee), amount]);
+        assert_eq!(confirmation_token(&approve), ("beef".to_string(), "spender"));
+        // Truncated or unknown calldata falls back to the contract address.
+        let truncated = call("transfer(address,uint256)", &[]);
+        assert_eq!(confirmation_token(&truncated), ("4174".to_string(), "recipient"));
+    }
+
+    #[test]
+    fn batch_digest_covers_every_recipient_and_amount() {
+        let tx = |to: u8, value: u64, payee: u8, amount: u64| UnsignedTx::Legacy {
+            nonce: 0u64.into(),
+            gas_price: 1u64.into(),
+            gas_limit: 60_000u64.into(),
+            to: Some(Address::repeat_byte(to)),
+            value: value.into(),
+            data: [&id("transfer(address,uint256)")[..], &ethers::abi::encode(&[Token::Address(Address::repeat_byte(payee)), Token::Uint(amount.into())])].concat(),
+            chain_id: 1u64.into(),
+        };
+        let batch = [tx(0x11, 0, 0xaa, 5), tx(0x22, 1, 0xbb, 0)];
+        let digest = batch_digest(&batch);
+        assert_eq!(batch_digest(&[tx(0x11, 0, 0xaa, 5), tx(0x22, 1, 0xbb, 0)]), digest);
+        assert_ne!(batch_digest(&[tx(0x11, 0, 0xab, 5), tx(0x22, 1, 0xbb, 0)]), digest, "token recipient");
+        assert_ne!(batch_digest(&[tx(0x11, 0, 0xaa, 6), tx(0x22, 1, 0xbb, 0)]), digest, "token amount");
+        assert_ne!(batch_digest(&[tx(0x11, 0, 0xaa, 5), tx(0x22, 2, 0xbb, 0)]), digest, "native value");
+        assert_ne!(batch_digest(&batch[..1]), digest, "a dropped transaction");
+    }
+
+    #[test]
+    fn flags_unknown_selectors_and_creation() {
+        let reviewer = Reviewer::new(None, &[]).unwrap();
+        let tx = UnsignedTx::Legacy {
+            nonce: 0u64.into(),
+            gas_price: 1u64.into(),
+            gas_limit: 1_000_000u64.into(),
+            to: None,
+            value: U256::zero(),
+            data: vec![0xde, 0xad, 0xbe, 0xef, 0x01],
+            chain_id: 999_999u64.into(),
+        };
+        let screen = reviewer.render(&tx, Address::zero());
+        assert!(screen.contains("CONTRACT CREATION"));
+        assert!(screen.contains("UNKNOWN CHAIN"));
+        assert!(screen.contains("UNKNOWN function selector 0xdeadbeef"));
+        assert_eq!(confirmation_token(&tx).0, "create");
+    }
+
+    #[test]
+    fn yes_needs_a_policy_that_allows_unattended_signing() {
+        assert!(skip_confirmation(None).is_err());
+        let attended = Policy::from_toml("allowed_chain_ids = [1]").unwrap();
+        assert!(skip_confirmation(Some(&attended)).is_err());
+        let unattended = Policy::from_toml("allow_unattended = true").unwrap();
+        assert!(skip_confirmation(Some(&unattended)).is_ok());
+    }
+}
@@ -0,0 +1,392 @@
+//! `tx_signer siwe`: Sign-In with Ethereum (EIP-4361).
+//!
+//! The message is parsed field by field, checked against the domain, chain ID and optionally
+//! URI and nonce supplied by the operator, and signed with `personal_sign` only if every
+//! field is well-formed, addressed to the signing key and inside its validity window.
+
+use crate::key_source::KeyArgs;
+use crate::message::sign_message;
+use crate::review::{chain_name, confirm_on_tty};
+use chrono::{DateTime, Duration, FixedOffset, Utc};
+use ethers::types::Address;
+use ethers::utils::to_checksum;
+use eyre::{eyre, Result};
+use std::{fs, path::PathBuf, str::FromStr};
+use tx_envelope::message::SignedMessage;
+
+const HEADER_SUFFIX: &str = " wants you to sign in with your Ethereum account:";
+
+/// Allowed clock skew for `Issued At` lying in the future.
+const MAX_CLOCK_SKEW_MINUTES: i64 = 5;
+
+#[derive(clap::Args, Debug)]
+pub struct SiweArgs {
+    #[command(flatten)]
+    key: KeyArgs,
+
+    /// EIP-4361 message exactly as presented by the site
+    #[arg(long)]
+    input: PathBuf,
+
+    /// Domain the message must be for (e.g. admin.example.com)
+    #[arg(long)]
+    domain: String,
+
+    /// Chain ID the message must be for
+    #[arg(long)]
+    chain_id: u64,
+
+    /// URI the message must carry
+    #[arg(long)]
+    uri: Option<String>,
+
+    /// Nonce the message must carry (as shown by the site)
+    #[arg(long)]
+    nonce: Option<String>,
+
+    /// Write the 65-byte signature (hex) to this file
+    #[arg(long)]
+    output: Option<PathBuf>,
+
+    /// Write the JSON form (address, message, signature) to this file
+    #[arg(long)]
+    json: Option<PathBuf>,
+}
+
+/// Parsed EIP-4361 message.
+#[derive(Debug)]
+pub struct SiweMessage {
+    pub scheme: Option<String>,
+    pub domain: String,
+    pub address: String,
+    pub statement: Option<String>,
+    pub uri: String,
+    pub version: String,
+    pub chain_id: u64,
+    pub nonce: String,
+    pub issued_at: DateTime<FixedOffset>,
+    pub expiration_time: Option<DateTime<FixedOffset>>,
+    pub not_before: Option<DateTime<FixedOffset>>,
+    pub request_id: Option<String>,
+    pub resources: Vec<String>,
+}
+
+/// Operator supplied values the message must match.
+pub struct Expectations<'a> {
+    pub domain: &'a str,
+    pub chain_id: u64,
+    pub uri: Option<&'a str>,
+    pub nonce: Option<&'a str>,
+    pub signer: Address,
+}
+
+fn timestamp(field: &str, value: &str) -> Result<DateTime<FixedOffset>> {
+    DateTime::parse_from_rfc3339(value).map_err(|e| eyre!("{} {:?} is not an RFC 3339 timestamp: {}", field, value, e))
+}
+
+/// The message as signed: file contents without the trailing newline editors add. EIP-4361
+/// messages end at the last field, and the site verifies exactly those bytes.
+fn canonical(raw: &str) -> &str {
+    raw.strip_suffix('\n').unwrap_or(raw)
+}
+
+impl FromStr for SiweMessage {
+    type Err = eyre::Report;
+
+    fn from_str(text: &str) -> Result<Self> {
+        let lines: Vec<&str> = canonical(text).split('\n').collect();
+        let mut idx = 0;
+        let mut next = |what: &str| -> Result<&str> {
+            let line = lines.get(idx).copied().ok_or_else(|| eyre!("Message ends before {}", what))?;
+            idx += 1;
+            Ok(line)
+        };
+
+        let origin = next("the header")?
+            .strip_suffix(HEADER_SUFFIX)
+            .ok_or_else(|| eyre!("First line must end with {:?}", HEADER_SUFFIX.trim()))?;
+        let (scheme, domain) = match origin.split_once("://") {
+            Some((scheme, domain)) => (Some(scheme.to_string()), domain.to_string()),
+            None => (None, origin.to_string()),
+        };
+        let address = next("the address")?.to_string();
+        if !next("the blank line after the address")?.is_empty() {
+            return Err(eyre!("Expected a blank line after the address"));
+        }
+
+        // Statement is optional: `LF [statement LF] LF` before the URI.
+        let mut statement = None;
+        let mut line = next("URI")?;
+        if !line.is_empty() && !line.starts_with("URI: ") {
+            statement = Some(line.to_string());
+            if !next("the blank line after the statement")?.is_empty() {
+                return Err(eyre!("Expected a blank line after the statement"));
+            }
+            line = next("URI")?;
+        } else if line.is_empty() {
+            line = next("URI")?;
+        }
+
+        let mut rest = std::iter::once(line).chain(lines[idx..].iter().copied()).peekable();
+        let mut required = |tag: &str| -> Result<String> {
+            rest.next()
+                .and_then(|l| l.strip_prefix(tag))
+                .map(str::to_string)
+                .ok_or_else(|| eyre!("Missing or misplaced {:?} field", tag.trim_end_matches(": ")))
+        };
+        let uri = required("URI: ")?;
+        let version = required("Version: ")?;
+        let chain_id = required("Chain ID: ")?;
+        let nonce = required("Nonce: ")?;
+        let issued_at = required("Issued At: ")?;
+
+        let mut optional = |tag: &str| -> Option<String> {
+            let value = rest.peek()?.strip_prefix(tag)?.to_string();
+            rest.next();
+            Some(value)
+        };
+        let expiration_time = optional("Expiration Time: ");
+        let not_before = optional("Not Before: ");
+        let request_id = optional("Request ID: ");
+        let mut resources = Vec::new();
+        if optional("Resources:").is_some() {
+            while let Some(resource) = rest.peek().and_then(|l| l.strip_prefix("- ")) {
+                resources.push(resource.to_string());
+                rest.next();
+            }
+        }
+        if let Some(extra) = rest.next() {
+            return Err(eyre!("Unexpected line in message: {:?}", extra));
+        }
+
+        Ok(SiweMessage {
+            scheme,
+            domain,
+            address,
+            statement,
+            uri,
+            version,
+            chain_id: chain_id.parse().map_err(|_| eyre!("Chain ID {:?} is not a number", chain_id))?,
+            nonce,
+            issued_at: timestamp("Issued At", &issued_at)?,
+            expiration_time: expiration_time.map(|t| timestamp("Expiration Time", &t)).transpose()?,
+            not_before: not_before.map(|t| timestamp("Not Before", &t)).transpose()?,
+            request_id,
+            resources,
+        })
+    }
+}
+
+impl SiweMessage {
+    /// Every problem that prevents signing (empty when the message may be signed).
+    pub fn validate(&self, expect: &Expectations, now: DateTime<Utc>) -> Vec<String> {
+        let mut problems = Vec::new();
+        if !self.domain.eq_ignore_ascii_case(expect.domain) {
+            problems.push(format!("domain {:?} is not the expected {:?}", self.domain, expect.domain));
+        }
+        match Address::from_str(&self.address) {
+            Ok(address) => {
+                if to_checksum(&address, None) != self.address {
+                    problems.push(format!("address {} is not EIP-55 checksummed", self.address));
+                }
+                if address != expect.signer {
+                    problems.push(format!("address {} is not the signing key {:?}", self.address, expect.signer));
+                }
+            }
+            Err(_) => problems.push(format!("address {:?} is not an Ethereum address", self.address)),
+        }
+        if let Some(uri) = expect.uri
+            && self.uri != uri
+        {
+            problems.push(format!("URI {:?} is not the expected {:?}", self.uri, uri));
+        }
+        if self.version != "1" {
+            problems.push(format!("version {:?} is not supported (expected \"1\")", self.version));
+        }
+        if self.chain_id != expect.chain_id {
+            problems.push(format!("chain ID {} is not the expected {}", self.chain_id, expect.chain_id));
+        }
+        if self.nonce.len() < 8 || !self.nonce.chars().all(|c| c.is_ascii_alphanumeric()) {
+            problems.push(format!("nonce {:?} must be at least 8 alphanumeric characters", self.nonce));
+        }
+        if let Some(nonce) = expect.nonce
+            && self.nonce != nonce
+        {
+            problems.push(format!("nonce {:?} is not the expected {:?}", self.nonce, nonce));
+        }
+        if self.issued_at > now + Duration::minutes(MAX_CLOCK_SKEW_MINUTES) {
+            problems.push(format!("issued at {} is in the future (local clock {})", self.issued_at, now));
+        }
+        if let Some(expiration) = self.expiration_time
+            && expiration <= now
+        {
+            problems.push(format!("message expired at {} (local clock {})", expiration, now));
+        }
+        if let Some(not_before) = self.not_before
+            && not_before > now
+        {
+            problems.push(format!("message is not valid before {} (local clock {})", not_before, now));
+        }
+        problems
+    }
+
+    /// The parsed fields as shown before confirmation.
+    pub fn render(&self) -> String {
+        let mut out = vec![
+            "============= REVIEW SIGN-IN WITH ETHEREUM (EIP-4361) =============".to_string(),
+            format!("Domain:          {}", self.domain),
+        ];
+        if let Some(scheme) = &self.scheme {
+            out.push(format!("Scheme:          {scheme}"));
+        }
+        out.push(format!("Address:         {}", self.address));
+        out.push(format!("Statement:       {}", self.statement.as_deref().unwrap_or("(none)")));
+        out.push(format!("URI:             {}", self.uri));
+        out.push(format!("Version:         {}", self.version));
+        out.push(format!("Chain:           {} ({})", self.chain_id, chain_name(self.chain_id.into()).0));
+        out.push(format!("Nonce:           {}", self.nonce));
+        out.push(format!("Issued at:       {}", self.issued_at));
+        out.push(format!(
+            "Expiration time: {}",
+            self.expiration_time.map(|t| t.to_string()).unwrap_or_else(|| "(never)".to_string())
+        ));
+        if let Some(not_before) = self.not_before {
+            out.push(format!("Not before:      {not_before}"));
+        }
+        if let Some(request_id) = &self.request_id {
+            out.push(format!("Request ID:      {request_id}"));
+        }
+        for resource in &self.resources {
+            out.push(format!("Resource:        {resource}"));
+        }
+        out.push("===================================================================".to_string());
+        out.join("\n")
+    }
+}
+
+pub fn run(mut args: SiweArgs) -> Result<()> {
+    let raw = fs::read_to_string(&args.input)?;
+    let text = canonical(&raw);
+    let message: SiweMessage = text.parse()?;
+    let wallet = args.key.load()?;
+    let address = wallet.address();
+    println!("{}", message.render());
+
+    let expect = Expectations {
+        domain: &args.domain,
+        chain_id: args.chain_id,
+        uri: args.uri.as_deref(),
+        nonce: args.nonce.as_deref(),
+        signer: address,
+    };
+    let problems = message.validate(&expect, Utc::now());
+    if !problems.is_empty() {
+        eprintln!("SIWE check failed:");
+        for p in &problems {
+            eprintln!("  - {p}");
+        }
+        return Err(eyre!("Refusing to sign: {} SIWE check(s) failed", problems.len()));
+    }
+    if args.uri.is_none() || args.nonce.is_none() {
+        println!("Note: URI and/or nonce were not checked; pass --uri and --nonce to pin them");
+    }
+    confirm_on_tty(&format!("Type \"sign\" to sign in to {}: ", message.domain), "sign")?;
+
+    let signature = sign_message(&wallet, text.as_bytes())?;
+    let signed = SignedMessage::new(text.as_bytes(), address, &signature);
+    println!("Signature: {}", signed.sig);
+    if let Some(path) = &args.output {
+        fs::write(path, &signed.sig)?;
+        println!("Signature (hex) written to: {}", path.display());
+    }
+    if let Some(path) = &args.json {
+        fs::write(path, serde_json::to_string_pretty(&signed)?)?;
+        println!("Signed message (JSON) written to: {}", path.display());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SIGNER: &str = "0xBAe8513769f2174fE262dcd8182Ec184786D2d7A";
+
+    fn message(statement: bool, expiration: &str) -> String {
+        format!(
+            "https://admin.example.com wants you to sign in with your Ethereum account:\n\
+             {SIGNER}\n\n{}\
+             URI: https://admin.example.com/login\n\
+             Version: 1\n\
+             Chain ID: 1\n\
+             Nonce: 32891756abcd\n\
+             Issued At: 2026-10-18T09:00:00Z\n\
+             Expiration Time: {expiration}\n\
+             Resources:\n\
+             - ipfs://bafybeiemxf5abjwjbikoz4mc3a3dla6ual3jsgpdr4cjr3oz3evfyavhwq/\n\
+             - https://admin.example.com/terms",
+            if statement { "Operator access for cold wallet.\n\n" } else { "\n" }
+        )
+    }
+
+    fn expect(domain: &str) -> Expectations<'_> {
+        Expectations {
+            domain,
+            chain_id: 1,
+            uri: Some("https://admin.example.com/login"),
+            nonce: Some("32891756abcd"),
+            signer: Address::from_str(SIGNER).unwrap(),
+        }
+    }
+
+    fn now() -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339("2026-10-18T09:05:00Z").unwrap().with_timezone(&Utc)
+    }
+
+    #[test]
+    fn parses_and_accepts_valid_message() {
+        for statement in [true, false] {
+            let m: SiweMessage = message(statement, "2026-10-18T10:00:00Z").parse().unwrap();
+            assert_eq!(m.scheme.as_deref(), Some("https"));
+            assert_eq!(m.domain, "admin.example.com");
+            assert_eq!(m.statement.is_some(), statement);
+            assert_eq!(m.chain_id, 1);
+            assert_eq!(m.resources.len(), 2);
+            assert!(m.validate(&expect("admin.example.com"), now()).is_empty());
+        }
+    }
+
+    #[test]
+    fn signs_the_canonical_message_without_trailing_newline() {
+        use crate::key_source::LoadedKey;
+        use ethers::signers::{LocalWallet, Signer};
+        use ethers::utils::hash_message;
+
+        let wallet = LocalWallet::from_str("4c0883a69102937d6231471b5ecb4765d5e97f8e4dc6e8fa6a4de3b8a3a2f55b").unwrap();
+        let message = message(true, "2026-10-18T10:00:00Z");
+        let file = format!("{message}\n");
+        assert!(file.parse::<SiweMessage>().is_ok());
+        assert_eq!(canonical(&file), message);
+
+        let signature = sign_message(&LoadedKey::Local(wallet.clone()), canonical(&file).as_bytes()).unwrap();
+        assert_eq!(signature.recover(hash_message(&message)).unwrap(), wallet.address());
+    }
+
+    #[test]
+    fn rejects_wrong_domain_and_expired() {
+        let m: SiweMessage = message(true, "2026-10-18T09:01:00Z").parse().unwrap();
+        let problems = m.validate(&expect("admin.example.org"), now());
+        assert_eq!(problems.len(), 2, "{problems:?}");
+        assert!(problems[0].starts_with("domain"));
+        assert!(problems[1].starts_with("message expired"));
+    }
+
+    #[test]
+    fn rejects_malformed_messages() {
+        let valid = message(true, "2026-10-18T10:00:00Z");
+        assert!(valid.replace("Version: 1\n", "").parse::<SiweMessage>().is_err());
+        assert!(valid.replace("2026-10-18T09:00:00Z", "yesterday").parse::<SiweMessage>().is_err());
+        assert!(format!("{valid}\nEvil: field").parse::<SiweMessage>().is_err());
+        assert!(valid.replace(" wants you", " would like you").parse::<SiweMessage>().is_err());
+    }
+}
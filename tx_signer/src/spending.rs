@@ -0,0 +1,723 @@
+//! Persistent spending limits with rolling daily/weekly windows.
+//!
+//! Configured from the `[spending]` section of the policy file:
+//!
+//! ```toml
+//! [spending]
+//! state_file = "spending.jsonl"
+//! override_signers = ["0x..."]   # addresses whose detached signature lifts a limit
+//!
+//! [[spending.limit]]
+//! chain_id = 1
+//! asset = "native"
+//! daily = "5"
+//! weekly = "20"
+//!
+//! [[spending.limit]]
+//! chain_id = 1
+//! asset = "0xA0b86991c6218b36c1d19D4a2e9Eb0cE3606eB48"   # ERC-20 contract
+//! decimals = 6
+//! daily = "10000"
+//! ```
+//!
+//! The state file is append-only JSON lines. Each entry carries the hash of the previous
+//! one and an EIP-191 signature by the key that signed the transaction, so edited,
+//! reordered or forged entries fail verification on the next run. A signed anchor of the
+//! head (entry count and hash) is kept in `<state_file>.head`, so deleting the last entries
+//! is detected too. A missing state file, or a ledger whose anchor is still the unsigned
+//! one written at initialization, is refused unless `--init-spending-state` is given, so
+//! emptying the file and its anchor does not silently reset the budget. Restoring an older
+//! copy of both files is not detectable locally; keep a copy of the anchor elsewhere to
+//! catch that.
+//!
+//! Every signature that moves a limited asset is checked and recorded, including re-signs
+//! and fee bumps. Entries carry the nonce, and a transaction only counts what exceeds the
+//! amounts already recorded for the same chain, sender and nonce: only one of them can be
+//! mined. That credit comes from this signed ledger alone, never from the nonce ledger.
+//!
+//! Token budgets count `transfer`, `transferFrom`, `approve` and `increaseAllowance`
+//! amounts: an allowance lets the spender move that much, so granting it is a spend.
+
+use crate::erc20;
+use crate::key_source::LoadedKey;
+use crate::UnsignedTx;
+use ethers::types::{Address, Signature, H256, U256};
+use ethers::utils::{format_units, hash_message, keccak256, parse_units};
+use eyre::{eyre, Result};
+use serde::{Deserialize, Serialize};
+use std::{
+    fs::{self, OpenOptions},
+    io::Write,
+    path::{Path, PathBuf},
+    str::FromStr,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+const DAY_SECS: u64 = 24 * 60 * 60;
+const WEEK_SECS: u64 = 7 * DAY_SECS;
+const NATIVE: &str = "native";
+
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct SpendingConfig {
+    state_file: PathBuf,
+    #[serde(default)]
+    override_signers: Vec<Address>,
+    #[serde(default, rename = "limit")]
+    limits: Vec<LimitConfig>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+struct LimitConfig {
+    chain_id: u64,
+    asset: String,
+    #[serde(default = "default_decimals")]
+    decimals: u32,
+    daily: Option<String>,
+    weekly: Option<String>,
+}
+
+fn default_decimals() -> u32 {
+    18
+}
+
+/// Compiled limits with amounts in base units.
+#[derive(Debug)]
+pub struct SpendingLimits {
+    pub state_file: PathBuf,
+    override_signers: Vec<Address>,
+    limits: Vec<Limit>,
+}
+
+#[derive(Debug)]
+struct Limit {
+    chain_id: u64,
+    asset: Asset,
+    decimals: u32,
+    daily: Option<U256>,
+    weekly: Option<U256>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Asset {
+    Native,
+    Token(Address),
+}
+
+impl Asset {
+    fn parse(s: &str) -> Result<Self> {
+        if s.eq_ignore_ascii_case(NATIVE) {
+            Ok(Asset::Native)
+        } else {
+            Ok(Asset::Token(
+                Address::from_str(s).map_err(|e| eyre!("Invalid asset {:?}: {}", s, e))?,
+            ))
+        }
+    }
+
+    fn key(&self) -> String {
+        match self {
+            Asset::Native => NATIVE.to_string(),
+            Asset::Token(a) => format!("{:?}", a),
+        }
+    }
+}
+
+impl SpendingConfig {
+    pub fn compile(self) -> Result<SpendingLimits> {
+        let limits = self
+            .limits
+            .into_iter()
+            .map(|l| {
+                let amount = |v: Option<String>| -> Result<Option<U256>> {
+                    v.map(|v| {
+                        parse_units(&v, l.decimals)
+                            .map(Into::into)
+                            .map_err(|e| eyre!("Invalid spending limit {:?}: {}", v, e))
+                    })
+                    .transpose()
+                };
+                Ok(Limit {
+                    chain_id: l.chain_id,
+                    asset: Asset::parse(&l.asset)?,
+                    decimals: l.decimals,
+                    daily: amount(l.daily)?,
+                    weekly: amount(l.weekly)?,
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+        Ok(SpendingLimits {
+            state_file: self.state_file,
+            override_signers: self.override_signers,
+            limits,
+        })
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct Spend {
+    asset: String,
+    amount: U256,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct EntryBody {
+    seq: u64,
+    prev: H256,
+    timestamp: u64,
+    chain_id: u64,
+    from: Address,
+    signing_hash: H256,
+    /// Absent in entries written before nonces were recorded.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    nonce: Option<U256>,
+    spent: Vec<Spend>,
+    override_by: Option<Address>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct Entry {
+    #[serde(flatten)]
+    body: EntryBody,
+    signature: Signature,
+}
+
+impl EntryBody {
+    fn hash(&self) -> H256 {
+        let json = serde_json::to_vec(self).expect("entry serializes");
+        H256::from(keccak256(json))
+    }
+}
+
+/// Signed count and hash of the ledger head, stored next to the state file.
+#[derive(Serialize, Deserialize, Debug)]
+struct Anchor {
+    count: u64,
+    head: H256,
+    /// By the key that appended entry `count`; absent for a freshly initialized ledger.
+    signature: Option<Signature>,
+}
+
+impl Anchor {
+    fn message(count: u64, head: H256) -> String {
+        format!("tx_signer spending head {} {:?}", count, head)
+    }
+
+    fn write(&self, state_file: &Path) -> Result<()> {
+        let path = anchor_path(state_file);
+        let tmp = path.with_extension("head.tmp");
+        fs::write(&tmp, serde_json::to_string(self)?)?;
+        fs::rename(&tmp, &path)?;
+        Ok(())
+    }
+}
+
+fn anchor_path(state_file: &Path) -> PathBuf {
+    let mut path = state_file.as_os_str().to_owned();
+    path.push(".head");
+    PathBuf::from(path)
+}
+
+/// Verified contents of the state file.
+pub struct Ledger {
+    path: PathBuf,
+    entries: Vec<EntryBody>,
+    /// Spends reserved by this run and not recorded yet; they count against the budgets.
+    pending: Vec<EntryBody>,
+}
+
+impl Ledger {
+    /// Load and verify the state file. Creates an empty one, or accepts an existing empty one
+    /// that has no signed head yet, only when `init` is set.
+    pub fn open(path: &PathBuf, init: bool) -> Result<Self> {
+        if !path.exists() {
+            if !init {
+                return Err(eyre!(
+                    "Spending state file {} does not exist. Pass --init-spending-state to create it",
+                    path.display()
+                ));
+            }
+            fs::write(path, "")?;
+            Anchor { count: 0, head: H256::zero(), signature: None }.write(path)?;
+            println!("Initialized spending state file: {}", path.display());
+        }
+
+        let text = fs::read_to_string(path)?;
+        let mut entries = Vec::new();
+        let mut prev = H256::zero();
+        for (i, line) in text.lines().enumerate().filter(|(_, l)| !l.trim().is_empty()) {
+            let fail = |why: &str| eyre!("Spending state {} failed verification at line {}: {}", path.display(), i + 1, why);
+            let entry: Entry = serde_json::from_str(line).map_err(|e| fail(&e.to_string()))?;
+            if entry.body.seq != entries.len() as u64 {
+                return Err(fail("sequence number out of order"));
+            }
+            if entry.body.prev != prev {
+                return Err(fail("hash chain broken"));
+            }
+            let hash = entry.body.hash();
+            match entry.signature.recover(hash_message(hash)) {
+                Ok(signer) if signer == entry.body.from => {}
+                _ => return Err(fail("entry signature does not match its sender")),
+            }
+            prev = hash;
+            entries.push(entry.body);
+        }
+        verify_anchor(path, &entries, init)?;
+        Ok(Ledger { path: path.clone(), entries, pending: Vec::new() })
+    }
+
+    fn head(&self) -> H256 {
+        self.entries.last().map(EntryBody::hash).unwrap_or_default()
+    }
+
+    fn spent_since(&self, chain_id: u64, from: Address, asset: &str, since: u64) -> U256 {
+        self.entries
+            .iter()
+            .chain(&self.pending)
+            .filter(|e| e.chain_id == chain_id && e.from == from && e.timestamp > since)
+            .flat_map(|e| e.spent.iter())
+            .filter(|s| s.asset == asset)
+            .fold(U256::zero(), |acc, s| acc.saturating_add(s.amount))
+    }
+
+    /// Amount of `asset` already recorded (or reserved) for this chain, sender and nonce.
+    fn spent_for_nonce(&self, chain_id: u64, from: Address, nonce: U256, asset: &str) -> U256 {
+        self.entries
+            .iter()
+            .chain(&self.pending)
+            .filter(|e| e.chain_id == chain_id && e.from == from && e.nonce == Some(nonce))
+            .flat_map(|e| e.spent.iter())
+            .filter(|s| s.asset == asset)
+            .fold(U256::zero(), |acc, s| acc.saturating_add(s.amount))
+    }
+
+    fn append(&mut self, body: EntryBody, wallet: &LoadedKey) -> Result<()> {
+        let signature = wallet.sign_hash(hash_message(body.hash()))?;
+        let line = serde_json::to_string(&Entry { body: body.clone(), signature })?;
+        let mut f = OpenOptions::new().append(true).open(&self.path)?;
+        writeln!(f, "{line}")?;
+        f.sync_all()?;
+        let (count, head) = (body.seq + 1, body.hash());
+        self.entries.push(body);
+        let signature = wallet.sign_hash(hash_message(Anchor::message(count, head)))?;
+        Anchor { count, head, signature: Some(signature) }.write(&self.path)
+    }
+}
+
+/// The anchored head must be one of the verified entries. One entry past it (a crash between
+/// appending an entry and moving the anchor) is accepted, since it only adds spends. The
+/// unsigned anchor of a fresh ledger is only accepted for an empty file during `init`.
+fn verify_anchor(path: &Path, entries: &[EntryBody], init: bool) -> Result<()> {
+    let anchor_file = anchor_path(path);
+    let fail = |why: String| eyre!("Spending state {} failed verification: {}", path.display(), why);
+    let text = fs::read_to_string(&anchor_file).map_err(|e| fail(format!("cannot read head anchor {}: {}", anchor_file.display(), e)))?;
+    let anchor: Anchor = serde_json::from_str(&text).map_err(|e| fail(format!("head anchor {}: {}", anchor_file.display(), e)))?;
+    let count = entries.len() as u64;
+    if anchor.count > count {
+        return Err(fail(format!("{} entries, but the signed head counts {} (truncated?)", count, anchor.count)));
+    }
+    if count > anchor.count + 1 {
+        return Err(fail(format!("{} entries, but the signed head counts {} (stale head anchor?)", count, anchor.count)));
+    }
+    let Some(last) = anchor.count.checked_sub(1).map(|i| &entries[i as usize]) else {
+        if count > 0 {
+            return Err(fail(format!("{} entries, but the head anchor is the unsigned one of an empty ledger", count)));
+        }
+        if !init {
+            return Err(fail("no spends were ever anchored; pass --init-spending-state to start from an empty ledger".to_string()));
+        }
+        return Ok(());
+    };
+    if last.hash() != anchor.head {
+        return Err(fail(format!("entry {} does not match the signed head", anchor.count)));
+    }
+    match anchor.signature.map(|sig| sig.recover(hash_message(Anchor::message(anchor.count, anchor.head)))) {
+        Some(Ok(signer)) if signer == last.from => Ok(()),
+        _ => Err(fail("head anchor signature does not match the last entry's sender".to_string())),
+    }
+}
+
+/// Message an override signer signs (EIP-191 personal_sign) to lift the limits for one tx.
+pub fn override_message(signing_hash: H256) -> String {
+    format!("tx_signer spending override {:?}", signing_hash)
+}
+
+fn now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0)
+}
+
+impl SpendingLimits {
+    fn spends(&self, tx: &UnsignedTx) -> Result<Vec<(&Limit, U256)>> {
+        let chain_id = tx.chain_id_u64()?;
+        Ok(self
+            .limits
+            .iter()
+            .filter(|l| l.chain_id == chain_id)
+            .filter_map(|l| {
+                let amount = match l.asset {
+                    Asset::Native => Some(tx.value()),
+                    Asset::Token(token) if tx.to() == Some(token) => erc20::amount(tx.data()),
+                    Asset::Token(_) => None,
+                }?;
+                (!amount.is_zero()).then_some((l, amount))
+            })
+            .collect())
+    }
+
+    /// What `tx` adds to each budget: its spends minus what the ledger already holds for the
+    /// same nonce. A re-sign or fee bump of a recorded transaction adds nothing.
+    fn increments(&self, ledger: &Ledger, tx: &UnsignedTx, from: Address) -> Result<Vec<(&Limit, U256)>> {
+        let chain_id = tx.chain_id_u64()?;
+        Ok(self
+            .spends(tx)?
+            .into_iter()
+            .map(|(l, amount)| (l, amount.saturating_sub(ledger.spent_for_nonce(chain_id, from, tx.nonce(), &l.asset.key()))))
+            .collect())
+    }
+
+    /// Check the transaction against the rolling windows. Exceeding a budget is an error
+    /// unless `override_sig` is a valid override by one of `override_signers` other than
+    /// the signing key itself. Returns the overriding address, if one was used.
+    pub fn check(
+        &self,
+        ledger: &Ledger,
+        tx: &UnsignedTx,
+        from: Address,
+        signing_hash: H256,
+        override_sig: Option<&Signature>,
+    ) -> Result<Option<Address>> {
+        let chain_id = tx.chain_id_u64()?;
+        let now = now();
+        let mut exceeded = Vec::new();
+        for (limit, amount) in self.increments(ledger, tx, from)? {
+            let asset = limit.asset.key();
+            for (window, secs, cap) in [("daily", DAY_SECS, limit.daily), ("weekly", WEEK_SECS, limit.weekly)] {
+                let Some(cap) = cap else { continue };
+                let spent = ledger.spent_since(chain_id, from, &asset, now.saturating_sub(secs));
+                if spent.saturating_add(amount) > cap {
+                    let fmt = |v: U256| format_units(v, limit.decimals).unwrap_or_else(|_| v.to_string());
+                    exceeded.push(format!(
+                        "{window} {asset} budget on chain {chain_id}: spent {} + this tx {} > cap {}",
+                        fmt(spent),
+                        fmt(amount),
+                        fmt(cap)
+                    ));
+                }
+            }
+        }
+
+        if exceeded.is_empty() {
+            println!("Spending limits check passed");
+            return Ok(None);
+        }
+
+        eprintln!("Spending limits exceeded for {:?}:", from);
+        for e in &exceeded {
+            eprintln!("  - {e}");
+        }
+
+        let Some(sig) = override_sig else {
+            eprintln!(
+                "To override, an override signer must personal_sign the message:\n  {}\nand pass the signature via --spending-override",
+                override_message(signing_hash)
+            );
+            return Err(eyre!("Refusing to sign: spending limit exceeded"));
+        };
+        let signer = sig.recover(hash_message(override_message(signing_hash)))?;
+        if signer == from || !self.override_signers.contains(&signer) {
+            return Err(eyre!("Override signature from {:?} is not an authorized override signer", signer));
+        }
+        println!("Spending limit overridden by {:?}", signer);
+        Ok(Some(signer))
+    }
+
+    fn entry(&self, ledger: &Ledger, tx: &UnsignedTx, from: Address, signing_hash: H256, override_by: Option<Address>) -> Result<Option<EntryBody>> {
+        let spent: Vec<Spend> = self
+            .increments(ledger, tx, from)?
+            .into_iter()
+            .map(|(l, amount)| Spend { asset: l.asset.key(), amount })
+            .collect();
+        if spent.is_empty() {
+            return Ok(None);
+        }
+        Ok(Some(EntryBody {
+            seq: ledger.entries.len() as u64,
+            prev: ledger.head(),
+            timestamp: now(),
+            chain_id: tx.chain_id_u64()?,
+            from,
+            signing_hash,
+            nonce: Some(tx.nonce()),
+            spent,
+            override_by,
+        }))
+    }
+
+    /// Count a checked transaction against the budgets of later checks in this run, before it
+    /// is signed. [`SpendingLimits::record`] replaces the reservation with a ledger entry.
+    pub fn reserve(&self, ledger: &mut Ledger, tx: &UnsignedTx, from: Address, signing_hash: H256) -> Result<()> {
+        if let Some(body) = self.entry(ledger, tx, from, signing_hash, None)? {
+            ledger.pending.push(body);
+        }
+        Ok(())
+    }
+
+    /// Append a signed ledger entry for a transaction that is about to be released. Entries
+    /// are written for every signature, even when a re-sign adds nothing to the budget.
+    pub fn record(
+        &self,
+        ledger: &mut Ledger,
+        tx: &UnsignedTx,
+        wallet: &LoadedKey,
+        from: Address,
+        signing_hash: H256,
+        override_by: Option<Address>,
+    ) -> Result<()> {
+        ledger.pending.retain(|p| !(p.from == from && p.signing_hash == signing_hash));
+        match self.entry(ledger, tx, from, signing_hash, override_by)? {
+            Some(body) => ledger.append(body, wallet),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ethers::signers::{LocalWallet, Signer};
+    use ethers::utils::id;
+
+    const KEY: &str = "4c0883a69102937d6231471b5ecb4765d5e97f8e4dc6e8fa6a4de3b8a3a2f55b";
+    const OVERRIDE_KEY: &str = "0000000000000000000000000000000000000000000000000000000000000001";
+
+    fn transfer(value_eth: &str) -> UnsignedTx {
+        transfer_at(0, 1, value_eth)
+    }
+
+    fn transfer_at(nonce: u64, gas_price: u64, value_eth: &str) -> UnsignedTx {
+        UnsignedTx::Legacy {
+            nonce: nonce.into(),
+            gas_price: gas_price.into(),
+            gas_limit: 21_000u64.into(),
+            to: Some(Address::repeat_byte(0x11)),
+            value: parse_units(value_eth, "ether").unwrap().into(),
+            data: vec![],
+            chain_id: 1u64.into(),
+        }
+    }
+
+    fn limits(dir: &std::path::Path) -> SpendingLimits {
+        let override_addr = LocalWallet::from_str(OVERRIDE_KEY).unwrap().address();
+        let cfg: SpendingConfig = toml::from_str(&format!(
+            r#"
+            state_file = "{}"
+            override_signers = ["{:?}"]
+            [[limit]]
+            chain_id = 1
+            asset = "native"
+            daily = "1"
+            "#,
+            dir.join("state.jsonl").display(),
+            override_addr
+        ))
+        .unwrap();
+        cfg.compile().unwrap()
+    }
+
+    #[test]
+    fn enforces_daily_budget_and_override() {
+        let dir = std::env::temp_dir().join(format!("tx_signer_spending_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let limits = limits(&dir);
+        let wallet = LocalWallet::from_str(KEY).unwrap();
+        let from = wallet.address();
+
+        assert!(Ledger::open(&limits.state_file, false).is_err(), "missing state must be refused");
+        let mut ledger = Ledger::open(&limits.state_file, true).unwrap();
+
+        let tx = transfer("0.6");
+        let hash = H256::repeat_byte(1);
+        assert_eq!(limits.check(&ledger, &tx, from, hash, None).unwrap(), None);
+        limits.record(&mut ledger, &tx, &LoadedKey::Local(wallet.clone()), from, hash, None).unwrap();
+
+        // Reload: the chain verifies and a second 0.6 exceeds the 1.0 daily cap.
+        let ledger = Ledger::open(&limits.state_file, false).unwrap();
+        let tx = transfer_at(1, 1, "0.6");
+        let hash2 = H256::repeat_byte(2);
+        assert!(limits.check(&ledger, &tx, from, hash2, None).is_err());
+
+        let self_override = wallet.sign_hash(hash_message(override_message(hash2))).unwrap();
+        assert!(limits.check(&ledger, &tx, from, hash2, Some(&self_override)).is_err());
+
+        let approver = LocalWallet::from_str(OVERRIDE_KEY).unwrap();
+        let sig = approver.sign_hash(hash_message(override_message(hash2))).unwrap();
+        assert_eq!(
+            limits.check(&ledger, &tx, from, hash2, Some(&sig)).unwrap(),
+            Some(approver.address())
+        );
+
+        // Tampering with a recorded amount breaks verification.
+        let text = fs::read_to_string(&limits.state_file).unwrap();
+        let tampered = text.replace("\"amount\":\"0x", "\"amount\":\"0x1");
+        fs::write(&limits.state_file, tampered).unwrap();
+        assert!(Ledger::open(&limits.state_file, false).is_err());
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn resigns_are_credited_only_by_signed_entries_for_the_nonce() {
+        let dir = std::env::temp_dir().join(format!("tx_signer_spending_resign_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let limits = limits(&dir);
+        let key = LoadedKey::Local(LocalWallet::from_str(KEY).unwrap());
+        let from = key.address();
+        let mut ledger = Ledger::open(&limits.state_file, true).unwrap();
+
+        // Nothing recorded for nonce 0 yet: a "fee bump" is a full spend, whatever the nonce ledger says.
+        let bump = transfer_at(0, 2, "0.6");
+        limits.check(&ledger, &bump, from, H256::repeat_byte(1), None).unwrap();
+        limits.record(&mut ledger, &bump, &key, from, H256::repeat_byte(1), None).unwrap();
+
+        // Re-signing nonce 0 with a higher fee is recorded but adds nothing; raising the value adds the difference.
+        let rebump = transfer_at(0, 3, "0.6");
+        limits.check(&ledger, &rebump, from, H256::repeat_byte(2), None).unwrap();
+        limits.record(&mut ledger, &rebump, &key, from, H256::repeat_byte(2), None).unwrap();
+        let ledger = Ledger::open(&limits.state_file, false).unwrap();
+        assert_eq!(ledger.entries.len(), 2);
+        assert!(limits.check(&ledger, &transfer_at(0, 4, "0.9"), from, H256::repeat_byte(3), None).is_ok());
+        assert!(limits.check(&ledger, &transfer_at(0, 4, "1.1"), from, H256::repeat_byte(3), None).is_err());
+        assert!(limits.check(&ledger, &transfer_at(1, 1, "0.6"), from, H256::repeat_byte(4), None).is_err());
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn approvals_count_against_token_budgets() {
+        let token = Address::repeat_byte(0x22);
+        let cfg: SpendingConfig = toml::from_str(&format!(
+            "state_file = \"unused\"\n[[limit]]\nchain_id = 1\nasset = \"{:?}\"\ndecimals = 6\ndaily = \"100\"",
+            token
+        ))
+        .unwrap();
+        let limits = cfg.compile().unwrap();
+        let ledger = Ledger { path: PathBuf::new(), entries: vec![], pending: vec![] };
+        let call = |signature: &str, amount: U256| {
+            let mut data = id(signature).to_vec();
+            data.extend_from_slice(&[0u8; 32]);
+            let mut word = [0u8; 32];
+            amount.to_big_endian(&mut word);
+            data.extend_from_slice(&word);
+            UnsignedTx::Legacy {
+                nonce: 0u64.into(),
+                gas_price: 1u64.into(),
+                gas_limit: 60_000u64.into(),
+                to: Some(token),
+                value: U256::zero(),
+                data,
+                chain_id: 1u64.into(),
+            }
+        };
+        let from = Address::repeat_byte(0x33);
+        let hash = H256::repeat_byte(3);
+
+        assert!(limits.check(&ledger, &call("approve(address,uint256)", 50_000_000u64.into()), from, hash, None).is_ok());
+        assert!(limits.check(&ledger, &call("approve(address,uint256)", U256::MAX), from, hash, None).is_err());
+        assert!(limits.check(&ledger, &call("increaseAllowance(address,uint256)", 200_000_000u64.into()), from, hash, None).is_err());
+    }
+
+    #[test]
+    fn chain_id_above_u64_is_an_error() {
+        let dir = std::env::temp_dir().join(format!("tx_signer_spending_chain_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let limits = limits(&dir);
+        let ledger = Ledger::open(&limits.state_file, true).unwrap();
+        // Truncated to 64 bits this would be chain 1, and be checked against its budget.
+        let mut tx = transfer("0.1");
+        if let UnsignedTx::Legacy { chain_id, .. } = &mut tx {
+            *chain_id = (U256::from(1) << 64) + 1;
+        }
+        let err = limits.check(&ledger, &tx, Address::zero(), H256::zero(), None).unwrap_err();
+        assert!(err.to_string().contains("64 bits"), "{err}");
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn truncated_state_is_refused() {
+        let dir = std::env::temp_dir().join(format!("tx_signer_spending_trunc_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let limits = limits(&dir);
+        let key = LoadedKey::Local(LocalWallet::from_str(KEY).unwrap());
+        let from = key.address();
+
+        let mut ledger = Ledger::open(&limits.state_file, true).unwrap();
+        for i in 1..=2 {
+            limits.record(&mut ledger, &transfer("0.3"), &key, from, H256::repeat_byte(i), None).unwrap();
+        }
+        assert_eq!(Ledger::open(&limits.state_file, false).unwrap().entries.len(), 2);
+
+        // Dropping the last entry leaves a valid hash chain, but not the signed head.
+        let text = fs::read_to_string(&limits.state_file).unwrap();
+        let first_line = text.lines().next().unwrap();
+        fs::write(&limits.state_file, format!("{first_line}\n")).unwrap();
+        let err = Ledger::open(&limits.state_file, false).err().unwrap();
+        assert!(err.to_string().contains("truncated"), "{err}");
+
+        fs::write(&limits.state_file, &text).unwrap();
+        fs::remove_file(anchor_path(&limits.state_file)).unwrap();
+        assert!(Ledger::open(&limits.state_file, false).is_err(), "a missing anchor must be refused");
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn rewritten_anchor_is_refused() {
+        let dir = std::env::temp_dir().join(format!("tx_signer_spending_anchor_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let limits = limits(&dir);
+        let key = LoadedKey::Local(LocalWallet::from_str(KEY).unwrap());
+        let from = key.address();
+        let mut ledger = Ledger::open(&limits.state_file, true).unwrap();
+        assert!(Ledger::open(&limits.state_file, false).is_err(), "an unsigned empty ledger needs --init-spending-state");
+        limits.record(&mut ledger, &transfer("0.3"), &key, from, H256::repeat_byte(1), None).unwrap();
+
+        // The anchor of a fresh ledger does not vouch for recorded entries.
+        let empty = serde_json::to_string(&Anchor { count: 0, head: H256::zero(), signature: None }).unwrap();
+        fs::write(anchor_path(&limits.state_file), &empty).unwrap();
+        let err = Ledger::open(&limits.state_file, true).err().unwrap();
+        assert!(err.to_string().contains("unsigned"), "{err}");
+
+        // Emptying the file as well only passes as an explicit re-initialization.
+        fs::write(&limits.state_file, "").unwrap();
+        assert!(Ledger::open(&limits.state_file, false).is_err());
+        assert!(Ledger::open(&limits.state_file, true).is_ok());
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn truncated_state_with_stale_anchor_is_refused() {
+        let dir = std::env::temp_dir().join(format!("tx_signer_spending_stale_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let limits = limits(&dir);
+        let key = LoadedKey::Local(LocalWallet::from_str(KEY).unwrap());
+        let from = key.address();
+        let mut ledger = Ledger::open(&limits.state_file, true).unwrap();
+        limits.record(&mut ledger, &transfer("0.1"), &key, from, H256::repeat_byte(1), None).unwrap();
+        let stale = fs::read_to_string(anchor_path(&limits.state_file)).unwrap();
+        for i in 2..=4 {
+            limits.record(&mut ledger, &transfer("0.1"), &key, from, H256::repeat_byte(i), None).unwrap();
+        }
+
+        // Drop the last entry and put back the anchor saved after the first one.
+        let text = fs::read_to_string(&limits.state_file).unwrap();
+        let kept: Vec<&str> = text.lines().take(3).collect();
+        fs::write(&limits.state_file, kept.join("\n") + "\n").unwrap();
+        fs::write(anchor_path(&limits.state_file), stale).unwrap();
+        let err = Ledger::open(&limits.state_file, false).err().unwrap();
+        assert!(err.to_string().contains("stale"), "{err}");
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,220 @@
+//! Post-sign self-verification.
+//!
+//! The raw transaction produced by the encoder is decoded again here with plain RLP, without
+//! going through ethers' transaction types, and checked field by field against the payload
+//! that was reviewed. The signature must be low-s with a `v` for the payload's chain, and it
+//! must recover to the signing key. The transaction hash recomputed from the raw bytes must
+//! match the one ethers derives for the same fields and signature. Any mismatch aborts before
+//! an output file is written.
+
+use crate::assemble::normalize_s;
+use crate::{decode_to, unsigned_to_typed, UnsignedTx};
+use ethers::types::{Address, Signature, H256, U256};
+use ethers::utils::{keccak256, rlp};
+use eyre::{eyre, Result};
+
+/// Fields decoded from a signed raw transaction.
+struct Decoded {
+    fields: UnsignedTx,
+    /// `v` as encoded: EIP-155 `v` for legacy, `yParity` for EIP-1559.
+    v: u64,
+    recovery_id: u64,
+    r: U256,
+    s: U256,
+}
+
+/// A single RLP list spanning exactly `bytes`, with `items` entries.
+fn list(bytes: &[u8], items: usize) -> Result<rlp::Rlp<'_>> {
+    let r = rlp::Rlp::new(bytes);
+    let info = r.payload_info()?;
+    if !r.is_list() || info.header_len + info.value_len != bytes.len() || r.item_count()? != items {
+        return Err(eyre!("Signed transaction is not a single RLP list of {} items", items));
+    }
+    Ok(r)
+}
+
+fn decode(raw: &[u8]) -> Result<Decoded> {
+    if let Some((&0x02, rest)) = raw.split_first() {
+        let r = list(rest, 12)?;
+        let access_list = r.at(8)?;
+        if !(access_list.is_list() && access_list.item_count()? == 0) {
+            return Err(eyre!("Signed transaction has a non-empty access list"));
+        }
+        let fields = UnsignedTx::Eip1559 {
+            chain_id: r.val_at(0)?,
+            nonce: r.val_at(1)?,
+            max_priority_fee: r.val_at(2)?,
+            max_fee: r.val_at(3)?,
+            gas_limit: r.val_at(4)?,
+            to: decode_to(r.at(5)?)?,
+            value: r.val_at(6)?,
+            data: r.val_at(7)?,
+        };
+        let recovery_id: u64 = r.val_at(9)?;
+        if recovery_id > 1 {
+            return Err(eyre!("EIP-1559 yParity must be 0 or 1, got {}", recovery_id));
+        }
+        Ok(Decoded { fields, v: recovery_id, recovery_id, r: r.val_at(10)?, s: r.val_at(11)? })
+    } else {
+        let r = list(raw, 9)?;
+        let v: u64 = r.val_at(6)?;
+        if v < 35 {
+            return Err(eyre!("Legacy transaction v {} is not EIP-155 replay protected", v));
+        }
+        let fields = UnsignedTx::Legacy {
+            nonce: r.val_at(0)?,
+            gas_price: r.val_at(1)?,
+            gas_limit: r.val_at(2)?,
+            to: decode_to(r.at(3)?)?,
+            value: r.val_at(4)?,
+            data: r.val_at(5)?,
+            chain_id: ((v - 35) / 2).into(),
+        };
+        Ok(Decoded { fields, v, recovery_id: (v - 35) % 2, r: r.val_at(7)?, s: r.val_at(8)? })
+    }
+}
+
+/// The signing preimage hash, rebuilt from the decoded fields.
+fn signing_hash(tx: &UnsignedTx) -> H256 {
+    let mut s = rlp::RlpStream::new();
+    let append_to = |s: &mut rlp::RlpStream, to: &Option<Address>| {
+        match to {
+            Some(to) => s.append(to),
+            None => s.append_empty_data(),
+        };
+    };
+    let mut bytes = Vec::new();
+    match tx {
+        UnsignedTx::Legacy { nonce, gas_price, gas_limit, to, value, data, chain_id } => {
+            s.begin_list(9);
+            s.append(nonce).append(gas_price).append(gas_limit);
+            append_to(&mut s, to);
+            s.append(value).append(data).append(chain_id).append(&0u8).append(&0u8);
+        }
+        UnsignedTx::Eip1559 { chain_id, nonce, max_priority_fee, max_fee, gas_limit, to, value, data } => {
+            bytes.push(0x02);
+            s.begin_list(9);
+            s.append(chain_id).append(nonce).append(max_priority_fee).append(max_fee).append(gas_limit);
+            append_to(&mut s, to);
+            s.append(value).append(data).begin_list(0);
+        }
+    }
+    bytes.extend_from_slice(&s.out());
+    H256::from(keccak256(bytes))
+}
+
+fn mismatches(expected: &UnsignedTx, got: &UnsignedTx) -> Vec<String> {
+    let mut out = Vec::new();
+    let mut field = |name: &str, a: String, b: String| {
+        if a != b {
+            out.push(format!("{name}: expected {a}, encoded {b}"));
+        }
+    };
+    let kind = |tx: &UnsignedTx| matches!(tx, UnsignedTx::Eip1559 { .. });
+    field("type", (kind(expected) as u8 * 2).to_string(), (kind(got) as u8 * 2).to_string());
+    field("chain_id", expected.chain_id().to_string(), got.chain_id().to_string());
+    field("nonce", expected.nonce().to_string(), got.nonce().to_string());
+    field("to", format!("{:?}", expected.to()), format!("{:?}", got.to()));
+    field("value", expected.value().to_string(), got.value().to_string());
+    field("data", hex::encode(expected.data()), hex::encode(got.data()));
+    field("gas_limit", expected.gas_limit().to_string(), got.gas_limit().to_string());
+    field("max_fee_per_gas", expected.max_fee_per_gas().to_string(), got.max_fee_per_gas().to_string());
+    field("max_priority_fee", format!("{:?}", expected.max_priority_fee()), format!("{:?}", got.max_priority_fee()));
+    out
+}
+
+/// Check `raw` against the reviewed payload and the signer; returns the transaction hash.
+pub fn verify_signed(raw: &[u8], utx: &UnsignedTx, signer: Address) -> Result<H256> {
+    let decoded = decode(raw).map_err(|e| eyre!("Self-verification failed, nothing written: {}", e))?;
+    let problems = mismatches(utx, &decoded.fields);
+    if !problems.is_empty() {
+        return Err(eyre!(
+            "Self-verification failed, nothing written: encoded transaction differs from the payload ({})",
+            problems.join("; ")
+        ));
+    }
+    if decoded.r.is_zero() || decoded.s.is_zero() || normalize_s(decoded.s, 0).0 != decoded.s {
+        return Err(eyre!("Self-verification failed, nothing written: signature is not a valid low-s signature"));
+    }
+    let sig = Signature { r: decoded.r, s: decoded.s, v: 27 + decoded.recovery_id };
+    let recovered = sig.recover(signing_hash(&decoded.fields))?;
+    if recovered != signer {
+        return Err(eyre!(
+            "Self-verification failed, nothing written: signature recovers to {:?}, not the signing key {:?}",
+            recovered,
+            signer
+        ));
+    }
+    let tx_hash = H256::from(keccak256(raw));
+    let library_hash = unsigned_to_typed(&decoded.fields).hash(&Signature { v: decoded.v, ..sig });
+    if library_hash != tx_hash {
+        return Err(eyre!(
+            "Self-verification failed, nothing written: transaction hash {:?} differs from the encoder's {:?}",
+            tx_hash,
+            library_hash
+        ));
+    }
+    Ok(tx_hash)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ethers::signers::{LocalWallet, Signer};
+    use ethers::utils::parse_units;
+    use std::str::FromStr;
+
+    fn payloads() -> [UnsignedTx; 2] {
+        [
+            UnsignedTx::Legacy {
+                nonce: 5u64.into(),
+                gas_price: parse_units("20", "gwei").unwrap().into(),
+                gas_limit: 21_000u64.into(),
+                to: Some(Address::repeat_byte(0x11)),
+                value: 10u64.pow(16).into(),
+                data: vec![],
+                chain_id: 1u64.into(),
+            },
+            UnsignedTx::Eip1559 {
+                chain_id: 137u64.into(),
+                nonce: 0u64.into(),
+                max_priority_fee: parse_units("30", "gwei").unwrap().into(),
+                max_fee: parse_units("100", "gwei").unwrap().into(),
+                gas_limit: 500_000u64.into(),
+                to: None,
+                value: 0u64.into(),
+                data: vec![0x60, 0x80, 0x60, 0x40],
+            },
+        ]
+    }
+
+    #[test]
+    fn accepts_own_signatures_and_rejects_tampering() {
+        let wallet = LocalWallet::from_str("4c0883a69102937d6231471b5ecb4765d5e97f8e4dc6e8fa6a4de3b8a3a2f55b").unwrap();
+        let [legacy, eip1559] = payloads();
+        for utx in [&legacy, &eip1559] {
+            let typed = unsigned_to_typed(utx);
+            let sig = wallet.sign_transaction_sync(&typed).unwrap();
+            let raw = typed.rlp_signed(&sig);
+            let hash = verify_signed(&raw, utx, wallet.address()).unwrap();
+            assert_eq!(hash, H256::from(keccak256(&raw)));
+            assert_eq!(hash, typed.hash(&sig));
+            assert_eq!(signing_hash(utx), typed.sighash());
+
+            // Wrong signer, and the high-s twin of a valid signature.
+            assert!(verify_signed(&raw, utx, Address::zero()).is_err());
+            let n = U256::from_str_radix("fffffffffffffffffffffffffffffffebaaedce6af48a03bbfd25e8cd0364141", 16).unwrap();
+            let twin = Signature { s: n - sig.s, v: if sig.v % 2 == 1 { sig.v + 1 } else { sig.v - 1 }, ..sig };
+            assert!(verify_signed(&typed.rlp_signed(&twin), utx, wallet.address()).is_err());
+        }
+
+        // A raw transaction that does not encode the reviewed payload.
+        let typed = unsigned_to_typed(&legacy);
+        let raw = typed.rlp_signed(&wallet.sign_transaction_sync(&typed).unwrap());
+        let UnsignedTx::Legacy { nonce, gas_price, gas_limit, to, ref data, chain_id, .. } = legacy else { unreachable!() };
+        let other = UnsignedTx::Legacy { nonce, gas_price, gas_limit, to, value: 1u64.into(), data: data.clone(), chain_id };
+        let err = verify_signed(&raw, &other, wallet.address()).unwrap_err().to_string();
+        assert!(err.contains("value"), "{err}");
+        assert!(verify_signed(&raw[..raw.len() - 1], &legacy, wallet.address()).is_err());
+    }
+}